@@ -0,0 +1,413 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use rlox::{
+    error::Error,
+    vm::{
+        builder::{Capabilities, Captured, VmBuilder},
+        debug::{Breakpoint, Debugger, Pause, Step},
+        object::Obj,
+        value::Value,
+        RunState, Vm,
+    },
+};
+use serde_json::{json, Value as Json};
+
+use crate::message::{read_message, write_message};
+
+/// The vm runs a single thread
+const THREAD_ID: i64 = 1;
+/// Every frame has the scopes locals, upvalues and globals,
+/// their variable references are `frame * SCOPES + scope + 1`
+const SCOPES: i64 = 3;
+
+/// A debug adapter for one script, speaking the Debug Adapter Protocol
+pub struct Server<W: Write> {
+    writer: W,
+    seq: i64,
+    program: Option<PathBuf>,
+    source: String,
+    args: Vec<String>,
+    stop_on_entry: bool,
+    breakpoints: Vec<Breakpoint>,
+    vm: Option<Vm>,
+    /// What the script prints, sent to the client as output events
+    output: Captured,
+    /// The client sent all breakpoints, the program starts once it's launched too
+    configured: bool,
+    done: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            seq: 0,
+            program: None,
+            source: String::new(),
+            args: Vec::new(),
+            stop_on_entry: false,
+            breakpoints: Vec::new(),
+            vm: None,
+            output: Captured::default(),
+            configured: false,
+            done: false,
+        }
+    }
+
+    /// Answers requests from `reader` until the client disconnects
+    pub fn serve(&mut self, reader: &mut impl BufRead) -> io::Result<()> {
+        while !self.done {
+            let Some(request) = read_message(reader)? else {
+                break;
+            };
+            self.request(&request)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.writer, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn request(&mut self, request: &Json) -> io::Result<()> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+
+        match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                });
+                self.respond(request, Ok(capabilities))?;
+                self.event("initialized", json!({}))
+            }
+            "launch" => {
+                let result = self.launch(arguments);
+                let launched = result.is_ok();
+                self.respond(request, result.map(|_| json!({})))?;
+                if launched && self.configured {
+                    return self.start();
+                }
+                Ok(())
+            }
+            "setBreakpoints" => {
+                let lines: Vec<usize> = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect();
+                self.breakpoints
+                    .retain(|breakpoint| !matches!(breakpoint, Breakpoint::Line(_)));
+                self.breakpoints
+                    .extend(lines.iter().map(|line| Breakpoint::Line(*line)));
+                self.update_breakpoints();
+
+                let breakpoints: Vec<Json> = lines
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.respond(request, Ok(json!({ "breakpoints": breakpoints })))
+            }
+            "setFunctionBreakpoints" => {
+                let names: Vec<String> = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["name"].as_str())
+                    .map(str::to_string)
+                    .collect();
+                self.breakpoints
+                    .retain(|breakpoint| !matches!(breakpoint, Breakpoint::Function(_)));
+                self.breakpoints
+                    .extend(names.iter().cloned().map(Breakpoint::Function));
+                self.update_breakpoints();
+
+                let breakpoints: Vec<Json> =
+                    names.iter().map(|_| json!({ "verified": true })).collect();
+                self.respond(request, Ok(json!({ "breakpoints": breakpoints })))
+            }
+            "configurationDone" => {
+                self.configured = true;
+                self.respond(request, Ok(json!({})))?;
+                if self.program.is_some() {
+                    return self.start();
+                }
+                Ok(())
+            }
+            "threads" => self.respond(
+                request,
+                Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            ),
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                let Some(debugger) = self.vm.as_mut().and_then(Vm::debugger_mut) else {
+                    return self.respond(request, Err("The program isn't running.".to_string()));
+                };
+                match command {
+                    "next" => debugger.step(Step::Over),
+                    "stepIn" => debugger.step(Step::Into),
+                    "stepOut" => debugger.step(Step::Out),
+                    _ => debugger.run(),
+                };
+                let body = if command == "continue" {
+                    json!({ "allThreadsContinued": true })
+                } else {
+                    json!({})
+                };
+                self.respond(request, Ok(body))?;
+
+                let state = match self.vm.as_mut() {
+                    Some(vm) => vm.resume().map_err(|e| vec![e]),
+                    None => Ok(RunState::Finished),
+                };
+                self.stopped(state, "step")
+            }
+            "stackTrace" => {
+                let result = self.stack_trace();
+                self.respond(request, result)
+            }
+            "scopes" => {
+                let frame = arguments["frameId"].as_i64().unwrap_or_default();
+                let scopes: Vec<Json> = ["Locals", "Upvalues", "Globals"]
+                    .iter()
+                    .enumerate()
+                    .map(|(scope, name)| {
+                        json!({
+                            "name": name,
+                            "variablesReference": frame * SCOPES + scope as i64 + 1,
+                            "expensive": false,
+                        })
+                    })
+                    .collect();
+                self.respond(request, Ok(json!({ "scopes": scopes })))
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_i64().unwrap_or_default();
+                let result = self.variables(reference);
+                self.respond(request, result)
+            }
+            "disconnect" => {
+                self.done = true;
+                self.vm = None;
+                self.respond(request, Ok(json!({})))
+            }
+            command => self.respond(request, Err(format!("Unsupported command '{}'.", command))),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<(), String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or_else(|| "launch needs a 'program' to debug.".to_string())?;
+        let program = PathBuf::from(program);
+        self.source = rlox::read_source(&program).map_err(|errors| messages(&errors))?;
+        rlox::check(&self.source).map_err(|errors| messages(&errors))?;
+
+        self.args = arguments["args"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|arg| arg.as_str().map(str::to_string))
+            .collect();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or_default();
+        self.program = Some(program);
+        Ok(())
+    }
+
+    fn update_breakpoints(&mut self) {
+        let breakpoints = self.breakpoints.clone();
+        if let Some(debugger) = self.vm.as_mut().and_then(Vm::debugger_mut) {
+            debugger.clear_breakpoints();
+            for breakpoint in breakpoints {
+                debugger.add_breakpoint(breakpoint);
+            }
+        }
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        let mut debugger = Debugger::new();
+        for breakpoint in self.breakpoints.iter() {
+            debugger.add_breakpoint(breakpoint.clone());
+        }
+        if self.stop_on_entry {
+            debugger.step(Step::Into);
+        }
+
+        // stdin and stdout carry the protocol, the script reads nothing and prints into events
+        let mut vm = VmBuilder::new()
+            .capabilities(Capabilities::all())
+            .args(std::mem::take(&mut self.args))
+            .input(io::empty())
+            .output(self.output.clone())
+            .debugger(debugger)
+            .build();
+        let state = vm.interpret(&self.source);
+        self.vm = Some(vm);
+
+        // the only step before the first pause is the one to stop on entry
+        self.stopped(state, "entry")
+    }
+
+    /// Reports where execution went after starting or resuming the vm,
+    /// `step` is the reason given when a step paused it
+    fn stopped(&mut self, state: Result<RunState, Vec<Error>>, step: &str) -> io::Result<()> {
+        let reason = match self
+            .vm
+            .as_ref()
+            .and_then(Vm::debugger)
+            .and_then(Debugger::pause)
+        {
+            Some(Pause::Breakpoint) => "breakpoint",
+            Some(Pause::Step) => step,
+            None => "pause",
+        };
+        let printed = self.output.take();
+        if !printed.is_empty() {
+            self.event("output", json!({ "category": "stdout", "output": printed }))?;
+        }
+
+        match state {
+            Ok(RunState::Finished) => self.exited(0),
+            Ok(_) => self.event(
+                "stopped",
+                json!({
+                    "reason": reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                }),
+            ),
+            Err(errors) => {
+                let report = errors
+                    .iter()
+                    .filter(|error| !matches!(error, Error::Exit(_)))
+                    .map(|error| format!("{}\n", error))
+                    .collect::<String>();
+                if !report.is_empty() {
+                    self.event("output", json!({ "category": "stderr", "output": report }))?;
+                }
+                self.exited(rlox::exit_code(&errors))
+            }
+        }
+    }
+
+    fn exited(&mut self, code: i32) -> io::Result<()> {
+        self.vm = None;
+        self.event("exited", json!({ "exitCode": code }))?;
+        self.event("terminated", json!({}))
+    }
+
+    fn paused_vm(&self) -> Result<&Vm, String> {
+        self.vm
+            .as_ref()
+            .ok_or_else(|| "The program isn't running.".to_string())
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let vm = self.paused_vm()?;
+        let path = self
+            .program
+            .as_ref()
+            .map(|program| program.display().to_string());
+
+        let frames: Vec<Json> = vm
+            .call_stack()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                let name = if frame.function.is_empty() {
+                    "<script>"
+                } else {
+                    &frame.function
+                };
+                json!({
+                    "id": id,
+                    "name": name,
+                    "line": frame.line,
+                    "column": 1,
+                    "source": { "path": path },
+                })
+            })
+            .collect();
+        Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+    }
+
+    fn variables(&self, reference: i64) -> Result<Json, String> {
+        let vm = self.paused_vm()?;
+        if reference < 1 {
+            return Err(format!("No variables for reference {}.", reference));
+        }
+        let frame = ((reference - 1) / SCOPES) as usize;
+
+        let variables = match (reference - 1) % SCOPES {
+            0 => vm.locals(frame),
+            1 => vm.upvalues(frame),
+            _ => {
+                let mut globals: Vec<_> = vm
+                    .globals_iter()
+                    .filter(|(_, value)| {
+                        !matches!(value, Value::Obj(Obj::NativeFun(_) | Obj::Module(_)))
+                    })
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                globals.sort_by(|a, b| a.0.cmp(&b.0));
+                globals
+            }
+        };
+
+        let variables: Vec<Json> = variables
+            .iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name,
+                    "value": value.to_string(),
+                    "type": value.type_name(),
+                    "variablesReference": 0,
+                })
+            })
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+}
+
+fn messages(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(|error| match error {
+            Error::Compile(message, _) => message.trim_end().to_string(),
+            error => error.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Serves a debugging session over stdin and stdout
+pub fn run() -> io::Result<()> {
+    let stdout = io::stdout();
+    Server::new(stdout.lock()).serve(&mut io::stdin().lock())
+}
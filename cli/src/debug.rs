@@ -0,0 +1,224 @@
+use std::path::Path;
+
+use rlox::{
+    error::Error,
+    vm::{
+        builder::VmBuilder,
+        debug::{Breakpoint, Debugger, Step},
+        object::Obj,
+        value::Value,
+        RunState, Vm,
+    },
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+const HELP: &str = "\
+b [line|function]  add a breakpoint, list them without an argument
+d <n>              delete breakpoint n
+c                  continue to the next breakpoint
+s                  step to the next line, into calls
+n                  step to the next line, over calls
+o                  step out of the current function
+bt                 show the call stack
+f <n>              select frame n of the call stack
+l                  show the locals of the selected frame
+u                  show the upvalues of the selected frame
+g                  show the globals defined by the script
+p <name>           print a variable visible from the selected frame
+h                  show this help
+q                  quit";
+
+fn breakpoint(text: &str) -> Breakpoint {
+    match text.parse() {
+        Ok(line) => Breakpoint::Line(line),
+        Err(_) => Breakpoint::Function(text.to_string()),
+    }
+}
+
+fn variables(variables: &[(String, Value)]) {
+    for (name, value) in variables {
+        println!("  {} = {}", name, value);
+    }
+}
+
+/// What the user asked for at the prompt
+enum Action {
+    Resume,
+    Quit,
+}
+
+struct Session<'a> {
+    vm: Vm,
+    lines: Vec<&'a str>,
+    /// Frame the inspection commands look at, the innermost after every pause
+    frame: usize,
+}
+
+impl Session<'_> {
+    fn debugger(&mut self) -> &mut Debugger {
+        self.vm
+            .debugger_mut()
+            .expect("the vm is built with a debugger")
+    }
+
+    fn show_position(&self) {
+        let Some(frame) = self.vm.call_stack().get(self.frame).cloned() else {
+            return;
+        };
+        // line 0 is code the compiler added, there is no source for it
+        let source = frame
+            .line
+            .checked_sub(1)
+            .and_then(|line| self.lines.get(line))
+            .unwrap_or(&"");
+        println!("{}() line {}", name(&frame.function), frame.line);
+        println!("{:>4} | {}", frame.line, source);
+    }
+
+    fn command(&mut self, line: &str) -> Option<Action> {
+        let mut words = line.split_whitespace();
+        let command = words.next()?;
+        let argument = words.next();
+
+        match (command, argument) {
+            ("b", None) => {
+                for (i, breakpoint) in self.debugger().breakpoints().iter().enumerate() {
+                    println!("{}: {}", i, breakpoint);
+                }
+            }
+            ("b", Some(at)) => {
+                self.debugger().add_breakpoint(breakpoint(at));
+            }
+            ("d", Some(n)) => {
+                let removed = n
+                    .parse()
+                    .ok()
+                    .and_then(|n| self.debugger().remove_breakpoint(n));
+                if removed.is_none() {
+                    println!("No breakpoint {}", n);
+                }
+            }
+            ("c", None) => {
+                self.debugger().run();
+                return Some(Action::Resume);
+            }
+            ("s", None) | ("n", None) | ("o", None) => {
+                let step = match command {
+                    "s" => Step::Into,
+                    "n" => Step::Over,
+                    _ => Step::Out,
+                };
+                self.debugger().step(step);
+                return Some(Action::Resume);
+            }
+            ("bt", None) => {
+                for (i, frame) in self.vm.call_stack().iter().enumerate().rev() {
+                    let marker = if i == self.frame { '>' } else { ' ' };
+                    println!(
+                        "{}{}: {}() line {}",
+                        marker,
+                        i,
+                        name(&frame.function),
+                        frame.line
+                    );
+                }
+            }
+            ("f", Some(n)) => match n.parse() {
+                Ok(n) if n < self.vm.call_stack().len() => {
+                    self.frame = n;
+                    self.show_position();
+                }
+                _ => println!("No frame {}", n),
+            },
+            ("l", None) => variables(&self.vm.locals(self.frame)),
+            ("u", None) => variables(&self.vm.upvalues(self.frame)),
+            ("g", None) => {
+                let mut globals: Vec<_> = self
+                    .vm
+                    .globals_iter()
+                    .filter(|(_, value)| !is_builtin(value))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                globals.sort_by(|a, b| a.0.cmp(&b.0));
+                variables(&globals);
+            }
+            ("p", Some(name)) => {
+                let value = self
+                    .vm
+                    .locals(self.frame)
+                    .into_iter()
+                    .rev()
+                    .chain(self.vm.upvalues(self.frame))
+                    .find(|(local, _)| local == name)
+                    .map(|(_, value)| value)
+                    .or_else(|| self.vm.get_global(name).cloned());
+                match value {
+                    Some(value) => println!("{}", value),
+                    None => println!("No variable {}", name),
+                }
+            }
+            ("h", None) => println!("{}", HELP),
+            ("q", None) => return Some(Action::Quit),
+            _ => println!("Unknown command '{}', h shows the commands", line.trim()),
+        }
+        None
+    }
+}
+
+fn name(function: &str) -> &str {
+    if function.is_empty() {
+        "<script>"
+    } else {
+        function
+    }
+}
+
+/// The standard library, not worth showing among the globals
+fn is_builtin(value: &Value) -> bool {
+    matches!(value, Value::Obj(Obj::NativeFun(_) | Obj::Module(_)))
+}
+
+/// Runs the script at `path` under the debugger, pausing at its first line
+/// unless there are `breakpoints`
+pub fn run(path: &Path, builder: VmBuilder, breakpoints: &[String]) -> Result<(), Vec<Error>> {
+    let source = rlox::read_source(path)?;
+    let mut debugger = Debugger::new();
+    for at in breakpoints {
+        debugger.add_breakpoint(breakpoint(at));
+    }
+    if breakpoints.is_empty() {
+        debugger.step(Step::Into);
+    }
+
+    let mut rl = DefaultEditor::new().map_err(|e| vec![Error::Io(e.to_string())])?;
+    let mut session = Session {
+        vm: builder.debugger(debugger).build(),
+        lines: source.lines().collect(),
+        frame: 0,
+    };
+
+    let mut state = session.vm.interpret(&source)?;
+    while state != RunState::Finished {
+        session.frame = session.vm.call_stack().len().saturating_sub(1);
+        session.show_position();
+
+        let action = loop {
+            match rl.readline("(debug) ") {
+                Ok(line) => {
+                    let _ = rl.add_history_entry(line.as_str());
+                    if let Some(action) = session.command(&line) {
+                        break action;
+                    }
+                }
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break Action::Quit,
+                Err(e) => return Err(vec![Error::Io(e.to_string())]),
+            }
+        };
+
+        match action {
+            Action::Resume => state = session.vm.resume().map_err(|e| vec![e])?,
+            Action::Quit => break,
+        }
+    }
+    Ok(())
+}
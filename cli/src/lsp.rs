@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+use rlox::{
+    analysis::{self, Analysis, Symbol, SymbolKind, KEYWORDS},
+    lint,
+    vm::{
+        builder::{Capabilities, VmBuilder},
+        object::Obj,
+        value::Value,
+    },
+};
+use serde_json::{json, Value as Json};
+
+use crate::message::{read_message, write_message};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Converts between byte offsets and LSP positions, whose columns count UTF-16 code units
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> Json {
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let character = self.text[self.starts[line]..offset].encode_utf16().count();
+        json!({ "line": line, "character": character })
+    }
+
+    fn offset(&self, position: &Json) -> usize {
+        let line = position["line"].as_u64().unwrap_or_default() as usize;
+        let Some(&start) = self.starts.get(line) else {
+            return self.text.len();
+        };
+        let mut character = position["character"].as_u64().unwrap_or_default() as usize;
+        for (i, c) in self.text[start..].char_indices() {
+            if character == 0 || c == '\n' {
+                return start + i;
+            }
+            character = character.saturating_sub(c.len_utf16());
+        }
+        self.text.len()
+    }
+
+    fn range(&self, span: &Range<usize>) -> Json {
+        json!({ "start": self.position(span.start), "end": self.position(span.end) })
+    }
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let analysis = Analysis::new(&text);
+        Self { text, analysis }
+    }
+
+    fn lines(&self) -> LineIndex<'_> {
+        LineIndex::new(&self.text)
+    }
+
+    fn symbol_at(&self, position: &Json) -> Option<usize> {
+        self.analysis.symbol_at(self.lines().offset(position))
+    }
+}
+
+/// LSP `SymbolKind` and `CompletionItemKind` of a symbol
+fn kinds(symbol: &Symbol) -> (i64, i64) {
+    match symbol.kind {
+        SymbolKind::Class => (5, 7),
+        SymbolKind::Method => (6, 2),
+        SymbolKind::Function => (12, 3),
+        SymbolKind::Variable | SymbolKind::Parameter => (13, 6),
+    }
+}
+
+/// A language server for Lox scripts, speaking the Language Server Protocol
+pub struct Server<W: Write> {
+    writer: W,
+    documents: HashMap<String, Document>,
+    /// Globals of the standard library with their completion kind
+    builtins: Vec<(String, i64)>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W) -> Self {
+        let vm = VmBuilder::new()
+            .capabilities(Capabilities::default())
+            .input(io::empty())
+            .build();
+        let mut builtins: Vec<_> = vm
+            .globals_iter()
+            .map(|(name, value)| {
+                let kind = match value {
+                    Value::Obj(Obj::Module(_)) => 9,
+                    _ => 3,
+                };
+                (name.clone(), kind)
+            })
+            .collect();
+        builtins.sort();
+
+        Self {
+            writer,
+            documents: HashMap::new(),
+            builtins,
+            shutdown: false,
+            exit: false,
+        }
+    }
+
+    /// Answers the client from `reader` until it sends `exit`
+    pub fn serve(&mut self, reader: &mut impl BufRead) -> io::Result<()> {
+        while !self.exit {
+            let Some(message) = read_message(reader)? else {
+                break;
+            };
+            self.message(&message)?;
+        }
+        Ok(())
+    }
+
+    fn message(&mut self, message: &Json) -> io::Result<()> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+
+        let result = if self.shutdown {
+            Err((INVALID_REQUEST, "The server is shut down.".to_string()))
+        } else {
+            self.request(method, params)
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut self.writer, &response)
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string())
+            }
+            "textDocument/didChange" => {
+                // documents are synced in full, the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|changes| changes.last()) {
+                    Some(change) => {
+                        let text = change["text"].as_str().unwrap_or_default();
+                        self.update(uri, text.to_string())
+                    }
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.publish(uri, Vec::new())
+            }
+            "exit" => {
+                self.exit = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let diagnostics = {
+            let lines = LineIndex::new(&text);
+            let errors = analysis::diagnostics(&text).into_iter().map(|diagnostic| {
+                json!({
+                    "range": lines.range(&diagnostic.span),
+                    "severity": 1,
+                    "source": "rlox",
+                    "message": diagnostic.message,
+                })
+            });
+            // lints only run on sources without errors
+            let warnings = lint::lint(&text).unwrap_or_default();
+            let warnings = warnings.iter().map(|warning| {
+                json!({
+                    "range": lines.range(&warning.span),
+                    "severity": 2,
+                    "code": warning.code.code(),
+                    "source": "rlox",
+                    "message": warning.message,
+                })
+            });
+            errors.chain(warnings).collect()
+        };
+        self.documents.insert(uri.to_string(), Document::new(text));
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        });
+        write_message(&mut self.writer, &notification)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if method == "initialize" {
+            return Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "rlox", "version": env!("CARGO_PKG_VERSION") },
+            }));
+        }
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(Json::Null);
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let position = &params["position"];
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown document '{}'.", uri)))?;
+        let lines = document.lines();
+        let location = |span: &Range<usize>| json!({ "uri": uri, "range": lines.range(span) });
+
+        match method {
+            "textDocument/definition" => {
+                Ok(document.symbol_at(position).map_or(Json::Null, |symbol| {
+                    location(&document.analysis.symbols[symbol].span)
+                }))
+            }
+            "textDocument/references" => {
+                let Some(symbol) = document.symbol_at(position) else {
+                    return Ok(json!([]));
+                };
+                let declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or_default()
+                    .then_some(&document.analysis.symbols[symbol].span);
+                let locations: Vec<Json> = declaration
+                    .into_iter()
+                    .chain(document.analysis.references_to(symbol))
+                    .map(location)
+                    .collect();
+                Ok(json!(locations))
+            }
+            "textDocument/hover" => Ok(document
+                .symbol_at(position)
+                .map_or(Json::Null, |symbol| hover(&document.analysis, symbol))),
+            "textDocument/documentSymbol" => Ok(document_symbols(document)),
+            "textDocument/completion" => {
+                let offset = lines.offset(position);
+                Ok(self.completion(&document.analysis, offset))
+            }
+            method => Err((
+                METHOD_NOT_FOUND,
+                format!("Unsupported method '{}'.", method),
+            )),
+        }
+    }
+
+    fn completion(&self, analysis: &Analysis, offset: usize) -> Json {
+        let names = analysis
+            .visible_at(offset)
+            .into_iter()
+            .map(|symbol| (symbol.name.clone(), kinds(symbol).1, symbol.signature()))
+            .chain(
+                self.builtins
+                    .iter()
+                    .map(|(name, kind)| (name.clone(), *kind, String::new())),
+            );
+
+        let mut items: Vec<Json> = Vec::new();
+        let mut seen = Vec::new();
+        for (name, kind, detail) in names {
+            if !seen.contains(&name) {
+                items.push(json!({ "label": name, "kind": kind, "detail": detail }));
+                seen.push(name);
+            }
+        }
+        items.extend(
+            KEYWORDS
+                .iter()
+                .map(|keyword| json!({ "label": keyword, "kind": 14 })),
+        );
+        json!(items)
+    }
+}
+
+/// The signature with the arity of what's called, a class is called through `init`
+fn hover(analysis: &Analysis, index: usize) -> Json {
+    let symbol = &analysis.symbols[index];
+    let arity = match symbol.kind {
+        SymbolKind::Function | SymbolKind::Method => Some(symbol.params.len()),
+        SymbolKind::Class => Some(
+            analysis
+                .methods_of(index)
+                .find(|(_, method)| method.name == "init")
+                .map_or(0, |(_, init)| init.params.len()),
+        ),
+        SymbolKind::Variable | SymbolKind::Parameter => None,
+    };
+
+    let mut value = format!("```lox\n{}\n```", symbol.signature());
+    if let Some(arity) = arity {
+        value.push_str(&format!("\n\narity {}", arity));
+    }
+    json!({ "contents": { "kind": "markdown", "value": value } })
+}
+
+/// Global functions and classes, with the methods of the classes
+fn document_symbols(document: &Document) -> Json {
+    let lines = document.lines();
+    let analysis = &document.analysis;
+    let symbol = |symbol: &Symbol, children: Vec<Json>| {
+        let range = lines.range(&symbol.span);
+        json!({
+            "name": symbol.name,
+            "detail": symbol.signature(),
+            "kind": kinds(symbol).0,
+            "range": range,
+            "selectionRange": range,
+            "children": children,
+        })
+    };
+
+    let symbols: Vec<Json> = analysis
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| {
+            s.is_global() && matches!(s.kind, SymbolKind::Class | SymbolKind::Function)
+        })
+        .map(|(index, class)| {
+            let methods = analysis
+                .methods_of(index)
+                .map(|(_, method)| symbol(method, Vec::new()))
+                .collect();
+            symbol(class, methods)
+        })
+        .collect();
+    json!(symbols)
+}
+
+/// Serves the language server over stdin and stdout
+pub fn run() -> io::Result<()> {
+    let stdout = io::stdout();
+    Server::new(stdout.lock()).serve(&mut io::stdin().lock())
+}
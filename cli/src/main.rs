@@ -1,20 +1,191 @@
-#![allow(unused)]
-
-use clap::Parser;
-use std::io::BufRead;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
 
+use rlox::{
+    compiler::CompilerOptions,
+    error::Error,
+    format::FormatOptions,
+    vm::{
+        builder::{Capabilities, VmBuilder},
+        trace::PrintTracer,
+    },
+};
 use rustyline::error::ReadlineError;
-use rustyline::{DefaultEditor, Result};
+use rustyline::DefaultEditor;
+
+mod dap;
+mod debug;
+mod lsp;
+mod message;
+#[cfg(test)]
+mod tests;
 
-/// Search for a pattern in a file and display the lines that contain it.
+/// Bytecode interpreter for the Lox language
 #[derive(Parser)]
+#[command(name = "rlox", version)]
 struct Cli {
-    /// The path to the file to read
-    path: Option<std::path::PathBuf>,
+    /// Starts the repl when missing
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script, `-` reads it from stdin
+    Run {
+        path: PathBuf,
+        /// Arguments passed to the script, after a `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+        #[command(flatten)]
+        debug: DebugArgs,
+    },
+    /// Run a script under an interactive debugger, `h` at its prompt lists the commands
+    Debug {
+        path: PathBuf,
+        /// Pause at a line number or at the entry of a function, the first line without any
+        #[arg(short, long = "break")]
+        breakpoints: Vec<String>,
+        /// Arguments passed to the script, after a `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Serve the Debug Adapter Protocol over stdin and stdout, for editors
+    Dap,
+    /// Serve the Language Server Protocol over stdin and stdout, for editors
+    Lsp,
+    /// Compile a script without running it and report every error
+    Check { path: PathBuf },
+    /// Warn about code that likely doesn't do what was meant, failing if there is any
+    Lint {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Format scripts in place, `-` formats stdin to stdout
+    Fmt {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Only list the scripts that aren't formatted, failing if there are any
+        #[arg(long)]
+        check: bool,
+        /// Spaces per indentation level
+        #[arg(long, default_value_t = 4)]
+        indent: usize,
+    },
+    /// Print the bytecode of a script and of every function in it
+    Disasm {
+        path: PathBuf,
+        /// Never color the output, it's only colored on a terminal anyway
+        #[arg(long)]
+        no_color: bool,
+        /// Optimization level of the bytecode, from 0 to 2
+        #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+        optimize: u8,
+    },
+    /// Run the source given as argument
+    Eval {
+        source: String,
+        #[command(flatten)]
+        debug: DebugArgs,
+    },
+    /// Read and run scripts interactively, a script runs on Ctrl-D
+    Repl {
+        #[command(flatten)]
+        debug: DebugArgs,
+    },
+}
+
+#[derive(Args, Clone, Copy, Default)]
+struct DebugArgs {
+    /// Print every executed instruction with the top of the stack
+    #[arg(long)]
+    trace: bool,
+    /// Print the bytecode of every compiled function
+    #[arg(long)]
+    disassemble: bool,
+    /// Optimization level of the bytecode, from 0 to 2
+    #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    optimize: u8,
 }
 
-fn repl() {
+fn builder(debug: DebugArgs, args: Vec<String>) -> VmBuilder {
+    let mut builder = VmBuilder::new()
+        .capabilities(Capabilities::all())
+        .args(args)
+        .compiler_options(CompilerOptions {
+            disassemble: debug.disassemble,
+            color: std::io::stdout().is_terminal(),
+            optimize: debug.optimize,
+        });
+    if debug.trace {
+        builder = builder.tracer(PrintTracer::new(
+            std::io::stdout(),
+            std::io::stdout().is_terminal(),
+        ));
+    }
+    builder
+}
+
+/// Prints the errors of a failed run, an `exit()` from the script isn't an error
+fn report(errors: &[Error]) {
+    for error in errors {
+        match error {
+            Error::Exit(_) => {}
+            Error::Compile(message, _) => eprintln!("{}", message.trim_end()),
+            Error::Runtime(message, line) => {
+                eprintln!("[line {}] Runtime error: {}", line, message)
+            }
+            error => eprintln!("{}", error),
+        }
+    }
+}
+
+/// Exits with the status for `result` once it failed
+fn finish<T>(result: Result<T, Vec<Error>>) -> T {
+    result.unwrap_or_else(|errors| {
+        report(&errors);
+        std::process::exit(rlox::exit_code(&errors));
+    })
+}
+
+/// Prints the warnings for every script at `paths`, whether there were none
+fn lint(paths: &[PathBuf]) -> Result<bool, Vec<Error>> {
+    let mut clean = true;
+    for path in paths {
+        let source = rlox::read_source(path)?;
+        for warning in rlox::lint::lint(&source)? {
+            eprintln!("{}: {}", path.display(), warning);
+            clean = false;
+        }
+    }
+    Ok(clean)
+}
+
+/// Formats every script at `paths`, with `check` only tells whether they all are
+fn fmt(paths: &[PathBuf], check: bool, options: &FormatOptions) -> Result<bool, Vec<Error>> {
+    let mut formatted = true;
+    for path in paths {
+        let source = rlox::read_source(path)?;
+        let output = rlox::format::format(&source, options)?;
+        if check {
+            if output != source {
+                eprintln!("{} is not formatted", path.display());
+                formatted = false;
+            }
+        } else if path == Path::new("-") {
+            print!("{}", output);
+        } else if output != source {
+            std::fs::write(path, output)
+                .map_err(|e| vec![Error::Io(format!("{}: {}", path.display(), e))])?;
+        }
+    }
+    Ok(formatted)
+}
+
+fn repl(debug: DebugArgs) {
     let mut rl = DefaultEditor::new().unwrap();
     let mut lines = String::new();
 
@@ -22,17 +193,20 @@ fn repl() {
         let readline = rl.readline(">> ");
         match readline {
             Ok(line) => {
-                rl.add_history_entry(line.as_str());
+                let _ = rl.add_history_entry(line.as_str());
                 lines.push_str(line.as_str());
+                lines.push('\n');
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
                 break;
             }
             Err(ReadlineError::Eof) => {
-                let expr = lines.as_str();
-                if let Err(e) = rlox::run(expr) {
-                    println!("ERROR: {:#?}", e);
+                if let Err(e) = builder(debug, Vec::new()).build().interpret(&lines) {
+                    if let [Error::Exit(code)] = e[..] {
+                        std::process::exit(code);
+                    }
+                    report(&e);
                 }
                 lines.clear();
             }
@@ -45,13 +219,66 @@ fn repl() {
 }
 
 fn main() {
-    let args = Cli::parse();
+    let cli = Cli::parse();
 
-    if let Some(path) = args.path {
-        if let Err(e) = rlox::run_file(path) {
-            println!("ERROR: {:#?}", e);
+    match cli.command {
+        Some(Command::Run { path, args, debug }) => {
+            finish(rlox::run_file(path, builder(debug, args)));
+        }
+        Some(Command::Debug {
+            path,
+            breakpoints,
+            args,
+        }) => {
+            finish(debug::run(
+                &path,
+                builder(DebugArgs::default(), args),
+                &breakpoints,
+            ));
+        }
+        Some(Command::Dap) => {
+            if let Err(e) = dap::run() {
+                eprintln!("{}", e);
+                std::process::exit(74);
+            }
+        }
+        Some(Command::Lsp) => {
+            if let Err(e) = lsp::run() {
+                eprintln!("{}", e);
+                std::process::exit(74);
+            }
+        }
+        Some(Command::Check { path }) => {
+            let source = finish(rlox::read_source(&path));
+            finish(rlox::check(&source));
+        }
+        Some(Command::Lint { paths }) => {
+            if !finish(lint(&paths)) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Fmt {
+            paths,
+            check,
+            indent,
+        }) => {
+            if !finish(fmt(&paths, check, &FormatOptions { indent })) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Disasm {
+            path,
+            no_color,
+            optimize,
+        }) => {
+            let source = finish(rlox::read_source(&path));
+            let color = !no_color && std::io::stdout().is_terminal();
+            print!("{}", finish(rlox::disassemble(&source, color, optimize)));
+        }
+        Some(Command::Eval { source, debug }) => {
+            finish(builder(debug, Vec::new()).build().interpret(&source));
         }
-    } else {
-        repl();
+        Some(Command::Repl { debug }) => repl(debug),
+        None => repl(DebugArgs::default()),
     }
 }
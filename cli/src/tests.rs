@@ -0,0 +1,328 @@
+use std::io::Cursor;
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    dap, lsp,
+    message::{read_message, write_message},
+};
+
+/// Runs a scripted client against the adapter and returns every message it sent back
+fn session(name: &str, source: &str, requests: &[(&str, Json)]) -> Vec<Json> {
+    let program = std::env::temp_dir().join(format!("rlox-{}-{}.lox", name, std::process::id()));
+    std::fs::write(&program, source).unwrap();
+
+    let mut input = Vec::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let mut arguments = arguments.clone();
+        if *command == "launch" {
+            arguments["program"] = json!(program.display().to_string());
+        }
+        let request = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        write_message(&mut input, &request).unwrap();
+    }
+
+    let mut output = Vec::new();
+    dap::Server::new(&mut output)
+        .serve(&mut Cursor::new(input))
+        .unwrap();
+    std::fs::remove_file(&program).unwrap();
+
+    let mut output = Cursor::new(output);
+    std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+}
+
+fn response<'a>(messages: &'a [Json], command: &str) -> Vec<&'a Json> {
+    messages
+        .iter()
+        .filter(|m| m["type"] == "response" && m["command"] == command)
+        .collect()
+}
+
+fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+    messages
+        .iter()
+        .filter(|m| m["type"] == "event" && m["event"] == event)
+        .collect()
+}
+
+#[test]
+fn dap_breakpoints_steps_and_variables() {
+    let source = indoc::indoc! {"
+        fun add(a, b) {
+            var sum = a + b;
+            return sum;
+        }
+        var total = add(1, 2);
+        print total;
+    "};
+    let messages = session(
+        "breakpoints",
+        source,
+        &[
+            ("initialize", json!({ "adapterID": "rlox" })),
+            ("launch", json!({})),
+            (
+                "setBreakpoints",
+                json!({ "breakpoints": [{ "line": 2 }, { "line": 3 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("threads", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("scopes", json!({ "frameId": 1 })),
+            ("variables", json!({ "variablesReference": 4 })),
+            ("next", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 4 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ],
+    );
+
+    for message in messages.iter().filter(|m| m["type"] == "response") {
+        assert_eq!(message["success"], true, "{}", message);
+    }
+    let initialized = events(&messages, "initialized");
+    assert!(initialized[0]["seq"].as_i64() > response(&messages, "initialize")[0]["seq"].as_i64());
+
+    let stopped: Vec<_> = events(&messages, "stopped")
+        .iter()
+        .map(|e| e["body"]["reason"].as_str().unwrap())
+        .collect();
+    // the step to line 3 ends on a breakpoint
+    assert_eq!(stopped, ["breakpoint", "breakpoint", "step"]);
+
+    let traces = response(&messages, "stackTrace");
+    let frames = &traces[0]["body"]["stackFrames"];
+    assert_eq!(frames[0]["name"], "add");
+    assert_eq!(frames[0]["line"], 2);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 5);
+    assert_eq!(traces[1]["body"]["totalFrames"], 1);
+
+    let scopes = &response(&messages, "scopes")[0]["body"]["scopes"];
+    assert_eq!(scopes[0]["name"], "Locals");
+    assert_eq!(scopes[0]["variablesReference"], 4);
+
+    let variables = response(&messages, "variables");
+    assert_eq!(
+        variables[0]["body"]["variables"],
+        json!([
+            { "name": "a", "value": "1", "type": "number", "variablesReference": 0 },
+            { "name": "b", "value": "2", "type": "number", "variablesReference": 0 },
+        ])
+    );
+    assert_eq!(variables[1]["body"]["variables"][2]["name"], "sum");
+    assert_eq!(variables[1]["body"]["variables"][2]["value"], "3");
+
+    let output = events(&messages, "output");
+    assert_eq!(output[0]["body"]["output"], "3\n");
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
+
+#[test]
+fn dap_reports_errors() {
+    let messages = session(
+        "errors",
+        "print 1 +;",
+        &[
+            ("initialize", json!({})),
+            ("launch", json!({})),
+            ("evaluate", json!({ "expression": "1" })),
+        ],
+    );
+    let launch = response(&messages, "launch")[0];
+    assert_eq!(launch["success"], false);
+    assert!(launch["message"]
+        .as_str()
+        .unwrap()
+        .contains("Expect expression"));
+    assert_eq!(response(&messages, "evaluate")[0]["success"], false);
+
+    let messages = session(
+        "runtime",
+        "print -nil;",
+        &[
+            ("launch", json!({ "stopOnEntry": true })),
+            ("configurationDone", json!({})),
+            ("stepIn", json!({ "threadId": 1 })),
+        ],
+    );
+    assert_eq!(events(&messages, "stopped")[0]["body"]["reason"], "entry");
+    let output = events(&messages, "output");
+    assert_eq!(output[0]["body"]["category"], "stderr");
+    assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 70);
+}
+
+/// Sends `messages` to the language server and returns everything it sent back
+fn lsp_session(messages: &[Json]) -> Vec<Json> {
+    let mut input = Vec::new();
+    for message in messages {
+        write_message(&mut input, message).unwrap();
+    }
+
+    let mut output = Vec::new();
+    lsp::Server::new(&mut output)
+        .serve(&mut Cursor::new(input))
+        .unwrap();
+
+    let mut output = Cursor::new(output);
+    std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+}
+
+fn lsp_request(id: i64, method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn lsp_notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+#[test]
+fn lsp_navigation_hover_symbols_and_completion() {
+    let uri = "file:///test.lox";
+    let source = indoc::indoc! {r#"
+        var café = "😀"; var n = café;
+        fun add(a, b) { return a + b; }
+        class Point {
+            init(x, y) { this.x = x; this.y = y; }
+            len() { return 0; }
+        }
+        print add(1, 2);
+    "#};
+    let at = |line, character| {
+        json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+            "context": { "includeDeclaration": true },
+        })
+    };
+    let range = |line, start, end| {
+        json!({
+            "start": { "line": line, "character": start },
+            "end": { "line": line, "character": end },
+        })
+    };
+
+    let messages = lsp_session(&[
+        lsp_request(1, "initialize", json!({ "capabilities": {} })),
+        lsp_notification("initialized", json!({})),
+        lsp_notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "lox", "version": 1, "text": source } }),
+        ),
+        lsp_request(2, "textDocument/definition", at(0, 26)),
+        lsp_request(3, "textDocument/references", at(6, 7)),
+        lsp_request(4, "textDocument/hover", at(6, 7)),
+        lsp_request(5, "textDocument/hover", at(2, 7)),
+        lsp_request(6, "textDocument/documentSymbol", at(0, 0)),
+        lsp_request(7, "textDocument/completion", at(1, 24)),
+        lsp_request(8, "textDocument/formatting", at(0, 0)),
+        lsp_request(9, "shutdown", json!(null)),
+        lsp_notification("exit", json!(null)),
+    ]);
+    let result = |id: i64| {
+        messages
+            .iter()
+            .find(|m| m["id"] == id)
+            .map(|m| m["result"].clone())
+            .unwrap()
+    };
+
+    assert_eq!(result(1)["capabilities"]["hoverProvider"], true);
+    assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(messages[1]["params"]["diagnostics"], json!([]));
+
+    assert_eq!(result(2), json!({ "uri": uri, "range": range(0, 4, 8) }));
+    assert_eq!(
+        result(3),
+        json!([
+            { "uri": uri, "range": range(1, 4, 7) },
+            { "uri": uri, "range": range(6, 6, 9) },
+        ])
+    );
+    assert_eq!(
+        result(4)["contents"]["value"],
+        "```lox\nfun add(a, b)\n```\n\narity 2"
+    );
+    assert_eq!(
+        result(5)["contents"]["value"],
+        "```lox\nclass Point\n```\n\narity 2"
+    );
+
+    let symbols = result(6);
+    assert_eq!(symbols[0]["name"], "add");
+    assert_eq!(symbols[1]["name"], "Point");
+    assert_eq!(symbols[1]["children"][0]["detail"], "init(x, y)");
+    assert_eq!(symbols[1]["children"][1]["name"], "len");
+
+    let labels: Vec<_> = result(7)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect();
+    for name in [
+        "a", "b", "café", "n", "add", "Point", "clock", "math", "while",
+    ] {
+        assert!(labels.contains(&name.to_string()), "{}", name);
+    }
+    assert!(!labels.contains(&"x".to_string()));
+
+    let unsupported = messages.iter().find(|m| m["id"] == 8).unwrap();
+    assert_eq!(unsupported["error"]["code"], -32601);
+    assert_eq!(result(9), json!(null));
+}
+
+#[test]
+fn lsp_publishes_diagnostics() {
+    let uri = "file:///broken.lox";
+    let messages = lsp_session(&[
+        lsp_notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "text": "var 😀 = 1;" } }),
+        ),
+        lsp_notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "var a = 1;\nprint a" }],
+            }),
+        ),
+        lsp_notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 3 },
+                "contentChanges": [{ "text": "fun f(a) {}" }],
+            }),
+        ),
+        lsp_notification(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": uri } }),
+        ),
+    ]);
+
+    let diagnostics: Vec<_> = messages
+        .iter()
+        .map(|m| m["params"]["diagnostics"].clone())
+        .collect();
+    assert_eq!(diagnostics[0][0]["message"], "Error: Unexpected character.");
+    assert_eq!(diagnostics[0][0]["range"]["start"]["character"], 4);
+    let message = diagnostics[1][0]["message"].as_str().unwrap();
+    assert!(message.starts_with("Error at end:"), "{}", message);
+    assert_eq!(
+        diagnostics[1][0]["range"]["start"],
+        json!({ "line": 1, "character": 7 })
+    );
+    assert_eq!(diagnostics[2][0]["severity"], 2);
+    assert_eq!(diagnostics[2][0]["code"], "W002");
+    assert_eq!(diagnostics[2][0]["message"], "Unused parameter 'a'.");
+    assert_eq!(diagnostics[3], json!([]));
+}
@@ -0,0 +1,394 @@
+//! Names declared and used in a script, resolved on its [`ast`](crate::ast) for editor
+//! tooling. A script with errors gets the tree the parser made out of it, so it's usable
+//! while typing.
+
+use std::ops::Range;
+
+use crate::{
+    ast::{parser, Expr, ExprKind, Function, Identifier, Script, Stmt, StmtKind},
+    error::Error,
+};
+
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Byte range of the name where it's declared
+    pub span: Range<usize>,
+    /// Where the name can be used, the whole source for globals and nothing for methods
+    pub scope: Range<usize>,
+    /// Class of a method
+    pub parent: Option<usize>,
+    /// Parameters of functions and methods
+    pub params: Vec<String>,
+    /// Superclass of a class
+    pub superclass: Option<String>,
+}
+
+impl Symbol {
+    pub fn is_global(&self) -> bool {
+        self.parent.is_none() && self.scope.start == 0 && self.kind != SymbolKind::Parameter
+    }
+
+    /// `fun add(a, b)`, `class Point` or `var x`
+    pub fn signature(&self) -> String {
+        match self.kind {
+            SymbolKind::Variable | SymbolKind::Parameter => format!("var {}", self.name),
+            SymbolKind::Class => format!("class {}", self.name),
+            SymbolKind::Function => format!("fun {}({})", self.name, self.params.join(", ")),
+            SymbolKind::Method => format!("{}({})", self.name, self.params.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub span: Range<usize>,
+    pub symbol: usize,
+    /// The use assigns the name rather than reading it
+    pub assigned: bool,
+}
+
+/// A compile error with the byte range it points at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+#[derive(Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Self {
+        let (script, _) = parser::parse(source);
+        Self::of(source, &script)
+    }
+
+    /// Names of `script`, the tree of `source`
+    pub fn of(source: &str, script: &Script) -> Self {
+        Resolver::new(source).resolve(script)
+    }
+
+    /// The symbol declared or used at `offset`
+    pub fn symbol_at(&self, offset: usize) -> Option<usize> {
+        // the end is included so a cursor right after a name still finds it
+        let at = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+        self.symbols
+            .iter()
+            .position(|symbol| at(&symbol.span))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|reference| at(&reference.span))
+                    .map(|reference| reference.symbol)
+            })
+    }
+
+    /// Every use of `symbol`, without its declaration
+    pub fn references_to(&self, symbol: usize) -> impl Iterator<Item = &Range<usize>> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.symbol == symbol)
+            .map(|reference| &reference.span)
+    }
+
+    /// Names usable at `offset`, a shadowed name only once
+    pub fn visible_at(&self, offset: usize) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = Vec::new();
+        for symbol in self.symbols.iter().rev() {
+            let declared = symbol.is_global() || symbol.span.end <= offset;
+            if symbol.scope.contains(&offset)
+                && declared
+                && !visible.iter().any(|other| other.name == symbol.name)
+            {
+                visible.push(symbol);
+            }
+        }
+        visible.reverse();
+        visible
+    }
+
+    pub fn methods_of(&self, class: usize) -> impl Iterator<Item = (usize, &Symbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .filter(move |(_, symbol)| symbol.parent == Some(class))
+    }
+}
+
+struct Resolver {
+    len: usize,
+    /// Symbols of the enclosing local scopes, innermost last
+    scopes: Vec<Vec<usize>>,
+    analysis: Analysis,
+    /// Names not declared in an enclosing scope, globals may be declared after their use
+    unresolved: Vec<(Identifier, bool)>,
+}
+
+impl Resolver {
+    fn new(source: &str) -> Self {
+        Self {
+            len: source.len(),
+            scopes: Vec::new(),
+            analysis: Analysis::default(),
+            unresolved: Vec::new(),
+        }
+    }
+
+    fn resolve(mut self, script: &Script) -> Analysis {
+        self.statements(&script.statements);
+
+        for (name, assigned) in std::mem::take(&mut self.unresolved) {
+            let global = self
+                .analysis
+                .symbols
+                .iter()
+                .position(|symbol| symbol.is_global() && symbol.name == name.name);
+            if let Some(symbol) = global {
+                self.analysis.references.push(Reference {
+                    span: name.span.range(),
+                    symbol,
+                    assigned,
+                });
+            }
+        }
+        self.analysis
+            .references
+            .sort_by_key(|reference| reference.span.start);
+        self.analysis
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => self.expression(expr),
+            StmtKind::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, SymbolKind::Variable, None);
+            }
+            StmtKind::Function(function) => {
+                let symbol = self.declare(&function.name, SymbolKind::Function, None);
+                self.function(function, symbol);
+            }
+            StmtKind::Class(class) => {
+                let symbol = self.declare(&class.name, SymbolKind::Class, None);
+                if let Some(superclass) = &class.superclass {
+                    self.reference(superclass, false);
+                    self.analysis.symbols[symbol].superclass = Some(superclass.name.clone());
+                }
+                for method in class.methods.iter() {
+                    let index = self.declare(&method.name, SymbolKind::Method, Some(symbol));
+                    self.function(method, index);
+                }
+            }
+            StmtKind::Block(statements) => {
+                self.scopes.push(Vec::new());
+                self.statements(statements);
+                self.end_scope(statement.span.end);
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StmtKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.scopes.push(Vec::new());
+                if let Some(initializer) = initializer {
+                    self.statement(initializer);
+                }
+                for expr in condition.iter().chain(increment.iter()) {
+                    self.expression(expr);
+                }
+                self.statement(body);
+                self.end_scope(statement.span.end);
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Number(_)
+            | ExprKind::String(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Nil
+            | ExprKind::This
+            | ExprKind::Super { .. } => {}
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            ExprKind::Variable(name) => self.reference(name, false),
+            ExprKind::Assign { name, value } => {
+                self.reference(name, true);
+                self.expression(value);
+            }
+            ExprKind::Grouping(operand) | ExprKind::Unary { operand, .. } => {
+                self.expression(operand)
+            }
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.expression(object),
+            ExprKind::Set { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            ExprKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+        }
+    }
+
+    fn end_scope(&mut self, end: usize) {
+        if let Some(scope) = self.scopes.pop() {
+            for symbol in scope {
+                self.analysis.symbols[symbol].scope.end = end;
+            }
+        }
+    }
+
+    /// Parameters and body of the function declared as `symbol`
+    fn function(&mut self, function: &Function, symbol: usize) {
+        self.scopes.push(Vec::new());
+        for param in function.params.iter() {
+            self.declare(param, SymbolKind::Parameter, None);
+            self.analysis.symbols[symbol]
+                .params
+                .push(param.name.clone());
+        }
+        self.statements(&function.body);
+        self.end_scope(function.span.end);
+    }
+
+    fn declare(&mut self, name: &Identifier, kind: SymbolKind, parent: Option<usize>) -> usize {
+        let index = self.analysis.symbols.len();
+        let start = name.span.start;
+        let scope = match (kind, self.scopes.last_mut()) {
+            (SymbolKind::Method, _) => start..start,
+            (_, Some(scope)) => {
+                scope.push(index);
+                start..self.len
+            }
+            (_, None) => 0..self.len,
+        };
+
+        self.analysis.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            span: name.span.range(),
+            scope,
+            parent,
+            params: Vec::new(),
+            superclass: None,
+        });
+        index
+    }
+
+    fn reference(&mut self, name: &Identifier, assigned: bool) {
+        let local = self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .iter()
+                .rev()
+                .find(|symbol| self.analysis.symbols[**symbol].name == name.name)
+        });
+        match local {
+            Some(&symbol) => self.analysis.references.push(Reference {
+                span: name.span.range(),
+                symbol,
+                assigned,
+            }),
+            None => self.unresolved.push((name.clone(), assigned)),
+        }
+    }
+}
+
+/// Compile errors of `source`, pointing at the token they are reported at
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let (_, errors) = parser::parse(source);
+
+    errors
+        .into_iter()
+        .filter_map(|error| match error.error {
+            Error::Compile(message, _) => Some((message, error.span)),
+            _ => None,
+        })
+        .map(|(message, span)| {
+            // messages look like `[line 1, <script>] Error at 'x': Expect expression.`
+            let message = message.trim_end();
+            let message = message.split_once("] ").map_or(message, |(_, rest)| rest);
+            Diagnostic {
+                message: message.to_string(),
+                span: span.range(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,885 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::*,
+    compiler::{
+        scanner::{Scanner, Token, TokenKind},
+        FunctionKind,
+    },
+    error::Error,
+};
+
+/// A compile error with the byte range of the token it's reported at
+#[derive(Clone, Debug)]
+pub struct SyntaxError {
+    pub error: Error,
+    pub span: Span,
+}
+
+/// Tree of `source` with every compile error in it, the tree of a broken script is
+/// what could be made out of it
+pub fn parse(source: &str) -> (Script, Vec<SyntaxError>) {
+    Parser::new(source).parse()
+}
+
+/// What the parser knows about the function it's in, to check declarations and `return`
+struct FunctionScope<'a> {
+    name: &'a str,
+    kind: FunctionKind,
+    /// Errors are skipped after one until the next statement
+    panic_mode: bool,
+    errors: Vec<SyntaxError>,
+    /// Names of the locals with their scope depth
+    locals: Vec<(&'a str, isize)>,
+    scope_depth: isize,
+}
+
+impl<'a> FunctionScope<'a> {
+    fn new(name: &'a str, kind: FunctionKind) -> Self {
+        let this = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Function | FunctionKind::Script => "",
+        };
+        Self {
+            name,
+            kind,
+            panic_mode: false,
+            errors: Vec::new(),
+            locals: vec![(this, 0)],
+            scope_depth: 0,
+        }
+    }
+}
+
+pub struct Parser<'a> {
+    scanner: Scanner<'a>,
+    previous: Token<'a>,
+    current: Token<'a>,
+    functions: Vec<FunctionScope<'a>>,
+    /// Whether each class being parsed has a superclass
+    classes: Vec<bool>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            scanner: Scanner::new(source),
+            previous: Token::new(TokenKind::Error, "n/a", 0),
+            current: Token::new(TokenKind::Error, "n/a", 0),
+            functions: vec![FunctionScope::new("", FunctionKind::Script)],
+            classes: Vec::new(),
+        }
+    }
+
+    pub fn parse(mut self) -> (Script, Vec<SyntaxError>) {
+        self.advance();
+
+        let mut statements = Vec::new();
+        while !self.matches(TokenKind::Eof) {
+            statements.push(self.declaration());
+        }
+
+        let errors = self.functions.pop().unwrap().errors;
+        (Script { statements }, errors)
+    }
+
+    fn function_scope(&mut self) -> &mut FunctionScope<'a> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn advance(&mut self) {
+        self.previous = self.current;
+
+        loop {
+            self.current = self.scanner.scan_token();
+
+            if self.current.kind != TokenKind::Error {
+                break;
+            }
+
+            self.error_at_current(self.current.lexeme);
+        }
+    }
+
+    fn consume(&mut self, kind: TokenKind, message: &str) {
+        if self.current.kind == kind {
+            self.advance();
+            return;
+        }
+
+        self.error_at_current(message)
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.current.kind == kind
+    }
+
+    fn matches(&mut self, kind: TokenKind) -> bool {
+        if !self.check(kind) {
+            return false;
+        }
+
+        self.advance();
+        true
+    }
+
+    /// Span from `start` to the end of the previous token
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.previous.span().end.max(start))
+    }
+
+    fn identifier(&self) -> Identifier {
+        Identifier {
+            name: self.previous.lexeme.to_string(),
+            span: self.previous.span().into(),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.function_scope().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let function = self.function_scope();
+        function.scope_depth -= 1;
+        while function
+            .locals
+            .last()
+            .is_some_and(|(_, depth)| *depth > function.scope_depth)
+        {
+            function.locals.pop();
+        }
+    }
+
+    /// Declares the local named by the previous token, globals aren't declared
+    fn declare_variable(&mut self) {
+        let name = self.previous.lexeme;
+        let function = self.function_scope();
+        if function.scope_depth == 0 {
+            return;
+        }
+
+        for (local, depth) in function.locals.iter().rev() {
+            if *depth < function.scope_depth {
+                break;
+            }
+
+            if *local == name {
+                return self.error("Already a variable with this name in this scope.");
+            }
+        }
+
+        let depth = function.scope_depth;
+        function.locals.push((name, depth));
+    }
+
+    fn parse_variable(&mut self, message: &str) -> Identifier {
+        self.consume(TokenKind::Identifier, message);
+        self.declare_variable();
+        self.identifier()
+    }
+
+    fn declaration(&mut self) -> Stmt {
+        let statement = if self.matches(TokenKind::Class) {
+            self.class_declaration()
+        } else if self.matches(TokenKind::Fun) {
+            self.fun_declaration()
+        } else if self.matches(TokenKind::Var) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+
+        if self.function_scope().panic_mode {
+            self.synchronize();
+        }
+        statement
+    }
+
+    fn synchronize(&mut self) {
+        self.function_scope().panic_mode = false;
+
+        while self.current.kind != TokenKind::Eof {
+            match self.current.kind {
+                TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Var
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Print
+                | TokenKind::Return => {
+                    return;
+                }
+                _ => {}
+            }
+
+            self.advance();
+        }
+    }
+
+    fn class_declaration(&mut self) -> Stmt {
+        let start = self.previous.offset;
+        self.consume(TokenKind::Identifier, "Expect class name.");
+        let name = self.identifier();
+        self.declare_variable();
+
+        self.classes.push(false);
+
+        let mut superclass = None;
+        if self.matches(TokenKind::Less) {
+            self.consume(TokenKind::Identifier, "Expect superclass name.");
+            if self.previous.lexeme == name.name {
+                self.error("A class can't inherit from itself.");
+            }
+            superclass = Some(self.identifier());
+
+            self.begin_scope();
+            let depth = self.function_scope().scope_depth;
+            self.function_scope().locals.push(("super", depth));
+            *self.classes.last_mut().unwrap() = true;
+        }
+
+        self.consume(TokenKind::LeftBrace, "Expect '{' before class body.");
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            methods.push(self.method());
+        }
+        self.consume(TokenKind::RightBrace, "Expect '}' after class body.");
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        self.classes.pop();
+
+        let class = Class {
+            name,
+            superclass,
+            methods,
+            span: self.span_from(start),
+        };
+        Stmt::new(StmtKind::Class(class), self.span_from(start))
+    }
+
+    fn method(&mut self) -> Function {
+        self.consume(TokenKind::Identifier, "Expect method name.");
+        let kind = if self.previous.lexeme == "init" {
+            FunctionKind::Initializer
+        } else {
+            FunctionKind::Method
+        };
+        self.function(kind)
+    }
+
+    fn fun_declaration(&mut self) -> Stmt {
+        let start = self.previous.offset;
+        self.parse_variable("Expect function name.");
+        let function = self.function(FunctionKind::Function);
+        Stmt::new(StmtKind::Function(function), self.span_from(start))
+    }
+
+    /// Parameters and body of the function named by the previous token
+    fn function(&mut self, kind: FunctionKind) -> Function {
+        let name = self.identifier();
+        self.functions
+            .push(FunctionScope::new(self.previous.lexeme, kind));
+        self.begin_scope();
+
+        self.consume(TokenKind::LeftParen, "Expect '(' after function name.");
+
+        let mut params = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if params.len() == 256 {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+
+                params.push(self.parse_variable("Expect parameter name."));
+
+                if !self.matches(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenKind::RightParen, "Expect ')' after parameters.");
+        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.");
+
+        let body = self.block();
+
+        // errors of nested functions are reported after the ones of their parent
+        let mut errors = self.functions.pop().unwrap().errors;
+        self.function_scope().errors.append(&mut errors);
+
+        Function {
+            span: self.span_from(name.span.start),
+            name,
+            params,
+            body,
+        }
+    }
+
+    fn var_declaration(&mut self) -> Stmt {
+        let start = self.previous.offset;
+        let name = self.parse_variable("Expect variable name.");
+
+        let initializer = self.matches(TokenKind::Equal).then(|| self.expression());
+
+        self.consume(
+            TokenKind::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+
+        Stmt::new(StmtKind::Var { name, initializer }, self.span_from(start))
+    }
+
+    fn statement(&mut self) -> Stmt {
+        let start = self.current.offset;
+        let kind = if self.matches(TokenKind::Print) {
+            self.print_statement()
+        } else if self.matches(TokenKind::For) {
+            self.for_statement()
+        } else if self.matches(TokenKind::If) {
+            self.if_statement()
+        } else if self.matches(TokenKind::Return) {
+            self.return_statement()
+        } else if self.matches(TokenKind::While) {
+            self.while_statement()
+        } else if self.matches(TokenKind::LeftBrace) {
+            self.begin_scope();
+            let statements = self.block();
+            self.end_scope();
+            StmtKind::Block(statements)
+        } else {
+            StmtKind::Expression(self.expression_statement())
+        };
+        Stmt::new(kind, self.span_from(start))
+    }
+
+    fn block(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            statements.push(self.declaration());
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after block.");
+        statements
+    }
+
+    fn expression_statement(&mut self) -> Expr {
+        let expression = self.expression();
+        self.consume(TokenKind::Semicolon, "Expect ';' after expression.");
+        expression
+    }
+
+    fn print_statement(&mut self) -> StmtKind {
+        let value = self.expression();
+        self.consume(TokenKind::Semicolon, "Excpect ';' after value.");
+        StmtKind::Print(value)
+    }
+
+    fn return_statement(&mut self) -> StmtKind {
+        if self.function_scope().kind == FunctionKind::Script {
+            self.error("Can't return from top-level code.");
+        }
+
+        if self.matches(TokenKind::Semicolon) {
+            return StmtKind::Return(None);
+        }
+
+        if self.function_scope().kind == FunctionKind::Initializer {
+            self.error("Cant't return a value from an initializer.");
+        }
+        let value = self.expression();
+        self.consume(TokenKind::Semicolon, "Expect ';' after return value.");
+        StmtKind::Return(Some(value))
+    }
+
+    fn if_statement(&mut self) -> StmtKind {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.");
+        let condition = self.expression();
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+
+        let then_branch = Box::new(self.statement());
+        let else_branch = self
+            .matches(TokenKind::Else)
+            .then(|| Box::new(self.statement()));
+
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        }
+    }
+
+    fn while_statement(&mut self) -> StmtKind {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.");
+        let condition = self.expression();
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+
+        let body = Box::new(self.statement());
+        StmtKind::While { condition, body }
+    }
+
+    fn for_statement(&mut self) -> StmtKind {
+        self.begin_scope();
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.");
+
+        let initializer = if self.matches(TokenKind::Semicolon) {
+            None
+        } else if self.matches(TokenKind::Var) {
+            Some(Box::new(self.var_declaration()))
+        } else {
+            let start = self.current.offset;
+            let expression = self.expression_statement();
+            Some(Box::new(Stmt::new(
+                StmtKind::Expression(expression),
+                self.span_from(start),
+            )))
+        };
+
+        let mut condition = None;
+        if !self.matches(TokenKind::Semicolon) {
+            condition = Some(self.expression());
+            self.consume(TokenKind::Semicolon, "Expect ';' after loop condition.");
+        }
+
+        let mut increment = None;
+        if !self.matches(TokenKind::RightParen) {
+            increment = Some(self.expression());
+            self.consume(TokenKind::RightParen, "Expect ')' after for clauses.");
+        }
+
+        let body = Box::new(self.statement());
+        self.end_scope();
+
+        StmtKind::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        }
+    }
+
+    fn expression(&mut self) -> Expr {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Expr {
+        self.advance();
+
+        let Some(prefix) = get_rule(self.previous.kind).prefix else {
+            self.error_at_current("Expect expression.");
+            return Expr::new(ExprKind::Nil, self.previous.span().into());
+        };
+
+        let can_assign = precedence <= Precedence::Assignment;
+        let mut expression = prefix(self, can_assign);
+
+        while precedence <= get_rule(self.current.kind).precedence {
+            self.advance();
+
+            if let Some(infix) = get_rule(self.previous.kind).infix {
+                expression = infix(self, expression, can_assign);
+            }
+        }
+
+        if can_assign && self.matches(TokenKind::Equal) {
+            self.error("Invalid assignment target.");
+        }
+        expression
+    }
+
+    fn argument_list(&mut self) -> Vec<Expr> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                arguments.push(self.expression());
+                if arguments.len() == 256 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                if !self.matches(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenKind::RightParen, "Expect ')' after arguments.");
+        arguments
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current, message)
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error_at(self.previous, message)
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        let function = self.function_scope();
+        if function.panic_mode {
+            return;
+        }
+        function.panic_mode = true;
+
+        let mut out = String::new();
+        if function.name.is_empty() {
+            write!(out, "[line {}, <script>] Error", token.line).unwrap();
+        } else {
+            write!(out, "[line {}, <fn {}>] Error", token.line, function.name).unwrap();
+        }
+
+        if token.kind == TokenKind::Eof {
+            write!(out, " at end").unwrap();
+        } else if token.kind != TokenKind::Error {
+            write!(out, " at '{}'", token.lexeme).unwrap();
+        }
+
+        writeln!(out, ": {}", message).unwrap();
+
+        function.errors.push(SyntaxError {
+            error: Error::Compile(out, token.line),
+            span: token.span().into(),
+        });
+    }
+}
+
+fn get_rule(kind: TokenKind) -> Rule {
+    match kind {
+        TokenKind::LeftParen => Rule::new(Some(&grouping), Some(&call), Precedence::Call),
+        TokenKind::RightParen => Rule::new(None, None, Precedence::None),
+        TokenKind::LeftBrace => Rule::new(Some(&map), None, Precedence::None),
+        TokenKind::RightBrace => Rule::new(None, None, Precedence::None),
+        TokenKind::LeftBracket => Rule::new(Some(&list), Some(&subscript), Precedence::Call),
+        TokenKind::RightBracket => Rule::new(None, None, Precedence::None),
+        TokenKind::Comma => Rule::new(None, None, Precedence::None),
+        TokenKind::Dot => Rule::new(None, Some(&dot), Precedence::Call),
+        TokenKind::Minus => Rule::new(Some(&unary), Some(&binary), Precedence::Term),
+        TokenKind::Plus => Rule::new(None, Some(&binary), Precedence::Term),
+        TokenKind::Semicolon => Rule::new(None, None, Precedence::None),
+        TokenKind::Colon => Rule::new(None, None, Precedence::None),
+        TokenKind::Slash => Rule::new(None, Some(&binary), Precedence::Factor),
+        TokenKind::Star => Rule::new(None, Some(&binary), Precedence::Factor),
+        TokenKind::Bang => Rule::new(Some(&unary), None, Precedence::None),
+        TokenKind::BangEqual => Rule::new(None, Some(&binary), Precedence::Equality),
+        TokenKind::Equal => Rule::new(None, None, Precedence::None),
+        TokenKind::EqualEqual => Rule::new(None, Some(&binary), Precedence::Equality),
+        TokenKind::Greater => Rule::new(None, Some(&binary), Precedence::Comparison),
+        TokenKind::GreaterEqual => Rule::new(None, Some(&binary), Precedence::Comparison),
+        TokenKind::Less => Rule::new(None, Some(&binary), Precedence::Comparison),
+        TokenKind::LessEqual => Rule::new(None, Some(&binary), Precedence::Comparison),
+        TokenKind::Identifier => Rule::new(Some(&variable), None, Precedence::None),
+        TokenKind::String => Rule::new(Some(&string), None, Precedence::None),
+        TokenKind::Number => Rule::new(Some(&number), None, Precedence::None),
+        TokenKind::And => Rule::new(None, Some(&and), Precedence::And),
+        TokenKind::Class => Rule::new(None, None, Precedence::None),
+        TokenKind::Else => Rule::new(None, None, Precedence::None),
+        TokenKind::False => Rule::new(Some(&literal), None, Precedence::None),
+        TokenKind::For => Rule::new(None, None, Precedence::None),
+        TokenKind::Fun => Rule::new(None, None, Precedence::None),
+        TokenKind::If => Rule::new(None, None, Precedence::None),
+        TokenKind::Nil => Rule::new(Some(&literal), None, Precedence::None),
+        TokenKind::Or => Rule::new(None, Some(&or), Precedence::Or),
+        TokenKind::Print => Rule::new(None, None, Precedence::None),
+        TokenKind::Return => Rule::new(None, None, Precedence::None),
+        TokenKind::Super => Rule::new(Some(&super_), None, Precedence::None),
+        TokenKind::This => Rule::new(Some(&this), None, Precedence::None),
+        TokenKind::True => Rule::new(Some(&literal), None, Precedence::None),
+        TokenKind::Var => Rule::new(None, None, Precedence::None),
+        TokenKind::While => Rule::new(None, None, Precedence::None),
+        TokenKind::Comment => Rule::new(None, None, Precedence::None),
+        TokenKind::Error => Rule::new(None, None, Precedence::None),
+        TokenKind::Eof => Rule::new(None, None, Precedence::None),
+    }
+}
+
+fn grouping(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let start = parser.previous.offset;
+    let expression = parser.expression();
+    parser.consume(TokenKind::RightParen, "Expect ')' after expression.");
+    Expr::new(
+        ExprKind::Grouping(Box::new(expression)),
+        parser.span_from(start),
+    )
+}
+
+fn binary(parser: &mut Parser, left: Expr, _can_assign: bool) -> Expr {
+    let operator_kind = parser.previous.kind;
+
+    let rule = get_rule(operator_kind);
+    let right = parser.parse_precedence(rule.precedence.next());
+
+    let operator = match operator_kind {
+        TokenKind::BangEqual => BinaryOp::NotEqual,
+        TokenKind::EqualEqual => BinaryOp::Equal,
+        TokenKind::Greater => BinaryOp::Greater,
+        TokenKind::GreaterEqual => BinaryOp::GreaterEqual,
+        TokenKind::Less => BinaryOp::Less,
+        TokenKind::LessEqual => BinaryOp::LessEqual,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Subtract,
+        TokenKind::Star => BinaryOp::Multiply,
+        _ => BinaryOp::Divide,
+    };
+    let span = parser.span_from(left.span.start);
+    Expr::new(
+        ExprKind::Binary {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        span,
+    )
+}
+
+fn number(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let value = parser.previous.lexeme.parse::<f64>().unwrap();
+    Expr::new(ExprKind::Number(value), parser.previous.span().into())
+}
+
+fn unary(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let start = parser.previous.offset;
+    let operator = match parser.previous.kind {
+        TokenKind::Bang => UnaryOp::Not,
+        _ => UnaryOp::Negate,
+    };
+
+    let operand = parser.parse_precedence(Precedence::Unary);
+
+    Expr::new(
+        ExprKind::Unary {
+            operator,
+            operand: Box::new(operand),
+        },
+        parser.span_from(start),
+    )
+}
+
+fn literal(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let kind = match parser.previous.kind {
+        TokenKind::False => ExprKind::Bool(false),
+        TokenKind::True => ExprKind::Bool(true),
+        _ => ExprKind::Nil,
+    };
+    Expr::new(kind, parser.previous.span().into())
+}
+
+fn string(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let value = parser
+        .previous
+        .lexeme
+        .trim_matches('"')
+        .replace("\\n", "\n");
+    Expr::new(ExprKind::String(value), parser.previous.span().into())
+}
+
+fn variable(parser: &mut Parser, can_assign: bool) -> Expr {
+    let name = parser.identifier();
+    let start = name.span.start;
+
+    if can_assign && parser.matches(TokenKind::Equal) {
+        let value = parser.expression();
+        Expr::new(
+            ExprKind::Assign {
+                name,
+                value: Box::new(value),
+            },
+            parser.span_from(start),
+        )
+    } else {
+        Expr::new(ExprKind::Variable(name.clone()), name.span)
+    }
+}
+
+fn and(parser: &mut Parser, left: Expr, _can_assign: bool) -> Expr {
+    let right = parser.parse_precedence(Precedence::And);
+    logical(parser, LogicalOp::And, left, right)
+}
+
+fn or(parser: &mut Parser, left: Expr, _can_assign: bool) -> Expr {
+    let right = parser.parse_precedence(Precedence::Or);
+    logical(parser, LogicalOp::Or, left, right)
+}
+
+fn logical(parser: &Parser, operator: LogicalOp, left: Expr, right: Expr) -> Expr {
+    let span = parser.span_from(left.span.start);
+    Expr::new(
+        ExprKind::Logical {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        span,
+    )
+}
+
+fn call(parser: &mut Parser, callee: Expr, _can_assign: bool) -> Expr {
+    let arguments = parser.argument_list();
+    let span = parser.span_from(callee.span.start);
+    Expr::new(
+        ExprKind::Call {
+            callee: Box::new(callee),
+            arguments,
+        },
+        span,
+    )
+}
+
+fn dot(parser: &mut Parser, object: Expr, can_assign: bool) -> Expr {
+    parser.consume(TokenKind::Identifier, "Expect property name after '.'.");
+    let name = parser.identifier();
+    let start = object.span.start;
+    let object = Box::new(object);
+
+    if can_assign && parser.matches(TokenKind::Equal) {
+        let value = Box::new(parser.expression());
+        Expr::new(
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            },
+            parser.span_from(start),
+        )
+    } else if parser.matches(TokenKind::LeftParen) {
+        // compiled into a single invoke instruction
+        let get = Expr::new(ExprKind::Get { object, name }, parser.span_from(start));
+        call(parser, get, false)
+    } else {
+        Expr::new(ExprKind::Get { object, name }, parser.span_from(start))
+    }
+}
+
+fn list(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let start = parser.previous.offset;
+    let mut elements = Vec::new();
+    if !parser.check(TokenKind::RightBracket) {
+        loop {
+            elements.push(parser.expression());
+            if !parser.matches(TokenKind::Comma) {
+                break;
+            }
+        }
+    }
+    parser.consume(TokenKind::RightBracket, "Expect ']' after list elements.");
+    Expr::new(ExprKind::List(elements), parser.span_from(start))
+}
+
+fn map(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let start = parser.previous.offset;
+    let mut entries = Vec::new();
+    if !parser.check(TokenKind::RightBrace) {
+        loop {
+            let key = parser.expression();
+            parser.consume(TokenKind::Colon, "Expect ':' after map key.");
+            let value = parser.expression();
+            entries.push((key, value));
+            if !parser.matches(TokenKind::Comma) {
+                break;
+            }
+        }
+    }
+    parser.consume(TokenKind::RightBrace, "Expect '}' after map entries.");
+    Expr::new(ExprKind::Map(entries), parser.span_from(start))
+}
+
+fn subscript(parser: &mut Parser, object: Expr, can_assign: bool) -> Expr {
+    let index = Box::new(parser.expression());
+    parser.consume(TokenKind::RightBracket, "Expect ']' after index.");
+    let start = object.span.start;
+    let object = Box::new(object);
+
+    if can_assign && parser.matches(TokenKind::Equal) {
+        let value = Box::new(parser.expression());
+        Expr::new(
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+            },
+            parser.span_from(start),
+        )
+    } else {
+        Expr::new(ExprKind::Index { object, index }, parser.span_from(start))
+    }
+}
+
+fn this(parser: &mut Parser, _can_assign: bool) -> Expr {
+    if parser.classes.is_empty() {
+        parser.error("Can't use 'this' outside of a class.");
+    }
+    Expr::new(ExprKind::This, parser.previous.span().into())
+}
+
+fn super_(parser: &mut Parser, _can_assign: bool) -> Expr {
+    let start = parser.previous.offset;
+    match parser.classes.last() {
+        None => parser.error("Can't use 'super' outside of a class."),
+        Some(false) => parser.error("Can't use 'super' in a class with no superclass."),
+        Some(true) => {}
+    }
+
+    parser.consume(TokenKind::Dot, "Expect '.' after 'super'.");
+    parser.consume(TokenKind::Identifier, "Expect superclass method name.");
+    let method = parser.identifier();
+    let expression = Expr::new(ExprKind::Super { method }, parser.span_from(start));
+
+    if parser.matches(TokenKind::LeftParen) {
+        call(parser, expression, false)
+    } else {
+        expression
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::None => Self::Assignment,
+            Self::Assignment => Self::Or,
+            Self::Or => Self::And,
+            Self::And => Self::Equality,
+            Self::Equality => Self::Comparison,
+            Self::Comparison => Self::Term,
+            Self::Term => Self::Factor,
+            Self::Factor => Self::Unary,
+            Self::Unary => Self::Call,
+            Self::Call => Self::Primary,
+            Self::Primary => Self::Primary,
+        }
+    }
+}
+
+type PrefixFn = &'static dyn Fn(&mut Parser, bool) -> Expr;
+type InfixFn = &'static dyn Fn(&mut Parser, Expr, bool) -> Expr;
+
+#[derive(Clone, Copy)]
+struct Rule {
+    prefix: Option<PrefixFn>,
+    infix: Option<InfixFn>,
+    precedence: Precedence,
+}
+
+impl Rule {
+    fn new(prefix: Option<PrefixFn>, infix: Option<InfixFn>, precedence: Precedence) -> Rule {
+        Rule {
+            prefix,
+            infix,
+            precedence,
+        }
+    }
+}
@@ -1,44 +1,44 @@
-use crate::compiler::scanner::*;
+use crate::ast::{parser, *};
 use crate::error::*;
 use crate::vm::chunk::*;
 use crate::vm::object::*;
 use crate::vm::opcode::OpCode;
 use crate::vm::value::Value;
-use std::fmt::Write;
 use std::rc::Rc;
 
-mod scanner;
+pub mod optimizer;
+pub mod scanner;
 
-pub struct State<'a> {
-    panic_mode: bool,
+pub struct State {
     function: FunDescriptor,
     kind: FunctionKind,
     scope_depth: isize,
-    locals: Vec<Local<'a>>,
-    errors: Vec<Error>,
+    locals: Vec<Local>,
 }
 
-impl<'a> State<'a> {
+impl State {
     pub fn new(function_name: impl Into<String>, kind: FunctionKind) -> Self {
         let local = Local::new(
-            Token::new(
-                TokenKind::Fun,
-                if kind == FunctionKind::Method || kind == FunctionKind::Initializer {
-                    "this"
-                } else {
-                    ""
-                },
-                0,
-            ),
+            if kind == FunctionKind::Method || kind == FunctionKind::Initializer {
+                "this"
+            } else {
+                ""
+            },
             0,
         );
+        let mut function = FunDescriptor::new(function_name.into());
+        if !local.name.is_empty() {
+            function.locals.push(LocalInfo {
+                name: local.name.clone(),
+                slot: 0,
+                start: 0,
+                end: usize::MAX,
+            });
+        }
         Self {
-            panic_mode: false,
-            errors: Vec::new(),
-
             locals: vec![local],
             scope_depth: 0,
-            function: FunDescriptor::new(function_name.into()),
+            function,
             kind,
         }
     }
@@ -48,109 +48,97 @@ impl<'a> State<'a> {
     }
 }
 
+/// Switches for debugging the compiler output
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompilerOptions {
+    /// Keep the bytecode of every function once it's compiled, see [`Compiler::disassembly`]
+    pub disassemble: bool,
+    /// Color the disassembled bytecode for a terminal
+    pub color: bool,
+    /// Optimization level from 0, the bytecode as compiled, to 2, see [`optimizer`]
+    pub optimize: u8,
+}
+
+/// Compiles the syntax tree of a script into bytecode
 pub struct Compiler<'a> {
-    states: Vec<State<'a>>,
-    scanner: Scanner<'a>,
-    previous: Token<'a>,
-    current: Token<'a>,
-    classes: Vec<ClassScope>,
+    options: CompilerOptions,
+    source: &'a str,
+    /// Byte offsets where the lines of the source start
+    line_starts: Vec<usize>,
+    /// Line of the instructions emitted next
+    line: usize,
+    states: Vec<State>,
+    disassembly: String,
 }
 
 impl<'a> Compiler<'a> {
     pub fn compile(&mut self) -> Result<FunDescriptor, Vec<Error>> {
-        self.state().panic_mode = false;
-        self.advance();
+        let (script, errors) = parser::parse(self.source);
+        if !errors.is_empty() {
+            return Err(errors.into_iter().map(|error| error.error).collect());
+        }
 
-        while !self.matches(TokenKind::Eof) {
-            self.declaration();
+        for statement in script.statements.iter() {
+            self.statement(statement);
         }
 
-        self.end()
+        // the end of the source is where the script returns
+        self.line = self.line_starts.len();
+        Ok(self.end())
     }
 
-    pub fn new(source: &'a str, state: State<'a>) -> Compiler<'a> {
+    pub fn new(source: &'a str, state: State) -> Compiler<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
         Self {
-            scanner: Scanner::new(source),
+            options: CompilerOptions::default(),
+            source,
+            line_starts,
+            line: 1,
             states: vec![state],
-            previous: Token {
-                kind: TokenKind::Error,
-                lexeme: "n/a",
-                line: 0,
-            },
-            current: Token {
-                kind: TokenKind::Error,
-                lexeme: "n/a",
-                line: 0,
-            },
-            classes: Vec::new(),
+            disassembly: String::new(),
         }
     }
 
-    fn state(&mut self) -> &mut State<'a> {
-        self.states.last_mut().unwrap()
+    pub fn with_options(mut self, options: CompilerOptions) -> Self {
+        self.options = options;
+        self
     }
 
-    fn state_ref(&self) -> &State<'a> {
-        self.states.last().unwrap()
+    /// Bytecode of the functions compiled so far, in the order they were finished,
+    /// empty unless [`CompilerOptions::disassemble`] is set
+    pub fn disassembly(&self) -> &str {
+        &self.disassembly
     }
 
-    fn state_enclosing(&mut self) -> &mut State<'a> {
+    fn state(&mut self) -> &mut State {
         self.states.last_mut().unwrap()
     }
 
-    fn class(&mut self) -> &mut ClassScope {
-        self.classes.last_mut().unwrap()
+    /// Instructions emitted next belong to the line of the node or token ending at `end`
+    fn after(&mut self, end: usize) {
+        let offset = end.saturating_sub(1);
+        self.line = self.line_starts.partition_point(|start| *start <= offset);
     }
 
-    fn advance(&mut self) {
-        self.previous = self.current;
-
-        loop {
-            self.current = self.scanner.scan_token();
-
-            if self.current.kind != TokenKind::Error {
-                break;
-            }
-        }
-    }
-
-    fn consume(&mut self, kind: TokenKind, message: impl Into<String>) {
-        if self.current.kind == kind {
-            self.advance();
-            return;
-        }
-
-        self.error_at_current(message)
-    }
-
-    fn check(&mut self, kind: TokenKind) -> bool {
-        self.current.kind == kind
-    }
-
-    fn matches(&mut self, kind: TokenKind) -> bool {
-        if !self.check(kind) {
-            return false;
-        }
-
-        self.advance();
-        true
-    }
-
-    fn end(&mut self) -> Result<FunDescriptor, Vec<Error>> {
+    fn end(&mut self) -> FunDescriptor {
         self.emit_return();
+        let level = self.options.optimize;
+        optimizer::optimize(&mut self.state().function, level);
 
-        if cfg!(disassemble) {
+        if self.options.disassemble {
             let mut name = "Entry Point".to_string();
             if !self.state().function.name.is_empty() {
                 name = self.state().function.name.clone();
             }
-            println!("{}", self.state().chunk().disassemble(name).unwrap());
+            let color = self.options.color;
+            let disassembly = self.state().chunk().disassemble(name, color).unwrap();
+            self.disassembly.push_str(&disassembly);
+            self.disassembly.push('\n');
         }
 
-        if !self.state().errors.is_empty() {
-            return Err(std::mem::take(&mut self.state().errors));
-        }
-        Ok(std::mem::take(&mut self.state().function))
+        std::mem::take(&mut self.state().function)
     }
 
     fn begin_scope(&mut self) {
@@ -163,6 +151,10 @@ impl<'a> Compiler<'a> {
             && self.state().locals.last().unwrap().depth > self.state().scope_depth
         {
             let local = self.state().locals.pop().unwrap();
+            if let Some(debug) = local.debug {
+                let end = self.state().chunk().len();
+                self.state().function.locals[debug].end = end;
+            }
 
             if close_upvalues && local.is_captured {
                 self.emit_op(OpCode::CloseUpValue);
@@ -172,52 +164,15 @@ impl<'a> Compiler<'a> {
         }
     }
 
-    fn parse_precedence(&mut self, precendence: Precedence) {
-        self.advance();
-
-        if let Some(prefix) = get_rule(self.previous.kind).prefix {
-            let can_assign = precendence <= Precedence::Assignment;
-            prefix(self, can_assign);
-
-            while precendence <= get_rule(self.current.kind).precedence {
-                self.advance();
-
-                if let Some(infix) = get_rule(self.previous.kind).infix {
-                    infix(self, can_assign);
-                }
-            }
-
-            if can_assign && self.matches(TokenKind::Equal) {
-                self.error("Invalid assignment target.");
-            }
-        } else {
-            self.error_at_current("Expect expression.")
-        }
-    }
-
-    fn identifier_constant(&mut self, name: Token) -> usize {
-        self.make_constant(Value::String(name.lexeme.to_string()))
+    fn identifier_constant(&mut self, name: &str) -> usize {
+        self.make_constant(Value::String(name.to_string()))
     }
 
-    fn identifiers_equal(a: Token, b: Token) -> bool {
-        a.lexeme == b.lexeme
+    fn resolve_local(state: &mut State, name: &str) -> Option<usize> {
+        state.locals.iter().rposition(|local| local.name == name)
     }
 
-    fn resolve_local(state: &mut State, name: Token) -> Option<usize> {
-        for i in (0..state.locals.len()).rev() {
-            let local = state.locals[i].clone();
-            if Self::identifiers_equal(name, local.name) {
-                if local.depth == -1 {
-                    return None;
-                }
-                return Some(i);
-            }
-        }
-
-        None
-    }
-
-    fn add_upvalue(state: &mut State, index: usize, is_local: bool) -> usize {
+    fn add_upvalue(state: &mut State, index: usize, is_local: bool, name: &str) -> usize {
         let upvalue_count = state.function.upvalues.len();
 
         for i in 0..upvalue_count {
@@ -227,14 +182,15 @@ impl<'a> Compiler<'a> {
             }
         }
 
-        state
-            .function
-            .upvalues
-            .push(UpValueDescriptor { index, is_local });
+        state.function.upvalues.push(UpValueDescriptor {
+            index,
+            is_local,
+            name: name.to_string(),
+        });
         upvalue_count
     }
 
-    fn resolve_upvalue(&mut self, state_index: usize, name: Token) -> Option<usize> {
+    fn resolve_upvalue(&mut self, state_index: usize, name: &str) -> Option<usize> {
         if state_index < 1 {
             return None;
         }
@@ -246,54 +202,33 @@ impl<'a> Compiler<'a> {
                 &mut self.states[state_index],
                 index,
                 true,
+                name,
             ));
         } else if let Some(index) = self.resolve_upvalue(enclosing_index, name) {
             return Some(Self::add_upvalue(
                 &mut self.states[state_index],
                 index,
                 false,
+                name,
             ));
         }
 
         None
     }
 
-    fn add_local(&mut self, name: Token<'a>) {
+    fn add_local(&mut self, name: &str) {
         let local = Local::new(name, self.state().scope_depth);
         self.state().locals.push(local);
     }
 
-    fn declare_variable(&mut self) {
-        if self.state().scope_depth == 0 {
-            return;
-        }
-
-        let name = self.previous;
-
-        for i in (0..self.state().locals.len()).rev() {
-            let local = &self.state_ref().locals[i];
-
-            if local.depth != -1 && local.depth < self.state_ref().scope_depth {
-                break;
-            }
-
-            if Self::identifiers_equal(name, local.name) {
-                return self.error("Already a variable with this name in this scope.");
-            }
-        }
-
-        self.add_local(name)
-    }
-
-    fn parse_variable(&mut self, message: impl Into<String>) -> usize {
-        self.consume(TokenKind::Identifier, message);
-
-        self.declare_variable();
+    /// Declares a local, or makes the name constant of a global
+    fn parse_variable(&mut self, name: &Identifier) -> usize {
         if self.state().scope_depth > 0 {
+            self.add_local(&name.name);
             return 0;
         }
 
-        self.identifier_constant(self.previous)
+        self.identifier_constant(&name.name)
     }
 
     fn mark_initialized(&mut self) {
@@ -301,7 +236,19 @@ impl<'a> Compiler<'a> {
             return;
         }
         let index = self.state().locals.len() - 1;
-        self.state().locals[index].depth = self.state().scope_depth;
+
+        // functions are marked before their body and again when defined
+        if self.state().locals[index].debug.is_none() {
+            let start = self.state().chunk().len();
+            let state = self.state();
+            state.locals[index].debug = Some(state.function.locals.len());
+            state.function.locals.push(LocalInfo {
+                name: state.locals[index].name.clone(),
+                slot: index,
+                start,
+                end: usize::MAX,
+            });
+        }
     }
 
     fn define_variable(&mut self, global: usize) {
@@ -313,204 +260,149 @@ impl<'a> Compiler<'a> {
         self.emit_op(OpCode::DefineGlobal { name: global })
     }
 
-    fn argument_list(&mut self) -> usize {
-        let mut arg_count = 0;
-        if !self.check(TokenKind::RightParen) {
-            loop {
-                self.expression();
-                if arg_count == 255 {
-                    self.error("Can't have more than 255 arguments.");
-                }
-                arg_count += 1;
-                if !self.matches(TokenKind::Comma) {
-                    break;
-                }
-            }
-        }
-        self.consume(TokenKind::RightParen, "Expect ')' after arguments.");
-        arg_count
-    }
-
-    fn expression(&mut self) {
-        self.parse_precedence(Precedence::Assignment)
-    }
-
-    fn block(&mut self) {
-        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
-            self.declaration();
+    fn arguments(&mut self, arguments: &[Expr]) -> usize {
+        for argument in arguments {
+            self.expression(argument);
         }
-
-        self.consume(TokenKind::RightBrace, "Expect '}' after block.")
+        arguments.len()
     }
 
-    fn function(&mut self, kind: FunctionKind) {
+    fn function(&mut self, function: &Function, kind: FunctionKind) {
         self.states
-            .push(State::new(self.previous.lexeme.to_string(), kind));
+            .push(State::new(function.name.name.clone(), kind));
         self.begin_scope();
 
-        self.consume(TokenKind::LeftParen, "Expect '(' after function name.");
-
-        if !self.check(TokenKind::RightParen) {
-            loop {
-                self.state().function.arity += 1;
-
-                if self.state().function.arity > 256 {
-                    self.error_at_current("Can't have more than 255 parameters.");
-                }
-
-                let constant = self.parse_variable("Expect parameter name.");
-                self.define_variable(constant);
-
-                if !self.matches(TokenKind::Comma) {
-                    break;
-                }
-            }
+        for param in function.params.iter() {
+            self.state().function.arity += 1;
+            let constant = self.parse_variable(param);
+            self.define_variable(constant);
         }
 
-        self.consume(TokenKind::RightParen, "Expect ')' after parameters.");
-        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.");
-
-        self.block();
+        for statement in function.body.iter() {
+            self.statement(statement);
+        }
 
+        self.after(function.span.end);
         let result = self.end();
 
         self.states.pop();
 
-        match result {
-            Ok(result) => {
-                let func = self.make_constant(Value::Obj(Obj::Fun(Rc::new(result))));
-                self.emit_op(OpCode::Closure { func });
-            }
-            Err(mut e) => {
-                //handle errors from nested functions recursively
-                self.state().errors.append(&mut e);
-            }
-        }
+        let func = self.make_constant(Value::Obj(Obj::Fun(Rc::new(result))));
+        self.emit_op(OpCode::Closure { func });
     }
 
-    fn method(&mut self) {
-        self.consume(TokenKind::Identifier, "Expect method name.");
-        let name = self.identifier_constant(self.previous);
+    fn method(&mut self, method: &Function) {
+        let name = self.identifier_constant(&method.name.name);
 
-        self.function(if self.previous.lexeme == "init" {
-            FunctionKind::Initializer
-        } else {
-            FunctionKind::Method
-        });
+        self.function(
+            method,
+            if method.name.name == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            },
+        );
 
         self.emit_op(OpCode::Method { name });
     }
 
-    fn class_declaration(&mut self) {
-        self.consume(TokenKind::Identifier, "Expect class name.");
-        let class_name = self.previous;
-        let name = self.identifier_constant(self.previous);
-        self.declare_variable();
+    fn class_declaration(&mut self, class: &crate::ast::Class) {
+        let name = self.identifier_constant(&class.name.name);
+        if self.state().scope_depth > 0 {
+            self.add_local(&class.name.name);
+        }
 
+        self.after(class.name.span.end);
         self.emit_op(OpCode::Class { name });
         self.define_variable(name);
 
-        self.classes.push(ClassScope::new());
-
-        if self.matches(TokenKind::Less) {
-            self.consume(TokenKind::Identifier, "Expect superclass name.");
-            variable(self, false);
-            if Self::identifiers_equal(class_name, self.previous) {
-                self.error("A class can't inherit from itself.");
-            }
+        if let Some(superclass) = &class.superclass {
+            self.after(superclass.span.end);
+            self.named_variable(&superclass.name, None, superclass.span.end);
 
             self.begin_scope();
-
-            let token = Token::new(TokenKind::Super, "super", self.current.line);
-
-            self.add_local(token);
+            self.add_local("super");
             self.define_variable(0);
 
-            named_variable(self, class_name, false);
-
+            self.named_variable(&class.name.name, None, superclass.span.end);
             self.emit_op(OpCode::Inerhit);
-            self.class().has_super_class = true;
         }
 
-        named_variable(self, class_name, false);
-        self.consume(TokenKind::LeftBrace, "Expect '{' before class body.");
-        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
-            self.method();
+        let end = class.superclass.as_ref().unwrap_or(&class.name).span.end;
+        self.named_variable(&class.name.name, None, end);
+        for method in class.methods.iter() {
+            self.method(method);
         }
-        self.consume(TokenKind::RightBrace, "Expect '}' after class body.");
+        self.after(class.span.end);
+        self.emit_op(OpCode::Pop);
 
-        if self.class().has_super_class {
+        if class.superclass.is_some() {
             self.end_scope(true);
         }
-
-        self.classes.pop();
     }
 
-    fn fun_declaration(&mut self) {
-        let global = self.parse_variable("Expect function name.");
+    fn fun_declaration(&mut self, function: &Function) {
+        let global = self.parse_variable(&function.name);
         self.mark_initialized();
-        self.function(FunctionKind::Function);
+        self.function(function, FunctionKind::Function);
         self.define_variable(global);
     }
 
-    fn var_declaration(&mut self) {
-        let global = self.parse_variable("Expect variable name.");
+    fn var_declaration(&mut self, name: &Identifier, initializer: Option<&Expr>, span: Span) {
+        let global = self.parse_variable(name);
 
-        if self.matches(TokenKind::Equal) {
-            self.expression();
-        } else {
-            self.emit_op(OpCode::Nil);
+        match initializer {
+            Some(initializer) => self.expression(initializer),
+            None => {
+                self.after(name.span.end);
+                self.emit_op(OpCode::Nil);
+            }
         }
 
-        self.consume(
-            TokenKind::Semicolon,
-            "Expect ';' after variable declaration.",
-        );
-
+        self.after(span.end);
         self.define_variable(global);
     }
 
-    fn expression_statement(&mut self) {
-        self.expression();
-        self.consume(TokenKind::Semicolon, "Expect ';' after expression.");
-        self.emit_op(OpCode::Pop);
-    }
-
-    fn for_statement(&mut self) {
+    fn for_statement(
+        &mut self,
+        initializer: Option<&Stmt>,
+        condition: Option<&Expr>,
+        increment: Option<&Expr>,
+        body: &Stmt,
+        span: Span,
+    ) {
         self.begin_scope();
-        self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.");
 
-        if self.matches(TokenKind::Semicolon) {
-            // no initializer
-        } else if self.matches(TokenKind::Var) {
-            self.var_declaration();
-        } else {
-            self.expression_statement();
+        if let Some(initializer) = initializer {
+            self.statement(initializer);
         }
 
         let mut loop_start = self.state().chunk().len();
 
         //condition
-        let mut exit_jump = 0;
-        let mut condition_exists = false;
-        if !self.matches(TokenKind::Semicolon) {
-            self.expression();
-            self.consume(TokenKind::Semicolon, "Expect ';' after loop condition.");
+        let mut exit_jump = None;
+        if let Some(condition) = condition {
+            self.expression(condition);
+            self.after(condition.span.end);
 
-            exit_jump = self.emit_jump(OpCode::JumpIfFalse { offset: 0 });
-            condition_exists = true;
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse { offset: 0 }));
 
             self.emit_op(OpCode::Pop);
         }
 
         //increment
-        if !self.matches(TokenKind::RightParen) {
+        if let Some(increment) = increment {
+            let clauses_end = condition
+                .map(|condition| condition.span.end)
+                .or(initializer.map(|initializer| initializer.span.end))
+                .unwrap_or(span.start + 1);
+            self.after(clauses_end);
             let body_jump = self.emit_jump(OpCode::Jump { offset: 0 });
             let increment_start = self.state().chunk().len();
 
-            self.expression();
+            self.expression(increment);
+            self.after(increment.span.end);
             self.emit_op(OpCode::Pop);
-            self.consume(TokenKind::RightParen, "Expect ')' after for clauses.");
 
             self.emit_loop(loop_start);
 
@@ -518,7 +410,8 @@ impl<'a> Compiler<'a> {
             self.patch_jump(body_jump, OpCode::Jump { offset: 0 });
         }
 
-        self.statement();
+        self.statement(body);
+        self.after(body.span.end);
 
         let scope_depth = self.state().scope_depth - 1;
         //manually handle closing upvalues
@@ -535,7 +428,7 @@ impl<'a> Compiler<'a> {
         self.emit_loop(loop_start);
 
         //condition
-        if condition_exists {
+        if let Some(exit_jump) = exit_jump {
             self.patch_jump(exit_jump, OpCode::JumpIfFalse { offset: 0 });
             self.emit_op(OpCode::Pop);
         }
@@ -543,131 +436,300 @@ impl<'a> Compiler<'a> {
         self.end_scope(false);
     }
 
-    fn if_statement(&mut self) {
-        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.");
-        self.expression();
-        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+    fn if_statement(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        self.expression(condition);
+        self.after(condition.span.end);
 
         let then_jump = self.emit_jump(OpCode::JumpIfFalse { offset: 0 });
         self.emit_op(OpCode::Pop);
 
-        self.statement();
+        self.statement(then_branch);
+        self.after(then_branch.span.end);
 
         let else_jump = self.emit_jump(OpCode::Jump { offset: 0 });
 
         self.patch_jump(then_jump, OpCode::JumpIfFalse { offset: 0 });
         self.emit_op(OpCode::Pop);
 
-        if self.matches(TokenKind::Else) {
-            self.statement();
+        if let Some(else_branch) = else_branch {
+            self.statement(else_branch);
         }
         self.patch_jump(else_jump, OpCode::Jump { offset: 0 });
     }
 
-    fn print_statement(&mut self) {
-        self.expression();
-        self.consume(TokenKind::Semicolon, "Excpect ';' after value.");
-        self.emit_op(OpCode::Print);
-    }
-
-    fn return_statement(&mut self) {
-        if self.state().kind == FunctionKind::Script {
-            self.error("Can't return from top-level code.");
-        }
-
-        if self.matches(TokenKind::Semicolon) {
-            self.emit_return();
-        } else {
-            if self.state().kind == FunctionKind::Initializer {
-                self.error("Cant't return a value from an initializer.");
-            }
-            self.expression();
-            self.consume(TokenKind::Semicolon, "Expect ';' after return value.");
-            self.emit_op(OpCode::Return);
-        }
-    }
-
-    fn while_statement(&mut self) {
+    fn while_statement(&mut self, condition: &Expr, body: &Stmt) {
         let loop_start = self.state().chunk().len();
-        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.");
 
-        self.expression();
-        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+        self.expression(condition);
+        self.after(condition.span.end);
 
         let exit_jump = self.emit_jump(OpCode::JumpIfFalse { offset: 0 });
         self.emit_op(OpCode::Pop);
-        self.statement();
+        self.statement(body);
+        self.after(body.span.end);
         self.emit_loop(loop_start);
 
         self.patch_jump(exit_jump, OpCode::JumpIfFalse { offset: 0 });
         self.emit_op(OpCode::Pop);
     }
 
-    fn synchronize(&mut self) {
-        self.state().panic_mode = false;
+    fn statement(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Expression(expression) => {
+                self.expression(expression);
+                self.after(statement.span.end);
+                self.emit_op(OpCode::Pop);
+            }
+            StmtKind::Print(value) => {
+                self.expression(value);
+                self.after(statement.span.end);
+                self.emit_op(OpCode::Print);
+            }
+            StmtKind::Var { name, initializer } => {
+                self.var_declaration(name, initializer.as_ref(), statement.span)
+            }
+            StmtKind::Function(function) => self.fun_declaration(function),
+            StmtKind::Class(class) => self.class_declaration(class),
+            StmtKind::Block(statements) => {
+                self.begin_scope();
+                for statement in statements.iter() {
+                    self.statement(statement);
+                }
+                self.after(statement.span.end);
+                self.end_scope(true);
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.if_statement(condition, then_branch, else_branch.as_deref()),
+            StmtKind::While { condition, body } => self.while_statement(condition, body),
+            StmtKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => self.for_statement(
+                initializer.as_deref(),
+                condition.as_ref(),
+                increment.as_ref(),
+                body,
+                statement.span,
+            ),
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => {
+                        self.expression(value);
+                        self.after(statement.span.end);
+                        self.emit_op(OpCode::Return);
+                    }
+                    None => {
+                        self.after(statement.span.end);
+                        self.emit_return();
+                    }
+                };
+            }
+        }
+    }
 
-        while self.current.kind != TokenKind::Eof {
-            if self.previous.kind == TokenKind::Semicolon {
-                self.state();
+    fn expression(&mut self, expression: &Expr) {
+        let end = expression.span.end;
+        match &expression.kind {
+            ExprKind::Number(value) => {
+                self.after(end);
+                self.emit_constant(Value::Number(*value));
+            }
+            ExprKind::String(value) => {
+                self.after(end);
+                self.emit_constant(Value::String(value.clone()));
+            }
+            ExprKind::Bool(value) => {
+                self.after(end);
+                self.emit_op(if *value { OpCode::True } else { OpCode::False });
+            }
+            ExprKind::Nil => {
+                self.after(end);
+                self.emit_op(OpCode::Nil);
+            }
+            ExprKind::List(elements) => {
+                let count = self.arguments(elements);
+                self.after(end);
+                self.emit_op(OpCode::BuildList { count });
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+                self.after(end);
+                self.emit_op(OpCode::BuildMap {
+                    count: entries.len(),
+                });
+            }
+            ExprKind::Variable(name) => self.named_variable(&name.name, None, end),
+            ExprKind::Assign { name, value } => self.named_variable(&name.name, Some(value), end),
+            ExprKind::This => self.named_variable("this", None, end),
+            ExprKind::Super { method } => {
+                let name = self.identifier_constant(&method.name);
+                self.named_variable("this", None, end);
+                self.named_variable("super", None, end);
+                self.emit_op(OpCode::GetSuper { name });
+            }
+            ExprKind::Grouping(expression) => self.expression(expression),
+            ExprKind::Unary { operator, operand } => {
+                self.expression(operand);
+                self.after(end);
+                match operator {
+                    UnaryOp::Not => self.emit_op(OpCode::Not),
+                    UnaryOp::Negate => self.emit_op(OpCode::Negate),
+                }
             }
-            match self.current.kind {
-                TokenKind::Class
-                | TokenKind::Fun
-                | TokenKind::Var
-                | TokenKind::For
-                | TokenKind::If
-                | TokenKind::While
-                | TokenKind::Print
-                | TokenKind::Return => {
-                    return;
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left);
+                self.expression(right);
+                self.after(end);
+                match operator {
+                    BinaryOp::NotEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+                    BinaryOp::Equal => self.emit_op(OpCode::Equal),
+                    BinaryOp::Greater => self.emit_op(OpCode::Greater),
+                    BinaryOp::GreaterEqual => self.emit_ops(OpCode::Less, OpCode::Not),
+                    BinaryOp::Less => self.emit_op(OpCode::Less),
+                    BinaryOp::LessEqual => self.emit_ops(OpCode::Greater, OpCode::Not),
+                    BinaryOp::Add => self.emit_op(OpCode::Add),
+                    BinaryOp::Subtract => self.emit_op(OpCode::Subtract),
+                    BinaryOp::Multiply => self.emit_op(OpCode::Multiply),
+                    BinaryOp::Divide => self.emit_op(OpCode::Divide),
                 }
-                _ => {}
             }
+            ExprKind::Logical {
+                operator: LogicalOp::And,
+                left,
+                right,
+            } => {
+                self.expression(left);
+                self.after(left.span.end);
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse { offset: 0 });
 
-            self.advance();
+                self.emit_op(OpCode::Pop);
+                self.expression(right);
+
+                self.patch_jump(end_jump, OpCode::JumpIfFalse { offset: 0 });
+            }
+            ExprKind::Logical {
+                operator: LogicalOp::Or,
+                left,
+                right,
+            } => {
+                self.expression(left);
+                self.after(left.span.end);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse { offset: 0 });
+                let end_jump = self.emit_jump(OpCode::Jump { offset: 0 });
+
+                self.patch_jump(else_jump, OpCode::JumpIfFalse { offset: 0 });
+                self.emit_op(OpCode::Pop);
+
+                self.expression(right);
+                self.patch_jump(end_jump, OpCode::Jump { offset: 0 });
+            }
+            ExprKind::Call { callee, arguments } => self.call(callee, arguments, end),
+            ExprKind::Get { object, name } => {
+                self.expression(object);
+                let name = self.identifier_constant(&name.name);
+                self.after(end);
+                self.emit_op(OpCode::GetProperty { prop_name: name });
+            }
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object);
+                let name = self.identifier_constant(&name.name);
+                self.expression(value);
+                self.after(end);
+                self.emit_op(OpCode::SetProperty { prop_name: name });
+            }
+            ExprKind::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+                self.after(end);
+                self.emit_op(OpCode::GetIndex);
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+                self.after(end);
+                self.emit_op(OpCode::SetIndex);
+            }
         }
     }
 
-    fn statement(&mut self) {
-        if self.matches(TokenKind::Print) {
-            self.print_statement();
-        } else if self.matches(TokenKind::For) {
-            self.for_statement();
-        } else if self.matches(TokenKind::If) {
-            self.if_statement();
-        } else if self.matches(TokenKind::Return) {
-            self.return_statement();
-        } else if self.matches(TokenKind::While) {
-            self.while_statement();
-        } else if self.matches(TokenKind::LeftBrace) {
-            self.begin_scope();
-            self.block();
-            self.end_scope(true);
-        } else {
-            self.expression_statement()
+    /// Calls of methods are a single instruction, unless there are parentheses around the method
+    fn call(&mut self, callee: &Expr, arguments: &[Expr], end: usize) {
+        match &callee.kind {
+            ExprKind::Get { object, name } => {
+                self.expression(object);
+                let method = self.identifier_constant(&name.name);
+                let arg_count = self.arguments(arguments);
+                self.after(end);
+                self.emit_op(OpCode::Invoke { method, arg_count });
+            }
+            ExprKind::Super { method } => {
+                let method = self.identifier_constant(&method.name);
+                self.named_variable("this", None, callee.span.end);
+                let arg_count = self.arguments(arguments);
+                self.named_variable("super", None, end);
+                self.emit_op(OpCode::SuperInvoke { method, arg_count });
+            }
+            _ => {
+                self.expression(callee);
+                let arg_count = self.arguments(arguments);
+                self.after(end);
+                self.emit_op(OpCode::Call { arg_count });
+            }
         }
     }
 
-    fn declaration(&mut self) {
-        // ignore self.errors on this level
-        // manually synchronized after
-        if self.matches(TokenKind::Class) {
-            self.class_declaration();
-        } else if self.matches(TokenKind::Fun) {
-            self.fun_declaration();
-        } else if self.matches(TokenKind::Var) {
-            self.var_declaration();
+    /// Reads the variable, or sets it to `value`, with the instruction on the line ending at `end`
+    fn named_variable(&mut self, name: &str, value: Option<&Expr>, end: usize) {
+        let (get_op, set_op);
+
+        if let Some(local) = Self::resolve_local(self.state(), name) {
+            get_op = OpCode::GetLocal { local };
+            set_op = OpCode::SetLocal { local };
+        } else if let Some(upvalue) = self.resolve_upvalue(self.states.len() - 1, name) {
+            get_op = OpCode::GetUpValue { upvalue };
+            set_op = OpCode::SetUpValue { upvalue };
         } else {
-            self.statement();
+            let global = self.identifier_constant(name);
+            get_op = OpCode::GetGlobal { name: global };
+            set_op = OpCode::SetGlobal { name: global };
         }
 
-        if self.state().panic_mode {
-            self.synchronize();
+        match value {
+            Some(value) => {
+                self.expression(value);
+                self.after(end);
+                self.emit_op(set_op);
+            }
+            None => {
+                self.after(end);
+                self.emit_op(get_op);
+            }
         }
     }
 
     fn emit_op(&mut self, op: OpCode) {
-        let line = self.previous.line;
+        let line = self.line;
         self.state().chunk().push_op(op, line)
     }
 
@@ -697,9 +759,7 @@ impl<'a> Compiler<'a> {
     }
 
     fn make_constant(&mut self, value: Value) -> usize {
-        let constant = self.state().chunk().push_constant(value);
-
-        constant
+        self.state().chunk().push_constant(value)
     }
 
     fn emit_constant(&mut self, value: Value) {
@@ -724,317 +784,6 @@ impl<'a> Compiler<'a> {
             _ => (),
         }
     }
-
-    fn error_at_current(&mut self, message: impl Into<String>) {
-        self.error_at(self.current, message)
-    }
-
-    fn error(&mut self, message: impl Into<String>) {
-        self.error_at(self.previous, message)
-    }
-
-    fn error_at(&mut self, token: Token, message: impl Into<String>) {
-        if self.state().panic_mode {
-            return;
-        }
-        self.state().panic_mode = true;
-
-        let mut out = String::new();
-        write!(
-            out,
-            "[line {}, {}] Error",
-            token.line,
-            self.state().function
-        )
-        .unwrap();
-
-        if token.kind == TokenKind::Eof {
-            write!(out, " at end").unwrap();
-        } else if token.kind == TokenKind::Error {
-        } else {
-            write!(out, " at '{}'", token.lexeme).unwrap();
-        }
-
-        writeln!(out, ": {}", message.into()).unwrap();
-
-        let err = Error::Compile(out, token.line);
-        self.state().errors.push(err);
-    }
-}
-
-fn get_rule(kind: TokenKind) -> Rule {
-    match kind {
-        TokenKind::LeftParen => Rule::new(Some(&grouping), Some(&call), Precedence::Call),
-        TokenKind::RightParen => Rule::new(None, None, Precedence::None),
-        TokenKind::LeftBrace => Rule::new(None, None, Precedence::None),
-        TokenKind::RightBrace => Rule::new(None, None, Precedence::None),
-        TokenKind::Comma => Rule::new(None, None, Precedence::None),
-        TokenKind::Dot => Rule::new(None, Some(&dot), Precedence::Call),
-        TokenKind::Minus => Rule::new(Some(&unary), Some(&binary), Precedence::Term),
-        TokenKind::Plus => Rule::new(None, Some(&binary), Precedence::Term),
-        TokenKind::Semicolon => Rule::new(None, None, Precedence::None),
-        TokenKind::Slash => Rule::new(None, Some(&binary), Precedence::Factor),
-        TokenKind::Star => Rule::new(None, Some(&binary), Precedence::Factor),
-        TokenKind::Bang => Rule::new(Some(&unary), None, Precedence::None),
-        TokenKind::BangEqual => Rule::new(None, Some(&binary), Precedence::Equality),
-        TokenKind::Equal => Rule::new(None, None, Precedence::None),
-        TokenKind::EqualEqual => Rule::new(None, Some(&binary), Precedence::Equality),
-        TokenKind::Greater => Rule::new(None, Some(&binary), Precedence::Comparison),
-        TokenKind::GreaterEqual => Rule::new(None, Some(&binary), Precedence::Comparison),
-        TokenKind::Less => Rule::new(None, Some(&binary), Precedence::Comparison),
-        TokenKind::LessEqual => Rule::new(None, Some(&binary), Precedence::Comparison),
-        TokenKind::Identifier => Rule::new(Some(&variable), None, Precedence::None),
-        TokenKind::String => Rule::new(Some(&string), None, Precedence::None),
-        TokenKind::Number => Rule::new(Some(&number), None, Precedence::None),
-        TokenKind::And => Rule::new(None, Some(&and), Precedence::And),
-        TokenKind::Class => Rule::new(None, None, Precedence::None),
-        TokenKind::Else => Rule::new(None, None, Precedence::None),
-        TokenKind::False => Rule::new(Some(&literal), None, Precedence::None),
-        TokenKind::For => Rule::new(None, None, Precedence::None),
-        TokenKind::Fun => Rule::new(None, None, Precedence::None),
-        TokenKind::If => Rule::new(None, None, Precedence::None),
-        TokenKind::Nil => Rule::new(Some(&literal), None, Precedence::None),
-        TokenKind::Or => Rule::new(None, Some(&or), Precedence::Or),
-        TokenKind::Print => Rule::new(None, None, Precedence::None),
-        TokenKind::Return => Rule::new(None, None, Precedence::None),
-        TokenKind::Super => Rule::new(Some(&super_), None, Precedence::None),
-        TokenKind::This => Rule::new(Some(&this), None, Precedence::None),
-        TokenKind::True => Rule::new(Some(&literal), None, Precedence::None),
-        TokenKind::Var => Rule::new(None, None, Precedence::None),
-        TokenKind::While => Rule::new(None, None, Precedence::None),
-        TokenKind::Error => Rule::new(None, None, Precedence::None),
-        TokenKind::Eof => Rule::new(None, None, Precedence::None),
-    }
-}
-
-fn grouping(compiler: &mut Compiler, _can_assign: bool) {
-    compiler.expression();
-    compiler.consume(TokenKind::RightParen, "Expect ')' after expression.")
-}
-
-fn binary(compiler: &mut Compiler, _can_assign: bool) {
-    let operator_kind = compiler.previous.kind;
-
-    let compiler_rule = get_rule(operator_kind);
-    compiler.parse_precedence(compiler_rule.precedence.next());
-
-    match operator_kind {
-        TokenKind::BangEqual => compiler.emit_ops(OpCode::Equal, OpCode::Not),
-        TokenKind::EqualEqual => compiler.emit_op(OpCode::Equal),
-        TokenKind::Greater => compiler.emit_op(OpCode::Greater),
-        TokenKind::GreaterEqual => compiler.emit_ops(OpCode::Less, OpCode::Not),
-        TokenKind::Less => compiler.emit_op(OpCode::Less),
-        TokenKind::LessEqual => compiler.emit_ops(OpCode::Greater, OpCode::Not),
-        TokenKind::Plus => compiler.emit_op(OpCode::Add),
-        TokenKind::Minus => compiler.emit_op(OpCode::Subtract),
-        TokenKind::Star => compiler.emit_op(OpCode::Multiply),
-        TokenKind::Slash => compiler.emit_op(OpCode::Divide),
-        _ => {}
-    }
-}
-
-fn number(compiler: &mut Compiler, _can_assign: bool) {
-    let value = compiler.previous.lexeme.parse::<f64>().unwrap();
-    compiler.emit_constant(Value::Number(value))
-}
-
-fn unary(compiler: &mut Compiler, _can_assign: bool) {
-    let operator_kind = compiler.previous.kind;
-
-    compiler.parse_precedence(Precedence::Unary);
-
-    match operator_kind {
-        TokenKind::Bang => compiler.emit_op(OpCode::Not),
-        TokenKind::Minus => compiler.emit_op(OpCode::Negate),
-        _ => {}
-    }
-}
-
-fn literal(compiler: &mut Compiler, _can_assign: bool) {
-    match compiler.previous.kind {
-        TokenKind::False => compiler.emit_op(OpCode::False),
-        TokenKind::Nil => compiler.emit_op(OpCode::Nil),
-        TokenKind::True => compiler.emit_op(OpCode::True),
-        _ => {}
-    }
-}
-
-fn string(compiler: &mut Compiler, _can_assign: bool) {
-    compiler.emit_constant(Value::String(
-        compiler
-            .previous
-            .lexeme
-            .trim_matches('"')
-            .replace("\\n", "\n"),
-    ))
-}
-
-fn variable(compiler: &mut Compiler, can_assign: bool) {
-    named_variable(compiler, compiler.previous, can_assign)
-}
-
-fn named_variable(compiler: &mut Compiler, name: Token, can_assign: bool) {
-    let (get_op, set_op);
-
-    if let Some(local) = Compiler::resolve_local(compiler.state(), name) {
-        get_op = OpCode::GetLocal { local };
-        set_op = OpCode::SetLocal { local };
-    } else if let Some(upvalue) = compiler.resolve_upvalue(compiler.states.len() - 1, name) {
-        get_op = OpCode::GetUpValue { upvalue };
-        set_op = OpCode::SetUpValue { upvalue };
-    } else {
-        let global = compiler.identifier_constant(name);
-        get_op = OpCode::GetGlobal { name: global };
-        set_op = OpCode::SetGlobal { name: global };
-    }
-
-    if can_assign && compiler.matches(TokenKind::Equal) {
-        compiler.expression();
-        compiler.emit_op(set_op);
-    } else {
-        compiler.emit_op(get_op);
-    }
-}
-
-fn and(compiler: &mut Compiler, _can_assign: bool) {
-    let end_jump = compiler.emit_jump(OpCode::JumpIfFalse { offset: 0 });
-
-    compiler.emit_op(OpCode::Pop);
-    compiler.parse_precedence(Precedence::And);
-
-    compiler.patch_jump(end_jump, OpCode::JumpIfFalse { offset: 0 });
-}
-
-fn or(compiler: &mut Compiler, _can_assign: bool) {
-    let else_jump = compiler.emit_jump(OpCode::JumpIfFalse { offset: 0 });
-    let end_jump = compiler.emit_jump(OpCode::Jump { offset: 0 });
-
-    compiler.patch_jump(else_jump, OpCode::JumpIfFalse { offset: 0 });
-    compiler.emit_op(OpCode::Pop);
-
-    compiler.parse_precedence(Precedence::Or);
-    compiler.patch_jump(end_jump, OpCode::Jump { offset: 0 });
-}
-
-fn call(compiler: &mut Compiler, _can_assign: bool) {
-    let arg_count = compiler.argument_list();
-    compiler.emit_op(OpCode::Call { arg_count })
-}
-
-fn dot(compiler: &mut Compiler, can_assign: bool) {
-    compiler.consume(TokenKind::Identifier, "Expect property name after '.'.");
-    let name = compiler.identifier_constant(compiler.previous);
-
-    if can_assign && compiler.matches(TokenKind::Equal) {
-        compiler.expression();
-        compiler.emit_op(OpCode::SetProperty { prop_name: name });
-    } else if compiler.matches(TokenKind::LeftParen) {
-        let arg_count = compiler.argument_list();
-        compiler.emit_op(OpCode::Invoke {
-            method: name,
-            arg_count,
-        });
-    } else {
-        compiler.emit_op(OpCode::GetProperty { prop_name: name });
-    }
-}
-
-fn this(compiler: &mut Compiler, _can_assign: bool) {
-    if compiler.classes.is_empty() {
-        compiler.error("Can't use 'this' outside of a class.");
-        return;
-    }
-    variable(compiler, false);
-}
-
-fn super_(compiler: &mut Compiler, _can_assign: bool) {
-    if compiler.classes.is_empty() {
-        compiler.error("Can't use 'super' outside of a class.");
-    } else if !compiler.class().has_super_class {
-        compiler.error("Can't use 'super' in a class with no superclass.");
-    }
-
-    compiler.consume(TokenKind::Dot, "Expect '.' after 'super'.");
-    compiler.consume(TokenKind::Identifier, "Expect superclass method name.");
-    let name = compiler.identifier_constant(compiler.previous);
-
-    named_variable(
-        compiler,
-        Token::new(TokenKind::This, "this", compiler.previous.line),
-        false,
-    );
-
-    if compiler.matches(TokenKind::LeftParen) {
-        let arg_count = compiler.argument_list();
-        named_variable(
-            compiler,
-            Token::new(TokenKind::Super, "super", compiler.previous.line),
-            false,
-        );
-        compiler.emit_op(OpCode::SuperInvoke {
-            method: name,
-            arg_count,
-        });
-    } else {
-        named_variable(
-            compiler,
-            Token::new(TokenKind::Super, "super", compiler.previous.line),
-            false,
-        );
-        compiler.emit_op(OpCode::GetSuper { name });
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
-enum Precedence {
-    None,
-    Assignment,
-    Or,
-    And,
-    Equality,
-    Comparison,
-    Term,
-    Factor,
-    Unary,
-    Call,
-    Primary,
-}
-
-impl Precedence {
-    pub fn next(&self) -> Self {
-        match self {
-            Self::None => Self::Assignment,
-            Self::Assignment => Self::Or,
-            Self::Or => Self::And,
-            Self::And => Self::Equality,
-            Self::Equality => Self::Comparison,
-            Self::Comparison => Self::Term,
-            Self::Term => Self::Factor,
-            Self::Factor => Self::Unary,
-            Self::Unary => Self::Call,
-            Self::Call => Self::Primary,
-            Self::Primary => Self::Primary,
-        }
-    }
-}
-
-type RuleFn = &'static dyn Fn(&mut Compiler, bool);
-
-#[derive(Clone, Copy)]
-struct Rule {
-    prefix: Option<RuleFn>,
-    infix: Option<RuleFn>,
-    precedence: Precedence,
-}
-
-impl Rule {
-    fn new(prefix: Option<RuleFn>, infix: Option<RuleFn>, precedence: Precedence) -> Rule {
-        Rule {
-            prefix,
-            infix,
-            precedence,
-        }
-    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -1046,31 +795,21 @@ pub enum FunctionKind {
 }
 
 #[derive(Clone)]
-struct Local<'a> {
-    pub name: Token<'a>,
+struct Local {
+    pub name: String,
     pub depth: isize,
     pub is_captured: bool,
+    /// Index of its entry in the function's debug info once initialized
+    pub debug: Option<usize>,
 }
 
-impl<'a> Local<'a> {
-    pub fn new(name: Token<'a>, depth: isize) -> Self {
+impl Local {
+    pub fn new(name: &str, depth: isize) -> Self {
         Self {
-            name,
+            name: name.to_string(),
             depth,
             is_captured: false,
-        }
-    }
-}
-
-#[derive(Clone)]
-struct ClassScope {
-    pub has_super_class: bool,
-}
-
-impl ClassScope {
-    pub fn new() -> Self {
-        Self {
-            has_super_class: false,
+            debug: None,
         }
     }
 }
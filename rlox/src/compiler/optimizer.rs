@@ -0,0 +1,405 @@
+//! Rewrites of compiled bytecode that keep what a script does. Level 1 folds operations
+//! on literals and drops instructions without effect, level 2 also removes unreachable
+//! code and duplicate constants.
+
+use crate::vm::{
+    chunk::Chunk,
+    object::{FunDescriptor, LocalInfo},
+    opcode::OpCode,
+    value::Value,
+};
+
+/// Rewrites the bytecode of `function` at optimization `level`, 0 leaves it as is.
+/// Nested functions aren't touched, they are optimized once they are compiled.
+pub fn optimize(function: &mut FunDescriptor, level: u8) {
+    if level == 0 {
+        return;
+    }
+
+    let chunk = std::mem::take(&mut function.chunk);
+    let mut optimizer = Optimizer::new(chunk, &mut function.locals);
+    loop {
+        let mut changed = optimizer.fold();
+        changed |= optimizer.peephole();
+        if level >= 2 {
+            changed |= optimizer.remove_unreachable();
+        }
+        if !changed {
+            break;
+        }
+    }
+    if level >= 2 {
+        optimizer.deduplicate_constants();
+    }
+    function.chunk = optimizer.finish();
+}
+
+struct Optimizer<'a> {
+    /// Jumps hold the index of the instruction they go to instead of a relative offset
+    code: Vec<OpCode>,
+    constants: Vec<Value>,
+    lines: Vec<usize>,
+    locals: &'a mut [LocalInfo],
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(chunk: Chunk, locals: &'a mut [LocalInfo]) -> Self {
+        let (code, constants, lines) = chunk.into_parts();
+        let code = code
+            .into_iter()
+            .enumerate()
+            .map(|(i, op)| match op {
+                OpCode::Loop { offset } => OpCode::Loop { offset: i - offset },
+                OpCode::Jump { offset } | OpCode::JumpIfFalse { offset } => {
+                    with_target(op, i + offset)
+                }
+                op => op,
+            })
+            .collect();
+        Self {
+            code,
+            constants,
+            lines,
+            locals,
+        }
+    }
+
+    fn finish(self) -> Chunk {
+        let code = self
+            .code
+            .into_iter()
+            .enumerate()
+            .map(|(i, op)| match op {
+                OpCode::Loop { offset } => OpCode::Loop { offset: i - offset },
+                OpCode::Jump { offset } | OpCode::JumpIfFalse { offset } => {
+                    with_target(op, offset - i)
+                }
+                op => op,
+            })
+            .collect();
+        Chunk::from_parts(code, self.constants, self.lines)
+    }
+
+    /// Whether a jump goes to each instruction
+    fn targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.code.len() + 1];
+        for op in self.code.iter() {
+            if let Some(target) = target(*op) {
+                targets[target] = true;
+            }
+        }
+        targets
+    }
+
+    /// Value pushed by the instruction at `index` when it's a literal
+    fn literal(&self, index: usize) -> Option<Value> {
+        match self.code[index] {
+            OpCode::Constant { constant } => match &self.constants[constant] {
+                value @ (Value::Number(_) | Value::String(_)) => Some(value.clone()),
+                _ => None,
+            },
+            OpCode::Nil => Some(Value::Nil),
+            OpCode::True => Some(Value::Bool(true)),
+            OpCode::False => Some(Value::Bool(false)),
+            _ => None,
+        }
+    }
+
+    /// Instruction pushing `value`, reusing an equal constant
+    fn push(&mut self, value: Value) -> OpCode {
+        match value {
+            Value::Nil => OpCode::Nil,
+            Value::Bool(true) => OpCode::True,
+            Value::Bool(false) => OpCode::False,
+            value => {
+                let constant = match self
+                    .constants
+                    .iter()
+                    .position(|constant| same_constant(constant, &value))
+                {
+                    Some(constant) => constant,
+                    None => {
+                        self.constants.push(value);
+                        self.constants.len() - 1
+                    }
+                };
+                OpCode::Constant { constant }
+            }
+        }
+    }
+
+    /// Replaces operations on literals with their result
+    fn fold(&mut self) -> bool {
+        let targets = self.targets();
+        let mut keep = vec![true; self.code.len()];
+        // only the first instruction of a sequence may be jumped to
+        let straight = |i: usize, len: usize| (i + 1..i + len).all(|i| !targets[i]);
+
+        let mut i = 0;
+        while i < self.code.len() {
+            if i + 2 < self.code.len() && straight(i, 3) {
+                if let (Some(a), Some(b)) = (self.literal(i), self.literal(i + 1)) {
+                    if let Some(result) = binary(self.code[i + 2], a, b) {
+                        self.code[i] = self.push(result);
+                        keep[i + 1] = false;
+                        keep[i + 2] = false;
+                        i += 3;
+                        continue;
+                    }
+                }
+            }
+            if i + 1 < self.code.len() && straight(i, 2) {
+                if let Some(a) = self.literal(i) {
+                    if let Some(result) = unary(self.code[i + 1], a) {
+                        self.code[i] = self.push(result);
+                        keep[i + 1] = false;
+                        i += 2;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        self.retain(&keep)
+    }
+
+    /// Drops values popped right away and jumps that go nowhere, and shortens jump chains
+    fn peephole(&mut self) -> bool {
+        let targets = self.targets();
+        let mut keep = vec![true; self.code.len()];
+        let mut changed = false;
+
+        let mut i = 0;
+        while i < self.code.len() {
+            let next = self.code.get(i + 1).filter(|_| !targets[i + 1]).copied();
+            match (self.code[i], next) {
+                (
+                    OpCode::Constant { .. }
+                    | OpCode::Nil
+                    | OpCode::True
+                    | OpCode::False
+                    | OpCode::GetLocal { .. }
+                    | OpCode::GetUpValue { .. },
+                    Some(OpCode::Pop),
+                ) => {
+                    keep[i] = false;
+                    keep[i + 1] = false;
+                    i += 2;
+                    continue;
+                }
+                // the condition is known, the value stays for the `Pop` after the jump
+                (_, Some(OpCode::JumpIfFalse { offset })) if self.literal(i).is_some() => {
+                    if self.literal(i).unwrap().is_falsey() {
+                        self.code[i + 1] = OpCode::Jump { offset };
+                        changed = true;
+                    } else {
+                        keep[i + 1] = false;
+                    }
+                    i += 2;
+                    continue;
+                }
+                (op @ (OpCode::Jump { .. } | OpCode::JumpIfFalse { .. }), _) => {
+                    let mut to = target(op).unwrap();
+                    if to == i + 1 {
+                        keep[i] = false;
+                    } else {
+                        while let Some(next) = self.code.get(to).and_then(|next| match next {
+                            OpCode::Jump { offset } => Some(*offset),
+                            OpCode::JumpIfFalse { offset }
+                                if matches!(op, OpCode::JumpIfFalse { .. }) =>
+                            {
+                                Some(*offset)
+                            }
+                            _ => None,
+                        }) {
+                            if next <= to {
+                                break;
+                            }
+                            to = next;
+                        }
+                        if Some(to) != target(op) {
+                            self.code[i] = with_target(op, to);
+                            changed = true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        self.retain(&keep) || changed
+    }
+
+    /// Removes instructions no path from the start of the function reaches
+    fn remove_unreachable(&mut self) -> bool {
+        let mut keep = vec![false; self.code.len()];
+        let mut pending = vec![0];
+        while let Some(i) = pending.pop() {
+            if i >= self.code.len() || keep[i] {
+                continue;
+            }
+            keep[i] = true;
+
+            let op = self.code[i];
+            pending.extend(target(op));
+            if !matches!(
+                op,
+                OpCode::Jump { .. } | OpCode::Loop { .. } | OpCode::Return
+            ) {
+                pending.push(i + 1);
+            }
+        }
+
+        self.retain(&keep)
+    }
+
+    /// Drops constants no instruction uses and merges the equal ones
+    fn deduplicate_constants(&mut self) {
+        let mut used = vec![false; self.constants.len()];
+        for op in self.code.iter() {
+            map_constant(*op, |constant| {
+                used[constant] = true;
+                constant
+            });
+        }
+
+        let mut constants: Vec<Value> = Vec::new();
+        let mut map = vec![0; self.constants.len()];
+        for (i, constant) in std::mem::take(&mut self.constants).into_iter().enumerate() {
+            if !used[i] {
+                continue;
+            }
+            map[i] = match constants.iter().position(|c| same_constant(c, &constant)) {
+                Some(existing) => existing,
+                None => {
+                    constants.push(constant);
+                    constants.len() - 1
+                }
+            };
+        }
+
+        self.constants = constants;
+        for op in self.code.iter_mut() {
+            *op = map_constant(*op, |constant| map[constant]);
+        }
+    }
+
+    /// Keeps the instructions marked in `keep`, jumps to a removed instruction go to
+    /// the next one kept
+    fn retain(&mut self, keep: &[bool]) -> bool {
+        if keep.iter().all(|keep| *keep) {
+            return false;
+        }
+
+        let mut map = Vec::with_capacity(keep.len() + 1);
+        let mut kept = 0;
+        for keep in keep.iter() {
+            map.push(kept);
+            kept += *keep as usize;
+        }
+        map.push(kept);
+
+        let code = std::mem::take(&mut self.code);
+        let lines = std::mem::take(&mut self.lines);
+        for (i, (op, line)) in code.into_iter().zip(lines).enumerate() {
+            if keep[i] {
+                self.code.push(match target(op) {
+                    Some(target) => with_target(op, map[target]),
+                    None => op,
+                });
+                self.lines.push(line);
+            }
+        }
+
+        let last = map.len() - 1;
+        for local in self.locals.iter_mut() {
+            local.start = map[local.start.min(last)];
+            if local.end != usize::MAX {
+                local.end = map[local.end.min(last)];
+            }
+        }
+        true
+    }
+}
+
+fn target(op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::Jump { offset } | OpCode::JumpIfFalse { offset } | OpCode::Loop { offset } => {
+            Some(offset)
+        }
+        _ => None,
+    }
+}
+
+fn with_target(op: OpCode, offset: usize) -> OpCode {
+    match op {
+        OpCode::Jump { .. } => OpCode::Jump { offset },
+        OpCode::JumpIfFalse { .. } => OpCode::JumpIfFalse { offset },
+        OpCode::Loop { .. } => OpCode::Loop { offset },
+        op => op,
+    }
+}
+
+/// The instruction with its constant operand, if any, replaced by `f` of it
+fn map_constant(op: OpCode, mut f: impl FnMut(usize) -> usize) -> OpCode {
+    match op {
+        OpCode::Constant { constant } => OpCode::Constant {
+            constant: f(constant),
+        },
+        OpCode::GetGlobal { name } => OpCode::GetGlobal { name: f(name) },
+        OpCode::DefineGlobal { name } => OpCode::DefineGlobal { name: f(name) },
+        OpCode::SetGlobal { name } => OpCode::SetGlobal { name: f(name) },
+        OpCode::GetProperty { prop_name } => OpCode::GetProperty {
+            prop_name: f(prop_name),
+        },
+        OpCode::SetProperty { prop_name } => OpCode::SetProperty {
+            prop_name: f(prop_name),
+        },
+        OpCode::GetSuper { name } => OpCode::GetSuper { name: f(name) },
+        OpCode::Invoke { method, arg_count } => OpCode::Invoke {
+            method: f(method),
+            arg_count,
+        },
+        OpCode::SuperInvoke { method, arg_count } => OpCode::SuperInvoke {
+            method: f(method),
+            arg_count,
+        },
+        OpCode::Closure { func } => OpCode::Closure { func: f(func) },
+        OpCode::Class { name } => OpCode::Class { name: f(name) },
+        OpCode::Method { name } => OpCode::Method { name: f(name) },
+        op => op,
+    }
+}
+
+/// Functions are never merged, they are distinct even when they look the same
+fn same_constant(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits(),
+        (Value::String(a), Value::String(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Result of `op` on two literals, none when it would fail at runtime
+fn binary(op: OpCode, a: Value, b: Value) -> Option<Value> {
+    match op {
+        OpCode::Equal => Some(Value::Bool(a == b)),
+        OpCode::Greater => Some(Value::Bool(a > b)),
+        OpCode::Less => Some(Value::Bool(a < b)),
+        OpCode::Add => (a + b).ok(),
+        OpCode::Subtract => (a - b).ok(),
+        OpCode::Multiply => (a * b).ok(),
+        OpCode::Divide => (a / b).ok(),
+        _ => None,
+    }
+}
+
+fn unary(op: OpCode, a: Value) -> Option<Value> {
+    match op {
+        OpCode::Not => (!a).ok(),
+        OpCode::Negate => (-a).ok(),
+        _ => None,
+    }
+}
@@ -3,6 +3,7 @@ pub struct Scanner<'a> {
     start: usize,
     current: usize,
     line: usize,
+    comments: bool,
 }
 
 impl<'a> Scanner<'a> {
@@ -12,9 +13,16 @@ impl<'a> Scanner<'a> {
             start: 0,
             current: 0,
             line: 1,
+            comments: false,
         }
     }
 
+    /// Return `//` comments as [`TokenKind::Comment`] tokens instead of skipping them
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
     pub fn scan_token(&mut self) -> Token<'a> {
         self.skip_whitespace();
         self.start = self.current;
@@ -24,7 +32,13 @@ impl<'a> Scanner<'a> {
         };
         let c = self.advance();
 
-        if c.is_alphabetic() {
+        if self.comments && c == '/' && self.peek() == '/' {
+            while self.peek() != '\n' && !self.is_at_end() {
+                self.advance();
+            }
+            return self.make_token(TokenKind::Comment);
+        }
+        if is_identifier_start(c) {
             return self.identifier();
         }
         if c.is_ascii_digit() {
@@ -36,7 +50,10 @@ impl<'a> Scanner<'a> {
             ')' => return self.make_token(TokenKind::RightParen),
             '{' => return self.make_token(TokenKind::LeftBrace),
             '}' => return self.make_token(TokenKind::RightBrace),
+            '[' => return self.make_token(TokenKind::LeftBracket),
+            ']' => return self.make_token(TokenKind::RightBracket),
             ';' => return self.make_token(TokenKind::Semicolon),
+            ':' => return self.make_token(TokenKind::Colon),
             ',' => return self.make_token(TokenKind::Comma),
             '.' => return self.make_token(TokenKind::Dot),
             '-' => return self.make_token(TokenKind::Minus),
@@ -63,7 +80,7 @@ impl<'a> Scanner<'a> {
             _ => {}
         }
 
-        self.error_token("unexpected character")
+        self.error_token("Unexpected character.")
     }
 
     fn is_at_end(&self) -> bool {
@@ -71,16 +88,18 @@ impl<'a> Scanner<'a> {
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        return self.source.as_bytes()[self.current - 1] as char;
+        let c = self.peek();
+        self.current += c.len_utf8();
+        c
     }
 
+    /// Next char or `'\0'` at the end of the source
     fn peek(&self) -> char {
-        return self.source.as_bytes()[self.current] as char;
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        return self.source.as_bytes()[self.current + 1] as char;
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn lexeme(&self) -> &str {
@@ -97,15 +116,22 @@ impl<'a> Scanner<'a> {
     }
 
     fn make_token(&mut self, kind: TokenKind) -> Token<'a> {
-        Token::new(kind, &self.source[self.start..self.current], self.line)
+        Token {
+            kind,
+            lexeme: &self.source[self.start..self.current],
+            line: self.line,
+            offset: self.start,
+        }
     }
 
-    fn error_token(&mut self, _message: &'a str) -> Token<'a> {
-        Token::new(
-            TokenKind::Error,
-            &self.source[self.start..self.current],
-            self.line,
-        )
+    /// Error tokens carry the message as their lexeme
+    fn error_token(&mut self, message: &'static str) -> Token<'a> {
+        Token {
+            kind: TokenKind::Error,
+            lexeme: message,
+            line: self.line,
+            offset: self.start,
+        }
     }
 
     fn skip_whitespace(&mut self) {
@@ -116,12 +142,11 @@ impl<'a> Scanner<'a> {
                     self.line += 1;
                 }
                 self.advance();
-            } else {
-                if c == '/' && self.peek_next() == '/' {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
+            } else if c == '/' && self.peek_next() == '/' && !self.comments {
+                while self.peek() != '\n' && !self.is_at_end() {
+                    self.advance();
                 }
+            } else {
                 return;
             }
         }
@@ -137,7 +162,7 @@ impl<'a> Scanner<'a> {
         }
 
         if self.is_at_end() {
-            return self.error_token("Unterminated string");
+            return self.error_token("Unterminated string.");
         }
 
         self.advance();
@@ -145,11 +170,11 @@ impl<'a> Scanner<'a> {
     }
 
     fn number(&mut self) -> Token<'a> {
-        while !self.is_at_end() && self.peek().is_ascii_digit() {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if !self.is_at_end() && self.peek() == '.' && self.peek_next().is_ascii_digit() {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
             while self.peek().is_ascii_digit() {
@@ -161,7 +186,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn identifier(&mut self) -> Token<'a> {
-        while self.peek().is_alphabetic() || self.peek().is_ascii_digit() {
+        while !self.is_at_end() && is_identifier_continue(self.peek()) {
             self.advance();
         }
         self.make_token(self.identifier_type())
@@ -191,17 +216,43 @@ impl<'a> Scanner<'a> {
     }
 }
 
+/// Identifiers follow the unicode XID rules, plus a leading underscore
+pub fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+pub fn is_identifier_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
 //copy pasted LULE
 #[derive(Clone, Copy, Debug)]
 pub struct Token<'a> {
     pub kind: TokenKind,
     pub lexeme: &'a str,
     pub line: usize,
+    /// Byte offset of the lexeme in the source
+    pub offset: usize,
 }
 
 impl<'a> Token<'a> {
+    /// Token that doesn't come from the source, like the implicit `this`
     pub fn new(kind: TokenKind, lexeme: &'a str, line: usize) -> Token<'a> {
-        Token { kind, lexeme, line }
+        Token {
+            kind,
+            lexeme,
+            line,
+            offset: 0,
+        }
+    }
+
+    /// Byte range of the token in the source, error tokens are empty
+    pub fn span(&self) -> std::ops::Range<usize> {
+        if self.kind == TokenKind::Error {
+            self.offset..self.offset
+        } else {
+            self.offset..self.offset + self.lexeme.len()
+        }
     }
 }
 
@@ -211,11 +262,14 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
     Plus,
     Semicolon,
+    Colon,
     Slash,
     Star,
     Bang, // One or two character tokens.
@@ -245,6 +299,8 @@ pub enum TokenKind {
     True,
     Var,
     While,
+    /// Only scanned [`Scanner::with_comments`]
+    Comment,
     Error,
     Eof,
 }
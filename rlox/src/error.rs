@@ -3,7 +3,7 @@ use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
 pub enum Error {
-    #[error("Io error")]
+    #[error("Io error, {0}")]
     Io(String),
     #[error("Runtime, {0}. Line {1}")]
     Runtime(String, usize),
@@ -15,5 +15,18 @@ pub enum Error {
     Compile(String, usize),
     #[error("Native, {0}")]
     Native(String),
+    #[error("Instruction budget exhausted. Line {0}")]
+    OutOfFuel(usize),
+    #[error("Heap limit of {0} bytes exceeded. Line {1}")]
+    OutOfMemory(usize, usize),
+    #[error("Exited with status {0}")]
+    Exit(i32),
 }
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Natives report their failures as plain messages
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Native(message)
+    }
+}
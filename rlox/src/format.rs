@@ -0,0 +1,243 @@
+use crate::{
+    compiler::scanner::TokenKind,
+    error::Error,
+    syntax::{self, Element, Node, NodeKind, SyntaxToken, Trivia},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions {
+    /// Spaces per nesting level
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { indent: 4 }
+    }
+}
+
+/// `source` in the canonical style, sources that don't compile aren't touched
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, Vec<Error>> {
+    crate::check(source)?;
+
+    let script = syntax::parse(source);
+    let mut printer = Printer {
+        out: String::new(),
+        options: *options,
+        depth: 0,
+        fresh: true,
+        last: None,
+        unary: false,
+        commented: false,
+    };
+    printer.statements(&script);
+    Ok(printer.out)
+}
+
+/// Tokens that end an operand, a `-` or `(` after them is binary or a call
+fn ends_operand(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Identifier
+            | TokenKind::Number
+            | TokenKind::String
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::Nil
+            | TokenKind::This
+            | TokenKind::RightParen
+            | TokenKind::RightBracket
+            | TokenKind::RightBrace
+    )
+}
+
+struct Printer {
+    out: String,
+    options: FormatOptions,
+    depth: usize,
+    /// Nothing but indentation was written on the current line
+    fresh: bool,
+    last: Option<TokenKind>,
+    /// The last token was a unary operator
+    unary: bool,
+    /// The comments before the next token were written with the blank lines around them
+    commented: bool,
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.fresh = true;
+    }
+
+    fn indent(&mut self, depth: usize) {
+        self.out
+            .extend(std::iter::repeat_n(' ', depth * self.options.indent));
+    }
+
+    /// Statements of the script, a block or a class body, with what comes before the
+    /// token that closes them
+    fn statements(&mut self, node: &Node) {
+        let mut first = true;
+        for child in node.children.iter() {
+            match child {
+                Element::Node(statement) => {
+                    if let Some(token) = statement.first_token() {
+                        if self.comments(token, first) {
+                            self.newline();
+                        }
+                    }
+                    first = false;
+                    self.indent(self.depth);
+                    self.last = None;
+                    self.commented = true;
+                    match statement.kind {
+                        NodeKind::Block => self.body(statement),
+                        _ => self.node(statement),
+                    }
+                    self.newline();
+                }
+                Element::Token(token) if token.kind == TokenKind::Eof => {
+                    self.comments(token, first);
+                }
+                Element::Token(_) => {}
+            }
+        }
+    }
+
+    /// Writes the comments before a statement or closing token on their own lines,
+    /// a comment on the line of the previous token stays there. Whether the source
+    /// has a blank line before the token, which is kept except first in a body
+    fn comments(&mut self, token: &SyntaxToken, mut first: bool) -> bool {
+        let mut newlines = 0;
+        for trivia in token.leading.iter() {
+            match trivia {
+                Trivia::Whitespace(text) => newlines += text.matches('\n').count(),
+                Trivia::Comment(comment) => {
+                    if newlines == 0 && !self.out.is_empty() {
+                        self.out.pop();
+                        self.out.push(' ');
+                    } else {
+                        if newlines >= 2 && !first {
+                            self.newline();
+                        }
+                        self.indent(self.depth);
+                        first = false;
+                    }
+                    self.out.push_str(comment.trim_end());
+                    self.newline();
+                    newlines = 0;
+                }
+            }
+        }
+        newlines >= 2 && !first
+    }
+
+    fn node(&mut self, node: &Node) {
+        let mut after_block = false;
+        for child in node.children.iter() {
+            match child {
+                // after a statement that isn't a block or a comment, the `else` starts a line
+                Element::Token(token)
+                    if token.kind == TokenKind::Else
+                        && (!after_block || token.comments().next().is_some()) =>
+                {
+                    self.newline();
+                    self.comments(token, true);
+                    self.indent(self.depth);
+                    self.last = None;
+                    self.write(token);
+                }
+                Element::Token(token) => self.token(token),
+                Element::Node(body)
+                    if matches!(body.kind, NodeKind::Block | NodeKind::ClassBody) =>
+                {
+                    self.body(body)
+                }
+                // a statement as body of an `if`, `while` or `for` stays on the line
+                Element::Node(node) => self.node(node),
+            }
+            after_block = matches!(child, Element::Node(node) if node.kind == NodeKind::Block);
+        }
+    }
+
+    /// A block or class body, its statements on their own lines
+    fn body(&mut self, body: &Node) {
+        let mut tokens = body.children.iter().filter_map(|child| match child {
+            Element::Token(token) => Some(token),
+            Element::Node(_) => None,
+        });
+        let (Some(open), close) = (tokens.next(), tokens.next()) else {
+            return self.node(body);
+        };
+
+        self.token(open);
+        let empty = body.nodes().next().is_none()
+            && close.is_none_or(|close| close.comments().next().is_none());
+        if !empty {
+            self.newline();
+            self.depth += 1;
+            self.statements(body);
+            if let Some(close) = close {
+                self.comments(close, true);
+            }
+            self.depth -= 1;
+            self.indent(self.depth);
+        }
+        if let Some(close) = close {
+            self.fresh = true;
+            self.last = None;
+            self.write(close);
+        }
+    }
+
+    /// A token inside a statement, comments before it break the line
+    fn token(&mut self, token: &SyntaxToken) {
+        let comments = token
+            .comments()
+            .take(if self.commented { 0 } else { usize::MAX });
+        self.commented = false;
+        for comment in comments {
+            if !self.fresh {
+                self.out.push(' ');
+            } else {
+                self.indent(self.depth + 1);
+            }
+            self.out.push_str(comment.trim_end());
+            self.newline();
+            self.indent(self.depth + 1);
+            self.last = None;
+        }
+        self.write(token);
+    }
+
+    fn write(&mut self, token: &SyntaxToken) {
+        if !self.fresh && self.space_before(token.kind) {
+            self.out.push(' ');
+        }
+        self.unary = matches!(token.kind, TokenKind::Minus | TokenKind::Bang)
+            && !self.last.is_some_and(ends_operand);
+        self.out.push_str(&token.text);
+        self.fresh = false;
+        self.last = Some(token.kind);
+    }
+
+    fn space_before(&self, next: TokenKind) -> bool {
+        use TokenKind::*;
+
+        let Some(last) = self.last else {
+            return false;
+        };
+        if self.unary || matches!(last, LeftParen | LeftBracket | Dot) {
+            return false;
+        }
+        match next {
+            RightParen | RightBracket | Comma | Semicolon | Dot | Colon => false,
+            // calls and indexing stick to what they apply to
+            LeftParen | LeftBracket => !ends_operand(last),
+            // map literals, blocks are written by `body`
+            RightBrace => false,
+            _ => last != LeftBrace,
+        }
+    }
+}
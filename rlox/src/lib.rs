@@ -1,37 +1,80 @@
-use std::path::PathBuf;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
 
+pub mod analysis;
+pub mod ast;
 pub mod compiler;
 pub mod error;
+pub mod format;
+pub mod lint;
 mod rlox_std;
+pub mod syntax;
 pub mod vm;
 
 use compiler::State;
 use error::*;
-use vm::Vm;
+use vm::builder::{Capabilities, VmBuilder};
 
-pub fn run_file(path: PathBuf) -> Result<(), Vec<Error>> {
-    let src = std::fs::read_to_string(path).map_err(|e| vec![Error::Io(e.to_string())])?;
-    run(src.as_str())
+/// Reads a script, the path `-` reads it from stdin
+pub fn read_source(path: &Path) -> Result<String, Vec<Error>> {
+    let result = if path == Path::new("-") {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        std::fs::read_to_string(path)
+    };
+    result.map_err(|e| vec![Error::Io(format!("{}: {}", path.display(), e))])
 }
 
-pub fn run(source: &str) -> Result<(), Vec<Error>> {
-    let mut compiler =
-        compiler::Compiler::new(source, State::new("", compiler::FunctionKind::Script));
-    let mut vm = Vm::new();
-    vm.define_native("clock", rlox_std::Clock::new());
-    vm.execute(compiler.compile()?).map_err(|e| vec![e])?;
+/// Runs the script at `path` in a vm set up by `builder`
+pub fn run_file(path: PathBuf, builder: VmBuilder) -> Result<(), Vec<Error>> {
+    let src = read_source(&path)?;
+    builder.build().interpret(&src)?;
+    Ok(())
+}
 
+/// Compiles `source` without running it, returning every compile error
+pub fn check(source: &str) -> Result<(), Vec<Error>> {
+    let (_, errors) = ast::parser::parse(source);
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(|error| error.error).collect());
+    }
     Ok(())
 }
 
-pub fn disassemble(source: &str) -> Result<String, Vec<Error>> {
+pub fn run(source: &str) -> Result<(), Vec<Error>> {
+    let mut vm = VmBuilder::new().capabilities(Capabilities::all()).build();
+    vm.interpret(source)?;
+    Ok(())
+}
+
+/// Process exit status for a failed run, following clox and sysexits.h
+pub fn exit_code(errors: &[Error]) -> i32 {
+    errors.first().map_or(0, |error| match error {
+        Error::Exit(code) => *code,
+        Error::Compile(..) => 65,
+        Error::Io(..) => 74,
+        _ => 70,
+    })
+}
+
+/// Bytecode of the script and every function in it, `color` adds terminal colors and
+/// `optimize` is the optimization level
+pub fn disassemble(source: &str, color: bool, optimize: u8) -> Result<String, Vec<Error>> {
     let mut compiler =
-        compiler::Compiler::new(source, State::new("", compiler::FunctionKind::Script));
-    let mut vm = Vm::new();
-    vm.define_native("clock", rlox_std::Clock::new());
-    //TODO: graceful error
-    let result = compiler.compile()?.chunk.disassemble("").unwrap();
-    Ok(result)
+        compiler::Compiler::new(source, State::new("", compiler::FunctionKind::Script))
+            .with_options(compiler::CompilerOptions {
+                optimize,
+                ..Default::default()
+            });
+    let function = compiler.compile()?;
+
+    let mut out = String::new();
+    vm::chunk::disassemble_function(&mut out, &function, color)
+        .map_err(|e| vec![Error::Io(e.to_string())])?;
+    Ok(out)
 }
 
 #[cfg(test)]
@@ -0,0 +1,677 @@
+//! Warnings about code that compiles but likely doesn't do what was meant.
+//! A `// allow(W001, shadowed-variable)` comment silences lints on its own line,
+//! or on the line after it when the comment stands alone.
+
+use std::{fmt, ops::Range};
+
+use crate::{
+    analysis::{Analysis, SymbolKind},
+    ast::{parser, BinaryOp, Class, Expr, ExprKind, Identifier, Script, Stmt, StmtKind},
+    compiler::scanner::{Scanner, TokenKind},
+    error::Error,
+    vm::builder::VmBuilder,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintCode {
+    UnusedVariable,
+    UnusedParameter,
+    UnreachableCode,
+    ShadowedVariable,
+    UndeclaredGlobal,
+    /// Misuse of `this`: a property read that is never assigned or an assignment
+    /// over a method. `this` in a function nested in a method is the method's
+    /// instance, and `this` outside a class doesn't compile, so neither is linted
+    ThisProperty,
+    WrongArity,
+    SuspiciousComparison,
+}
+
+impl LintCode {
+    pub const ALL: [LintCode; 8] = [
+        LintCode::UnusedVariable,
+        LintCode::UnusedParameter,
+        LintCode::UnreachableCode,
+        LintCode::ShadowedVariable,
+        LintCode::UndeclaredGlobal,
+        LintCode::ThisProperty,
+        LintCode::WrongArity,
+        LintCode::SuspiciousComparison,
+    ];
+
+    /// Stable identifier, a code is never reused for another lint
+    pub fn code(self) -> &'static str {
+        match self {
+            LintCode::UnusedVariable => "W001",
+            LintCode::UnusedParameter => "W002",
+            LintCode::UnreachableCode => "W003",
+            LintCode::ShadowedVariable => "W004",
+            LintCode::UndeclaredGlobal => "W005",
+            LintCode::ThisProperty => "W006",
+            LintCode::WrongArity => "W007",
+            LintCode::SuspiciousComparison => "W008",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LintCode::UnusedVariable => "unused-variable",
+            LintCode::UnusedParameter => "unused-parameter",
+            LintCode::UnreachableCode => "unreachable-code",
+            LintCode::ShadowedVariable => "shadowed-variable",
+            LintCode::UndeclaredGlobal => "undeclared-global",
+            LintCode::ThisProperty => "this-property",
+            LintCode::WrongArity => "wrong-arity",
+            LintCode::SuspiciousComparison => "suspicious-comparison",
+        }
+    }
+
+    /// The lint with `text` as code or name
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|lint| lint.code() == text || lint.name() == text)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub code: LintCode,
+    pub message: String,
+    /// Byte range of the code it's about
+    pub span: Range<usize>,
+    pub line: usize,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Warning {} ({}): {}",
+            self.line,
+            self.code.code(),
+            self.code.name(),
+            self.message
+        )
+    }
+}
+
+/// Warnings for `source` in source order, sources that don't compile only get their errors
+pub fn lint(source: &str) -> Result<Vec<Warning>, Vec<Error>> {
+    crate::check(source)?;
+
+    let linter = Linter::new(source);
+    let mut warnings = Vec::new();
+    linter.unused(&mut warnings);
+    linter.shadowed(&mut warnings);
+    linter.statements(&linter.script.statements, &mut warnings);
+
+    let allowed = linter.allowed();
+    warnings.retain(|warning| {
+        !allowed
+            .iter()
+            .any(|(line, code)| *line == warning.line && *code == warning.code)
+    });
+    warnings.sort_by_key(|warning| warning.span.start);
+    Ok(warnings)
+}
+
+fn is_comparison(operator: BinaryOp) -> bool {
+    is_ordering(operator) || matches!(operator, BinaryOp::Equal | BinaryOp::NotEqual)
+}
+
+fn is_ordering(operator: BinaryOp) -> bool {
+    matches!(
+        operator,
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual
+    )
+}
+
+/// Accesses of properties on `this` in `statements`, without the ones of classes
+/// declared in them, with whether they assign the property
+fn this_properties<'a>(statements: &'a [Stmt], found: &mut Vec<(&'a Identifier, bool)>) {
+    for statement in statements {
+        match &statement.kind {
+            StmtKind::Class(_) => {}
+            StmtKind::Function(function) => this_properties(&function.body, found),
+            _ => each_child(statement, &mut |child| match child {
+                Child::Stmts(statements) => this_properties(statements, found),
+                Child::Expr(expr) => this_properties_in(expr, found),
+            }),
+        }
+    }
+}
+
+fn this_properties_in<'a>(expr: &'a Expr, found: &mut Vec<(&'a Identifier, bool)>) {
+    match &expr.kind {
+        ExprKind::Get { object, name } if object.kind == ExprKind::This => {
+            found.push((name, false))
+        }
+        ExprKind::Set { object, name, .. } if object.kind == ExprKind::This => {
+            found.push((name, true))
+        }
+        _ => {}
+    }
+    each_operand(expr, &mut |operand| this_properties_in(operand, found));
+}
+
+/// Statements or an expression directly in a statement, a block gives all of its
+/// statements at once
+enum Child<'a> {
+    Stmts(&'a [Stmt]),
+    Expr(&'a Expr),
+}
+
+/// Calls `f` with the statements and expressions of `statement`, the bodies of
+/// functions and classes are left to the caller
+fn each_child<'a>(statement: &'a Stmt, f: &mut impl FnMut(Child<'a>)) {
+    match &statement.kind {
+        StmtKind::Expression(expr) | StmtKind::Print(expr) => f(Child::Expr(expr)),
+        StmtKind::Var { initializer, .. } | StmtKind::Return(initializer) => {
+            if let Some(initializer) = initializer {
+                f(Child::Expr(initializer));
+            }
+        }
+        StmtKind::Function(_) | StmtKind::Class(_) => {}
+        StmtKind::Block(statements) => f(Child::Stmts(statements)),
+        StmtKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            f(Child::Expr(condition));
+            f(Child::Stmts(std::slice::from_ref(then_branch)));
+            if let Some(else_branch) = else_branch {
+                f(Child::Stmts(std::slice::from_ref(else_branch)));
+            }
+        }
+        StmtKind::While { condition, body } => {
+            f(Child::Expr(condition));
+            f(Child::Stmts(std::slice::from_ref(body)));
+        }
+        StmtKind::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        } => {
+            if let Some(initializer) = initializer {
+                f(Child::Stmts(std::slice::from_ref(initializer)));
+            }
+            for expr in condition.iter().chain(increment.iter()) {
+                f(Child::Expr(expr));
+            }
+            f(Child::Stmts(std::slice::from_ref(body)));
+        }
+    }
+}
+
+/// Calls `f` with the expressions directly in `expr`
+fn each_operand<'a>(expr: &'a Expr, f: &mut impl FnMut(&'a Expr)) {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::String(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Nil
+        | ExprKind::Variable(_)
+        | ExprKind::This
+        | ExprKind::Super { .. } => {}
+        ExprKind::List(elements) => elements.iter().for_each(f),
+        ExprKind::Map(entries) => {
+            for (key, value) in entries {
+                f(key);
+                f(value);
+            }
+        }
+        ExprKind::Assign { value: operand, .. }
+        | ExprKind::Grouping(operand)
+        | ExprKind::Unary { operand, .. }
+        | ExprKind::Get {
+            object: operand, ..
+        } => f(operand),
+        ExprKind::Binary { left, right, .. }
+        | ExprKind::Logical { left, right, .. }
+        | ExprKind::Set {
+            object: left,
+            value: right,
+            ..
+        }
+        | ExprKind::Index {
+            object: left,
+            index: right,
+        } => {
+            f(left);
+            f(right);
+        }
+        ExprKind::Call { callee, arguments } => {
+            f(callee);
+            arguments.iter().for_each(f);
+        }
+        ExprKind::SetIndex {
+            object,
+            index,
+            value,
+        } => {
+            f(object);
+            f(index);
+            f(value);
+        }
+    }
+}
+
+struct Linter<'a> {
+    source: &'a str,
+    script: Script,
+    analysis: Analysis,
+    /// Globals of the standard library
+    builtins: Vec<String>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(source: &'a str) -> Self {
+        let (script, _) = parser::parse(source);
+        let builtins = VmBuilder::new()
+            .input(std::io::empty())
+            .build()
+            .globals_iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        Self {
+            source,
+            analysis: Analysis::of(source, &script),
+            script,
+            builtins,
+        }
+    }
+
+    fn warning(&self, code: LintCode, span: Range<usize>, message: String) -> Warning {
+        Warning {
+            code,
+            message,
+            line: self.line(span.start),
+            span,
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.source[..offset].matches('\n').count() + 1
+    }
+
+    /// Byte range of the first token from `offset` on that is `kind`,
+    /// for the operators the tree doesn't keep
+    fn token_from(&self, offset: usize, kind: TokenKind) -> Range<usize> {
+        let mut scanner = Scanner::new(&self.source[offset..]);
+        loop {
+            let token = scanner.scan_token();
+            if token.kind == kind || token.kind == TokenKind::Eof {
+                let span = token.span();
+                return offset + span.start..offset + span.end;
+            }
+        }
+    }
+
+    /// Locals and parameters that are never read, names starting with `_` are meant to be
+    fn unused(&self, warnings: &mut Vec<Warning>) {
+        for (index, symbol) in self.analysis.symbols.iter().enumerate() {
+            if symbol.is_global()
+                || symbol.kind == SymbolKind::Method
+                || symbol.name.starts_with('_')
+                || self
+                    .analysis
+                    .references
+                    .iter()
+                    .any(|reference| reference.symbol == index && !reference.assigned)
+            {
+                continue;
+            }
+            let (code, what) = match symbol.kind {
+                SymbolKind::Parameter => (LintCode::UnusedParameter, "parameter"),
+                SymbolKind::Function => (LintCode::UnusedVariable, "local function"),
+                SymbolKind::Class => (LintCode::UnusedVariable, "local class"),
+                _ => (LintCode::UnusedVariable, "local variable"),
+            };
+            warnings.push(self.warning(
+                code,
+                symbol.span.clone(),
+                format!("Unused {} '{}'.", what, symbol.name),
+            ));
+        }
+    }
+
+    /// Locals hiding a name declared before them in an enclosing scope
+    fn shadowed(&self, warnings: &mut Vec<Warning>) {
+        let symbols = &self.analysis.symbols;
+        for (index, symbol) in symbols.iter().enumerate() {
+            if symbol.is_global() || symbol.kind == SymbolKind::Method {
+                continue;
+            }
+            let shadowed = symbols.iter().enumerate().find(|(other_index, other)| {
+                *other_index != index
+                    && other.kind != SymbolKind::Method
+                    && other.name == symbol.name
+                    && other.scope.contains(&symbol.span.start)
+                    && other.span.start < symbol.span.start
+            });
+            if let Some((_, other)) = shadowed {
+                warnings.push(self.warning(
+                    LintCode::ShadowedVariable,
+                    symbol.span.clone(),
+                    format!(
+                        "'{}' shadows the declaration on line {}.",
+                        symbol.name,
+                        self.line(other.span.start)
+                    ),
+                ));
+            }
+        }
+    }
+
+    /// The lints of statements and the expressions in them
+    fn statements(&self, statements: &[Stmt], warnings: &mut Vec<Warning>) {
+        self.unreachable(statements, warnings);
+        for statement in statements {
+            match &statement.kind {
+                StmtKind::Function(function) => self.statements(&function.body, warnings),
+                StmtKind::Class(class) => {
+                    self.this_properties(class, warnings);
+                    for method in class.methods.iter() {
+                        self.statements(&method.body, warnings);
+                    }
+                }
+                StmtKind::If { condition, .. } | StmtKind::While { condition, .. } => {
+                    self.condition(condition, warnings)
+                }
+                _ => {}
+            }
+            each_child(statement, &mut |child| match child {
+                Child::Stmts(statements) => self.statements(statements, warnings),
+                Child::Expr(expr) => self.expression(expr, warnings),
+            });
+        }
+    }
+
+    /// The first statement after a `return` in a block or function body
+    fn unreachable(&self, statements: &[Stmt], warnings: &mut Vec<Warning>) {
+        let mut statements = statements
+            .iter()
+            .skip_while(|statement| !matches!(statement.kind, StmtKind::Return(_)));
+        if let (Some(_), Some(statement)) = (statements.next(), statements.next()) {
+            warnings.push(self.warning(
+                LintCode::UnreachableCode,
+                statement.span.range(),
+                "Unreachable code after 'return'.".to_string(),
+            ));
+        }
+    }
+
+    fn expression(&self, expr: &Expr, warnings: &mut Vec<Warning>) {
+        match &expr.kind {
+            ExprKind::Assign { name, .. } => self.undeclared(name, warnings),
+            ExprKind::Call { callee, arguments } => {
+                if let ExprKind::Variable(name) = &callee.kind {
+                    self.arity(name, arguments.len(), warnings);
+                }
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } if is_comparison(*operator) => self.comparison(*operator, left, right, warnings),
+            _ => {}
+        }
+        each_operand(expr, &mut |operand| self.expression(operand, warnings));
+    }
+
+    /// An assignment to a name declared nowhere, which fails at runtime
+    fn undeclared(&self, name: &Identifier, warnings: &mut Vec<Warning>) {
+        let span = name.span.range();
+        let declared = self
+            .analysis
+            .references
+            .iter()
+            .any(|reference| reference.span == span)
+            || self.builtins.contains(&name.name);
+        if !declared {
+            warnings.push(self.warning(
+                LintCode::UndeclaredGlobal,
+                span,
+                format!("Assignment to undeclared variable '{}'.", name.name),
+            ));
+        }
+    }
+
+    /// `this.x` read in a class that never sets `x`, or set over a method of the class
+    fn this_properties(&self, class: &Class, warnings: &mut Vec<Warning>) {
+        // inherited methods and fields aren't known
+        let inherits = class.superclass.is_some();
+        let is_method = |property: &str| class.methods.iter().any(|m| m.name.name == property);
+
+        let mut properties = Vec::new();
+        for method in class.methods.iter() {
+            this_properties(&method.body, &mut properties);
+        }
+        for (property, assigned) in properties {
+            let name = &property.name;
+            if assigned && is_method(name) {
+                warnings.push(self.warning(
+                    LintCode::ThisProperty,
+                    property.span.range(),
+                    format!("Assigning 'this.{}' hides the method '{}'.", name, name),
+                ));
+            } else if !assigned && !inherits && !is_method(name) && !self.is_field(name) {
+                warnings.push(self.warning(
+                    LintCode::ThisProperty,
+                    property.span.range(),
+                    format!(
+                        "'this.{}' is never assigned in class '{}'.",
+                        name, class.name.name
+                    ),
+                ));
+            }
+        }
+    }
+
+    /// Whether a field called `name` is assigned on anything in the script
+    fn is_field(&self, name: &str) -> bool {
+        fn assigns(expr: &Expr, name: &str) -> bool {
+            let mut found =
+                matches!(&expr.kind, ExprKind::Set { name: set, .. } if set.name == name);
+            each_operand(expr, &mut |operand| found |= assigns(operand, name));
+            found
+        }
+        fn assigns_in(statements: &[Stmt], name: &str) -> bool {
+            statements.iter().any(|statement| {
+                let mut found = match &statement.kind {
+                    StmtKind::Function(function) => assigns_in(&function.body, name),
+                    StmtKind::Class(class) => class
+                        .methods
+                        .iter()
+                        .any(|method| assigns_in(&method.body, name)),
+                    _ => false,
+                };
+                each_child(statement, &mut |child| match child {
+                    Child::Stmts(statements) => found |= assigns_in(statements, name),
+                    Child::Expr(expr) => found |= assigns(expr, name),
+                });
+                found
+            })
+        }
+        assigns_in(&self.script.statements, name)
+    }
+
+    /// A call of a function or class, that is never reassigned, with a wrong argument count
+    fn arity(&self, callee: &Identifier, arguments: usize, warnings: &mut Vec<Warning>) {
+        let symbols = &self.analysis.symbols;
+        let span = callee.span.range();
+        let Some(reference) = self
+            .analysis
+            .references
+            .iter()
+            .find(|reference| reference.span == span)
+        else {
+            return;
+        };
+        let symbol = &symbols[reference.symbol];
+        let redeclared = symbol.is_global()
+            && symbols
+                .iter()
+                .filter(|other| other.is_global() && other.name == symbol.name)
+                .count()
+                > 1;
+        let reassigned = self
+            .analysis
+            .references
+            .iter()
+            .any(|other| other.symbol == reference.symbol && other.assigned);
+        if redeclared || reassigned {
+            return;
+        }
+
+        let expected = match symbol.kind {
+            SymbolKind::Function => symbol.params.len(),
+            SymbolKind::Class => {
+                let init = self
+                    .analysis
+                    .methods_of(reference.symbol)
+                    .find(|(_, method)| method.name == "init");
+                match init {
+                    Some((_, init)) => init.params.len(),
+                    None if symbol.superclass.is_some() => return,
+                    None => 0,
+                }
+            }
+            _ => return,
+        };
+        if arguments != expected {
+            warnings.push(self.warning(
+                LintCode::WrongArity,
+                span,
+                format!(
+                    "'{}' expects {} arguments but got {}.",
+                    callee.name, expected, arguments
+                ),
+            ));
+        }
+    }
+
+    /// An assignment used as condition, extra parentheses say it's meant
+    fn condition(&self, condition: &Expr, warnings: &mut Vec<Warning>) {
+        let assigned = match &condition.kind {
+            ExprKind::Assign { name, .. } | ExprKind::Set { name, .. } => name.span.end,
+            ExprKind::SetIndex { index, .. } => index.span.end,
+            _ => return,
+        };
+        warnings.push(self.warning(
+            LintCode::SuspiciousComparison,
+            self.token_from(assigned, TokenKind::Equal),
+            "Assignment used as a condition, did you mean '=='?".to_string(),
+        ));
+    }
+
+    /// Comparisons that are constant, fail at runtime or compare a boolean by accident
+    fn comparison(
+        &self,
+        operator: BinaryOp,
+        left: &Expr,
+        right: &Expr,
+        warnings: &mut Vec<Warning>,
+    ) {
+        let span = self.token_from(left.span.end, operator_token(operator));
+        let text = &self.source[span.clone()];
+
+        if let ExprKind::Binary {
+            operator: before, ..
+        } = left.kind
+        {
+            if is_comparison(before) && is_ordering(before) == is_ordering(operator) {
+                warnings.push(self.warning(
+                    LintCode::SuspiciousComparison,
+                    span.clone(),
+                    format!(
+                        "'{}' compares the boolean result of the comparison before it.",
+                        text
+                    ),
+                ));
+            }
+        }
+
+        if let (ExprKind::Variable(left), ExprKind::Variable(right)) = (&left.kind, &right.kind) {
+            if left.name == right.name {
+                warnings.push(self.warning(
+                    LintCode::SuspiciousComparison,
+                    span.clone(),
+                    format!("Comparing '{}' with itself.", left.name),
+                ));
+            }
+        }
+
+        let literal = [left, right]
+            .into_iter()
+            .find_map(|operand| match operand.kind {
+                ExprKind::String(_) => Some("a string"),
+                ExprKind::Nil => Some("nil"),
+                ExprKind::Bool(_) => Some("a boolean"),
+                _ => None,
+            });
+        if let (true, Some(literal)) = (is_ordering(operator), literal) {
+            warnings.push(self.warning(
+                LintCode::SuspiciousComparison,
+                span,
+                format!("'{}' only compares numbers, not {}.", text, literal),
+            ));
+        }
+    }
+
+    /// Lints silenced by comments, with the line they are silenced on
+    fn allowed(&self) -> Vec<(usize, LintCode)> {
+        let mut allowed = Vec::new();
+        let mut scanner = Scanner::new(self.source);
+        let mut end = 0;
+        loop {
+            let token = scanner.scan_token();
+            // only whitespace and comments are between tokens
+            let mut offset = end;
+            // a comment after code on its line is about that line, otherwise about the next one
+            let mut alone = end == 0;
+            for text in self.source[end..token.offset].split_inclusive('\n') {
+                let comment = text.trim();
+                if let Some(comment) = comment.strip_prefix("//") {
+                    let line = self.line(if alone { token.offset } else { offset });
+                    let codes = comment
+                        .trim_start_matches('/')
+                        .trim()
+                        .strip_prefix("allow(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                        .into_iter()
+                        .flat_map(|codes| codes.split(','))
+                        .filter_map(|code| LintCode::parse(code.trim()));
+                    allowed.extend(codes.map(|code| (line, code)));
+                }
+                alone |= text.ends_with('\n');
+                offset += text.len();
+            }
+
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            end = token.span().end;
+        }
+        allowed
+    }
+}
+
+fn operator_token(operator: BinaryOp) -> TokenKind {
+    match operator {
+        BinaryOp::Equal => TokenKind::EqualEqual,
+        BinaryOp::NotEqual => TokenKind::BangEqual,
+        BinaryOp::Greater => TokenKind::Greater,
+        BinaryOp::GreaterEqual => TokenKind::GreaterEqual,
+        BinaryOp::Less => TokenKind::Less,
+        BinaryOp::LessEqual => TokenKind::LessEqual,
+        BinaryOp::Add => TokenKind::Plus,
+        BinaryOp::Subtract => TokenKind::Minus,
+        BinaryOp::Multiply => TokenKind::Star,
+        BinaryOp::Divide => TokenKind::Slash,
+    }
+}
@@ -1,15 +1,144 @@
-use std::time::Instant;
+use std::{collections::HashMap, io::BufRead, time::Instant};
 
 use crate::{
-    vm::{object::NativeFun, value::Value},
+    error::Error,
+    vm::{builder::Capabilities, object::NativeFun, value::Value, Vm},
 };
 
+mod format;
+mod fs;
+mod input;
+mod json;
+pub mod list;
+pub mod map;
+mod math;
+mod random;
+mod reflect;
+pub mod string;
+mod system;
+mod types;
+
+/// Defines the standard library natives, the ones that need
+/// a capability that wasn't granted error when called instead
+pub fn register(
+    vm: &mut Vm,
+    capabilities: &Capabilities,
+    args: Vec<String>,
+    env: Option<HashMap<String, String>>,
+    input: Box<dyn BufRead>,
+) {
+    if capabilities.clock {
+        vm.define_native("clock", Clock::new());
+    } else {
+        vm.define_native("clock", Denied::new("clock"));
+    }
+
+    vm.define_module(math::module());
+    vm.define_module(json::module());
+    vm.define_module(random::module(capabilities.random));
+    fs::register(vm, capabilities);
+    system::register(vm, capabilities, args, env);
+    input::register(vm, capabilities, input);
+    format::register(vm);
+    types::register(vm);
+    reflect::register(vm);
+}
+
+pub fn expect_arity(name: &str, args: &[Value], arity: usize) -> Result<(), String> {
+    if args.len() != arity {
+        return Err(format!(
+            "{}() expected {} arguments but got {}.",
+            name,
+            arity,
+            args.len()
+        ));
+    }
+    Ok(())
+}
+
+pub fn number_arg(name: &str, args: &[Value], index: usize) -> Result<f64, String> {
+    match &args[index] {
+        Value::Number(n) => Ok(*n),
+        value => Err(format!(
+            "{}() expected a number as argument {} but got {}.",
+            name,
+            index + 1,
+            value.type_name()
+        )),
+    }
+}
+
+pub fn string_arg<'a>(name: &str, args: &'a [Value], index: usize) -> Result<&'a str, String> {
+    match &args[index] {
+        Value::String(s) => Ok(s),
+        value => Err(format!(
+            "{}() expected a string as argument {} but got {}.",
+            name,
+            index + 1,
+            value.type_name()
+        )),
+    }
+}
+
+pub fn integer_arg(name: &str, args: &[Value], index: usize) -> Result<usize, String> {
+    let n = number_arg(name, args, index)?;
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(format!(
+            "{}() expected a non-negative integer as argument {} but got {}.",
+            name,
+            index + 1,
+            n
+        ));
+    }
+    Ok(n as usize)
+}
+
+/// Checks that `index` is a whole number within `0..len`
+pub fn index(index: &Value, len: usize) -> Result<usize, String> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < len => Ok(*n as usize),
+        Value::Number(n) => Err(format!("Index {} out of range for length {}.", n, len)),
+        value => Err(format!(
+            "Index must be a number, got {}.",
+            value.type_name()
+        )),
+    }
+}
+
+/// Stateless native backed by a plain function
+pub struct Function {
+    name: &'static str,
+    arity: usize,
+    function: fn(&[Value]) -> Result<Value, String>,
+}
+
+impl Function {
+    pub fn new(
+        name: &'static str,
+        arity: usize,
+        function: fn(&[Value]) -> Result<Value, String>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            name,
+            arity,
+            function,
+        })
+    }
+}
+
+impl NativeFun for Function {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        expect_arity(self.name, args, self.arity)?;
+        Ok((self.function)(args)?)
+    }
+}
+
 pub struct Clock {
     now: Instant,
 }
 
 impl NativeFun for Clock {
-    fn call(&self, _args: &[Value]) -> std::result::Result<Value, String> {
+    fn call(&self, _args: &[Value]) -> crate::error::Result<Value> {
         Ok(Value::Number(self.now.elapsed().as_secs_f64()))
     }
 }
@@ -21,3 +150,23 @@ impl Clock {
         })
     }
 }
+
+/// Stands in for a native whose capability wasn't granted
+pub struct Denied {
+    capability: &'static str,
+}
+
+impl NativeFun for Denied {
+    fn call(&self, _args: &[Value]) -> crate::error::Result<Value> {
+        Err(Error::Native(format!(
+            "Capability '{}' was not granted",
+            self.capability
+        )))
+    }
+}
+
+impl Denied {
+    pub fn new(capability: &'static str) -> Box<Self> {
+        Box::new(Self { capability })
+    }
+}
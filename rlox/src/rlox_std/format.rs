@@ -0,0 +1,290 @@
+use std::{iter::Peekable, str::Chars};
+
+use super::{integer_arg, number_arg, string_arg, Function};
+use crate::{
+    error::Result,
+    vm::{object::NativeFun, value::Value, Vm},
+};
+
+/// JavaScript's limit for `toFixed`, also keeps scripts from asking for huge strings
+const MAX_DIGITS: usize = 100;
+/// Widest padding of a placeholder, so a template can't ask for a huge string either
+const MAX_WIDTH: usize = 1000;
+
+pub fn register(vm: &mut Vm) {
+    vm.define_native("format", Box::new(Format))
+        .define_native("toFixed", Function::new("toFixed", 2, to_fixed));
+}
+
+/// `toFixed(n, digits)`, `n` with exactly `digits` decimals
+fn to_fixed(args: &[Value]) -> Result<Value, String> {
+    let n = number_arg("toFixed", args, 0)?;
+    let digits = integer_arg("toFixed", args, 1)?;
+    if digits > MAX_DIGITS {
+        return Err(format!(
+            "toFixed() expected at most {} digits but got {}.",
+            MAX_DIGITS, digits
+        ));
+    }
+    Ok(Value::String(format!("{:.*}", digits, n)))
+}
+
+/// `format(template, args...)` with the placeholders of Rust's `format!`,
+/// `{}`, `{0}` and `{:[[fill]align][+][#][0][width][.precision][type]}`
+/// where type is one of `x`, `X`, `o`, `b`, `e` or `E`
+struct Format;
+
+impl NativeFun for Format {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        if args.is_empty() {
+            return Err("format() expected at least 1 argument but got 0."
+                .to_string()
+                .into());
+        }
+        let template = string_arg("format", args, 0)?;
+        let values = &args[1..];
+
+        let mut out = String::new();
+        let mut used = vec![false; values.len()];
+        let mut next = 0;
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    out.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    out.push('}');
+                }
+                '{' => {
+                    let placeholder = placeholder(&mut chars)?;
+                    let index = placeholder.index.unwrap_or_else(|| {
+                        next += 1;
+                        next - 1
+                    });
+                    let value = values.get(index).ok_or_else(|| {
+                        format!(
+                            "format() has no argument {} for placeholder {{{}}}.",
+                            index, placeholder.text
+                        )
+                    })?;
+                    used[index] = true;
+                    placeholder.spec.write(&mut out, value)?;
+                }
+                '}' => return Err("format() unmatched '}' in template.".to_string().into()),
+                c => out.push(c),
+            }
+        }
+
+        if let Some(unused) = used.iter().position(|used| !used) {
+            return Err(format!("format() argument {} is never used.", unused).into());
+        }
+        Ok(Value::String(out))
+    }
+}
+
+struct Placeholder {
+    text: String,
+    index: Option<usize>,
+    spec: Spec,
+}
+
+/// Reads a placeholder up to and including its closing brace
+fn placeholder(chars: &mut Peekable<Chars>) -> Result<Placeholder, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => text.push(c),
+            None => return Err("format() unterminated placeholder in template.".to_string()),
+        }
+    }
+
+    let (index, spec) = text.split_once(':').unwrap_or((&text, ""));
+    let index = match index.trim() {
+        "" => None,
+        index => Some(
+            index
+                .parse::<usize>()
+                .map_err(|_| format!("format() invalid placeholder {{{}}}.", text))?,
+        ),
+    };
+    let spec =
+        Spec::parse(spec).ok_or_else(|| format!("format() invalid placeholder {{{}}}.", text))?;
+
+    Ok(Placeholder { text, index, spec })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Option<Self> {
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let mut result = Spec::default();
+        let mut rest = spec;
+        let mut chars = spec.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(c)) if align(c).is_some() => {
+                result.fill = Some(fill);
+                result.align = align(c);
+                rest = &spec[fill.len_utf8() + 1..];
+            }
+            (Some(c), _) if align(c).is_some() => {
+                result.align = align(c);
+                rest = &spec[1..];
+            }
+            _ => {}
+        }
+
+        if let Some(r) = rest.strip_prefix('+') {
+            result.plus = true;
+            rest = r;
+        }
+        if let Some(r) = rest.strip_prefix('#') {
+            result.alternate = true;
+            rest = r;
+        }
+        if let Some(r) = rest.strip_prefix('0') {
+            result.zero = true;
+            rest = r;
+        }
+
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 {
+            result.width = rest[..digits].parse().ok()?;
+            if result.width > MAX_WIDTH {
+                return None;
+            }
+            rest = &rest[digits..];
+        }
+
+        if let Some(r) = rest.strip_prefix('.') {
+            let digits = r.len() - r.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let precision = r[..digits].parse().ok()?;
+            if precision > MAX_DIGITS {
+                return None;
+            }
+            result.precision = Some(precision);
+            rest = &r[digits..];
+        }
+
+        match rest {
+            "" => {}
+            "x" | "X" | "o" | "b" | "e" | "E" => result.kind = rest.chars().next(),
+            _ => return None,
+        }
+        Some(result)
+    }
+
+    fn write(&self, out: &mut String, value: &Value) -> Result<(), String> {
+        match value {
+            Value::Number(n) => {
+                let (sign, prefix, digits) = self.number(*n)?;
+                if self.zero {
+                    // zeroes go between the sign and the digits, fill and alignment are ignored
+                    let len = sign.len() + prefix.len() + digits.chars().count();
+                    out.push_str(sign);
+                    out.push_str(prefix);
+                    out.extend(std::iter::repeat_n('0', self.width.saturating_sub(len)));
+                    out.push_str(&digits);
+                } else {
+                    self.pad(out, &format!("{}{}{}", sign, prefix, digits), Align::Right);
+                }
+            }
+            value => {
+                if let Some(kind) = self.kind {
+                    return Err(format!(
+                        "format() can't format a {} with '{}'.",
+                        value.type_name(),
+                        kind
+                    ));
+                }
+                let text = value.to_string();
+                let text = match self.precision {
+                    Some(precision) => text.chars().take(precision).collect(),
+                    None => text,
+                };
+                self.pad(out, &text, Align::Left);
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits a formatted number into its sign, radix prefix and digits
+    fn number(&self, n: f64) -> Result<(&'static str, &'static str, String), String> {
+        let integer = || {
+            if n.fract() != 0.0 || n.abs() >= i64::MAX as f64 {
+                return Err(format!(
+                    "format() expected an integer for '{}' but got {}.",
+                    self.kind.unwrap_or_default(),
+                    n
+                ));
+            }
+            Ok(n as i64)
+        };
+        let prefix = |prefix| if self.alternate { prefix } else { "" };
+
+        // like Rust's integers these are two's complement and never have a sign
+        match self.kind {
+            Some('x') => return Ok(("", prefix("0x"), format!("{:x}", integer()?))),
+            Some('X') => return Ok(("", prefix("0x"), format!("{:X}", integer()?))),
+            Some('o') => return Ok(("", prefix("0o"), format!("{:o}", integer()?))),
+            Some('b') => return Ok(("", prefix("0b"), format!("{:b}", integer()?))),
+            _ => {}
+        }
+
+        let text = match (self.kind, self.precision) {
+            (Some('e'), Some(precision)) => format!("{:.*e}", precision, n),
+            (Some('e'), None) => format!("{:e}", n),
+            (Some('E'), Some(precision)) => format!("{:.*E}", precision, n),
+            (Some('E'), None) => format!("{:E}", n),
+            (_, Some(precision)) => format!("{:.*}", precision, n),
+            (_, None) => format!("{}", n),
+        };
+        Ok(match text.strip_prefix('-') {
+            Some(digits) => ("-", "", digits.to_string()),
+            None if self.plus && !n.is_nan() => ("+", "", text),
+            None => ("", "", text),
+        })
+    }
+
+    fn pad(&self, out: &mut String, text: &str, default: Align) {
+        let padding = self.width.saturating_sub(text.chars().count());
+        let (before, after) = match self.align.unwrap_or(default) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+        let fill = self.fill.unwrap_or(' ');
+
+        out.extend(std::iter::repeat_n(fill, before));
+        out.push_str(text);
+        out.extend(std::iter::repeat_n(fill, after));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{expect_arity, string_arg, Denied};
+use crate::vm::{
+    builder::Capabilities,
+    object::{NativeFun, Obj},
+    value::Value,
+    Vm,
+};
+
+const NATIVES: [(&str, usize, Op); 6] = [
+    ("readFile", 1, read_file),
+    ("readLines", 1, read_lines),
+    ("writeFile", 2, write_file),
+    ("appendFile", 2, append_file),
+    ("exists", 1, exists),
+    ("listDir", 1, list_dir),
+];
+
+/// File natives only see the directories in `fs_roots`,
+/// without any root they all fail with a capability error
+pub fn register(vm: &mut Vm, capabilities: &Capabilities) {
+    if capabilities.fs_roots.is_empty() {
+        for (name, _, _) in NATIVES {
+            vm.define_native(name, Denied::new("fs"));
+        }
+        return;
+    }
+
+    let sandbox = Rc::new(Sandbox {
+        roots: capabilities.fs_roots.clone(),
+    });
+    for (name, arity, op) in NATIVES {
+        vm.define_native(
+            name,
+            Box::new(FsFn {
+                name,
+                arity,
+                sandbox: Rc::clone(&sandbox),
+                op,
+            }),
+        );
+    }
+}
+
+pub struct Sandbox {
+    roots: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Resolves `path` against the first root and checks that the result, with `..` and
+    /// symlinks resolved, is inside one of the roots. The path doesn't have to exist
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let joined = self.roots[0].join(path);
+        let resolved = canonicalize_lenient(&joined)
+            .map_err(|e| format!("Could not resolve path '{}': {}.", path, e))?;
+
+        let inside = self.roots.iter().any(|root| {
+            fs::canonicalize(root)
+                .map(|root| resolved.starts_with(root))
+                .unwrap_or(false)
+        });
+        if !inside {
+            return Err(format!("Path '{}' is outside the sandbox.", path));
+        }
+        Ok(resolved)
+    }
+}
+
+/// Like `fs::canonicalize` but the trailing components may not exist yet,
+/// those are normalized lexically and appended to the deepest existing ancestor
+fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+
+    let mut existing = normalized.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+
+    let mut resolved = fs::canonicalize(existing)?;
+    resolved.extend(missing.into_iter().rev());
+    Ok(resolved)
+}
+
+type Op = fn(&str, &Sandbox, &[Value]) -> Result<Value, String>;
+
+pub struct FsFn {
+    name: &'static str,
+    arity: usize,
+    sandbox: Rc<Sandbox>,
+    op: Op,
+}
+
+impl NativeFun for FsFn {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        expect_arity(self.name, args, self.arity)?;
+        Ok((self.op)(self.name, &self.sandbox, args)?)
+    }
+}
+
+fn path_arg(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<PathBuf, String> {
+    sandbox.resolve(string_arg(name, args, 0)?)
+}
+
+fn io_error(name: &str, args: &[Value], e: io::Error) -> String {
+    format!("{}() failed for '{}': {}.", name, args[0], e)
+}
+
+fn read_file(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    fs::read_to_string(path)
+        .map(Value::String)
+        .map_err(|e| io_error(name, args, e))
+}
+
+/// The lines of a file without their line endings, as a list
+fn read_lines(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    let text = fs::read_to_string(path).map_err(|e| io_error(name, args, e))?;
+    let lines = text
+        .lines()
+        .map(|line| Value::String(line.to_string()))
+        .collect();
+    Ok(Value::Obj(Obj::list(lines)))
+}
+
+fn write_file(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    let text = string_arg(name, args, 1)?;
+    fs::write(path, text).map_err(|e| io_error(name, args, e))?;
+    Ok(Value::Nil)
+}
+
+fn append_file(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    let text = string_arg(name, args, 1)?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| io_error(name, args, e))?;
+    Ok(Value::Nil)
+}
+
+fn exists(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    Ok(Value::Bool(path.exists()))
+}
+
+/// Entry names of a directory, sorted so the result doesn't depend on the platform
+fn list_dir(name: &str, sandbox: &Sandbox, args: &[Value]) -> Result<Value, String> {
+    let path = path_arg(name, sandbox, args)?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .map_err(|e| io_error(name, args, e))?;
+    names.sort();
+    Ok(Value::Obj(Obj::list(
+        names.into_iter().map(Value::String).collect(),
+    )))
+}
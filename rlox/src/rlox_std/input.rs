@@ -0,0 +1,102 @@
+use std::{
+    cell::RefCell,
+    io::{BufRead, Read, Write},
+    rc::Rc,
+};
+
+use super::{expect_arity, string_arg, Denied};
+use crate::{
+    error::{Error, Result},
+    vm::{builder::Capabilities, object::NativeFun, value::Value, Output, Vm},
+};
+
+type Source = Rc<RefCell<Box<dyn BufRead>>>;
+
+/// `readLine`, `readAll` and `input` share the vm's input source,
+/// they need the `input` capability
+pub fn register(vm: &mut Vm, capabilities: &Capabilities, input: Box<dyn BufRead>) {
+    if !capabilities.input {
+        vm.define_native("readLine", Denied::new("input"))
+            .define_native("readAll", Denied::new("input"))
+            .define_native("input", Denied::new("input"));
+        return;
+    }
+
+    let source: Source = Rc::new(RefCell::new(input));
+    let output = capabilities.output.then(|| vm.output());
+
+    vm.define_native("readLine", Box::new(ReadLine(Rc::clone(&source))))
+        .define_native("readAll", Box::new(ReadAll(Rc::clone(&source))))
+        .define_native("input", Box::new(Input { source, output }));
+}
+
+/// The next line without its line ending, nil at the end of the input
+fn read_line(name: &str, source: &Source) -> Result<Value> {
+    let mut line = String::new();
+    let read = source
+        .borrow_mut()
+        .read_line(&mut line)
+        .map_err(|e| format!("{}() failed: {}.", name, e))?;
+    if read == 0 {
+        return Ok(Value::Nil);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Value::String(line))
+}
+
+struct ReadLine(Source);
+
+impl NativeFun for ReadLine {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        expect_arity("readLine", args, 0)?;
+        read_line("readLine", &self.0)
+    }
+}
+
+/// Everything left in the input, an empty string at the end
+struct ReadAll(Source);
+
+impl NativeFun for ReadAll {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        expect_arity("readAll", args, 0)?;
+        let mut text = String::new();
+        self.0
+            .borrow_mut()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("readAll() failed: {}.", e))?;
+        Ok(Value::String(text))
+    }
+}
+
+/// `input(prompt)` writes the prompt where `print` does and reads a line,
+/// the prompt needs the `output` capability like `print`
+struct Input {
+    source: Source,
+    output: Option<Output>,
+}
+
+impl NativeFun for Input {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        expect_arity("input", args, 1)?;
+        let prompt = string_arg("input", args, 0)?;
+
+        if !prompt.is_empty() {
+            let Some(output) = &self.output else {
+                return Err(Error::Native(
+                    "Capability 'output' was not granted".to_string(),
+                ));
+            };
+            let mut output = output.borrow_mut();
+            write!(output, "{}", prompt)
+                .and_then(|_| output.flush())
+                .map_err(|e| format!("input() failed: {}.", e))?;
+        }
+        read_line("input", &self.source)
+    }
+}
@@ -0,0 +1,434 @@
+use std::{fmt::Write, iter::Peekable, str::CharIndices};
+
+use indexmap::IndexMap;
+
+use super::{integer_arg, string_arg, Function};
+use crate::vm::{
+    object::{Module, Obj},
+    value::Value,
+};
+
+/// Deepest nesting of arrays and objects, deeper documents would overflow the stack
+const MAX_DEPTH: usize = 512;
+
+pub fn module() -> Module {
+    Module::new("json")
+        .native("parse", Function::new("json.parse", 1, parse))
+        .native("stringify", Box::new(Stringify))
+}
+
+fn parse(args: &[Value]) -> Result<Value, String> {
+    let text = string_arg("json.parse", args, 0)?;
+    let mut parser = Parser::new(text);
+
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("Unexpected data after the value")),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+    /// Arrays and objects the parser is inside of
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.char_indices().peekable(),
+            line: 1,
+            column: 1,
+            depth: 0,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> String {
+        format!(
+            "json.parse() {} at line {}, column {}.",
+            message.into(),
+            self.line,
+            self.column
+        )
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.advance();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("Expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(format!("Expected '{}' but found end of input", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.advance();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Nil),
+            Some(c) => Err(self.error(format!("Unexpected character '{}'", c))),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(format!("Nesting deeper than {} levels", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        for expected in keyword.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(format!("Invalid literal, expected '{}'", keyword)));
+            }
+            self.advance();
+        }
+        Ok(value)
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries = IndexMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.advance();
+            return Ok(Value::Obj(Obj::map(entries)));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.value()?;
+            entries.insert(key, value);
+
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(self.error("Expected ',' or '}' after object entry")),
+            }
+        }
+
+        Ok(Value::Obj(Obj::map(entries)))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.advance();
+            return Ok(Value::Obj(Obj::list(items)));
+        }
+
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => continue,
+                Some(']') => break,
+                _ => return Err(self.error("Expected ',' or ']' after array element")),
+            }
+        }
+
+        Ok(Value::Obj(Obj::list(items)))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let escaped = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    };
+                    out.push(escaped);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("Control character in string"))
+                }
+                Some(c) => out.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .advance()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            // surrogate pair
+            if self.advance() != Some('\\') || self.advance() != Some('u') {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("Unpaired surrogate in unicode escape"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(c @ ('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) = self.peek() {
+            text.push(c);
+            self.advance();
+        }
+
+        if !is_json_number(&text) {
+            return Err(self.error(format!("Invalid number '{}'", text)));
+        }
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| self.error(format!("Invalid number '{}'", text)))
+    }
+}
+
+/// The JSON grammar is stricter than `f64::from_str`, no leading zeroes or bare dots
+fn is_json_number(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+        None => (digits, None),
+    };
+    let (int, fraction) = match mantissa.split_once('.') {
+        Some((int, fraction)) => (int, Some(fraction)),
+        None => (mantissa, None),
+    };
+
+    let all_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    all_digits(int)
+        && (int == "0" || !int.starts_with('0'))
+        && fraction.is_none_or(all_digits)
+        && exponent.is_none_or(|e| all_digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
+}
+
+/// `json.stringify(value)` or `json.stringify(value, indent)`
+struct Stringify;
+
+impl crate::vm::object::NativeFun for Stringify {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(format!(
+                "json.stringify() expected 1 or 2 arguments but got {}.",
+                args.len()
+            )
+            .into());
+        }
+        let indent = if args.len() == 2 {
+            integer_arg("json.stringify", args, 1)?
+        } else {
+            0
+        };
+
+        let mut writer = Writer {
+            out: String::new(),
+            indent,
+            path: Vec::new(),
+        };
+        writer.value(&args[0], 0)?;
+        Ok(Value::String(writer.out))
+    }
+}
+
+struct Writer {
+    out: String,
+    indent: usize,
+    /// Containers currently being written, to detect cycles
+    path: Vec<usize>,
+}
+
+impl Writer {
+    fn enter(&mut self, ptr: usize) -> Result<(), String> {
+        if self.path.contains(&ptr) {
+            return Err("json.stringify() can't serialize a cyclic structure.".to_string());
+        }
+        if self.path.len() == MAX_DEPTH {
+            return Err(format!(
+                "json.stringify() can't serialize nesting deeper than {} levels.",
+                MAX_DEPTH
+            ));
+        }
+        self.path.push(ptr);
+        Ok(())
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if self.indent > 0 {
+            self.out.push('\n');
+            self.out.push_str(&" ".repeat(self.indent * depth));
+        }
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) -> Result<(), String> {
+        match value {
+            Value::Nil => self.out.push_str("null"),
+            Value::Bool(b) => write!(self.out, "{}", b).unwrap(),
+            Value::Number(n) if n.is_finite() => write!(self.out, "{}", n).unwrap(),
+            Value::Number(n) => {
+                return Err(format!(
+                    "json.stringify() can't serialize the number {}.",
+                    n
+                ))
+            }
+            Value::String(s) => self.string(s),
+            Value::Obj(Obj::List(list)) => {
+                self.enter(list.as_ptr() as usize)?;
+                let items = list.borrow();
+                self.sequence('[', ']', items.len(), depth, |writer, i| {
+                    writer.value(&items[i], depth + 1)
+                })?;
+                self.path.pop();
+            }
+            Value::Obj(Obj::Map(map)) => {
+                self.enter(map.as_ptr() as usize)?;
+                let entries = map.borrow();
+                self.sequence('{', '}', entries.len(), depth, |writer, i| {
+                    let (key, value) = entries.get_index(i).unwrap();
+                    writer.entry(key, value, depth)
+                })?;
+                self.path.pop();
+            }
+            Value::Obj(Obj::Instance(instance)) => {
+                self.enter(instance.as_ptr() as usize)?;
+                let instance = instance.borrow();
+                let mut fields = instance.fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(name, _)| name.as_str());
+                self.sequence('{', '}', fields.len(), depth, |writer, i| {
+                    writer.entry(fields[i].0, fields[i].1, depth)
+                })?;
+                self.path.pop();
+            }
+            value => {
+                return Err(format!(
+                    "json.stringify() can't serialize a {}.",
+                    value.type_name()
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn sequence(
+        &mut self,
+        open: char,
+        close: char,
+        len: usize,
+        depth: usize,
+        mut item: impl FnMut(&mut Self, usize) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.out.push(open);
+        for i in 0..len {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.newline(depth + 1);
+            item(self, i)?;
+        }
+        if len > 0 {
+            self.newline(depth);
+        }
+        self.out.push(close);
+        Ok(())
+    }
+
+    fn entry(&mut self, key: &str, value: &Value, depth: usize) -> Result<(), String> {
+        self.string(key);
+        self.out.push(':');
+        if self.indent > 0 {
+            self.out.push(' ');
+        }
+        self.value(value, depth + 1)
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if (c as u32) < 0x20 => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+}
@@ -0,0 +1,33 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_arity;
+use crate::vm::value::Value;
+
+pub fn invoke(
+    receiver: &Rc<RefCell<Vec<Value>>>,
+    name: &str,
+    args: &[Value],
+) -> Result<Value, String> {
+    let method = format!("list.{}", name);
+    let arity = |arity| expect_arity(&method, args, arity);
+
+    Ok(match name {
+        "len" => {
+            arity(0)?;
+            Value::Number(receiver.borrow().len() as f64)
+        }
+        "push" => {
+            arity(1)?;
+            receiver.borrow_mut().push(args[0].clone());
+            Value::Nil
+        }
+        "pop" => {
+            arity(0)?;
+            receiver
+                .borrow_mut()
+                .pop()
+                .ok_or_else(|| format!("{}() on an empty list.", method))?
+        }
+        _ => return Err(format!("Undefined method {}.", method)),
+    })
+}
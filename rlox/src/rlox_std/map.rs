@@ -0,0 +1,63 @@
+use std::{cell::RefCell, rc::Rc};
+
+use indexmap::IndexMap;
+
+use super::{expect_arity, string_arg};
+use crate::vm::{object::Obj, value::Value};
+
+pub fn invoke(
+    receiver: &Rc<RefCell<IndexMap<String, Value>>>,
+    name: &str,
+    args: &[Value],
+) -> Result<Value, String> {
+    let method = format!("map.{}", name);
+    let arity = |arity| expect_arity(&method, args, arity);
+
+    Ok(match name {
+        "len" => {
+            arity(0)?;
+            Value::Number(receiver.borrow().len() as f64)
+        }
+        "keys" => {
+            arity(0)?;
+            let keys = receiver
+                .borrow()
+                .keys()
+                .cloned()
+                .map(Value::String)
+                .collect();
+            Value::Obj(Obj::list(keys))
+        }
+        "values" => {
+            arity(0)?;
+            Value::Obj(Obj::list(receiver.borrow().values().cloned().collect()))
+        }
+        "has" => {
+            arity(1)?;
+            Value::Bool(
+                receiver
+                    .borrow()
+                    .contains_key(string_arg(&method, args, 0)?),
+            )
+        }
+        "remove" => {
+            arity(1)?;
+            let key = string_arg(&method, args, 0)?;
+            receiver
+                .borrow_mut()
+                .shift_remove(key)
+                .unwrap_or(Value::Nil)
+        }
+        _ => return Err(format!("Undefined method {}.", method)),
+    })
+}
+
+pub fn key(key: &Value) -> Result<&str, String> {
+    match key {
+        Value::String(key) => Ok(key),
+        key => Err(format!(
+            "Map keys must be strings, got {}.",
+            key.type_name()
+        )),
+    }
+}
@@ -0,0 +1,115 @@
+use std::f64::consts;
+
+use super::{expect_arity, number_arg};
+use crate::vm::{
+    object::{Module, NativeFun},
+    value::Value,
+};
+
+pub fn module() -> Module {
+    Module::new("math")
+        .constant("PI", Value::Number(consts::PI))
+        .constant("TAU", Value::Number(consts::TAU))
+        .constant("E", Value::Number(consts::E))
+        .constant("INFINITY", Value::Number(f64::INFINITY))
+        .constant("NAN", Value::Number(f64::NAN))
+        .native("abs", MathFn::number("abs", 1, |x| x[0].abs()))
+        .native("sign", MathFn::number("sign", 1, |x| sign(x[0])))
+        .native("sqrt", MathFn::number("sqrt", 1, |x| x[0].sqrt()))
+        .native("cbrt", MathFn::number("cbrt", 1, |x| x[0].cbrt()))
+        .native("pow", MathFn::number("pow", 2, |x| x[0].powf(x[1])))
+        .native("exp", MathFn::number("exp", 1, |x| x[0].exp()))
+        .native("log", MathFn::number("log", 1, |x| x[0].ln()))
+        .native("log2", MathFn::number("log2", 1, |x| x[0].log2()))
+        .native("log10", MathFn::number("log10", 1, |x| x[0].log10()))
+        .native("floor", MathFn::number("floor", 1, |x| x[0].floor()))
+        .native("ceil", MathFn::number("ceil", 1, |x| x[0].ceil()))
+        .native("round", MathFn::number("round", 1, |x| x[0].round()))
+        .native("trunc", MathFn::number("trunc", 1, |x| x[0].trunc()))
+        .native("sin", MathFn::number("sin", 1, |x| x[0].sin()))
+        .native("cos", MathFn::number("cos", 1, |x| x[0].cos()))
+        .native("tan", MathFn::number("tan", 1, |x| x[0].tan()))
+        .native("asin", MathFn::number("asin", 1, |x| x[0].asin()))
+        .native("acos", MathFn::number("acos", 1, |x| x[0].acos()))
+        .native("atan", MathFn::number("atan", 1, |x| x[0].atan()))
+        .native("atan2", MathFn::number("atan2", 2, |x| x[0].atan2(x[1])))
+        .native("hypot", MathFn::number("hypot", 2, |x| x[0].hypot(x[1])))
+        .native("min", MathFn::variadic("min", |x| fold(x, f64::min)))
+        .native("max", MathFn::variadic("max", |x| fold(x, f64::max)))
+        .native("isNan", MathFn::predicate("isNan", f64::is_nan))
+        .native("isFinite", MathFn::predicate("isFinite", f64::is_finite))
+}
+
+/// Like `f64::signum` but keeps zeroes and NaN as they are
+fn sign(x: f64) -> f64 {
+    if x == 0.0 || x.is_nan() {
+        x
+    } else {
+        x.signum()
+    }
+}
+
+/// `min` and `max` follow `f64::min` and `f64::max`, NaN is only returned if every value is NaN
+fn fold(x: &[f64], f: fn(f64, f64) -> f64) -> f64 {
+    x.iter().copied().reduce(f).unwrap_or(f64::NAN)
+}
+
+enum MathOp {
+    Number(fn(&[f64]) -> f64),
+    Predicate(fn(f64) -> bool),
+}
+
+/// Native over number arguments, `arity` of `None` takes one or more arguments
+pub struct MathFn {
+    name: &'static str,
+    arity: Option<usize>,
+    op: MathOp,
+}
+
+impl MathFn {
+    fn number(name: &'static str, arity: usize, op: fn(&[f64]) -> f64) -> Box<Self> {
+        Box::new(Self {
+            name,
+            arity: Some(arity),
+            op: MathOp::Number(op),
+        })
+    }
+
+    fn variadic(name: &'static str, op: fn(&[f64]) -> f64) -> Box<Self> {
+        Box::new(Self {
+            name,
+            arity: None,
+            op: MathOp::Number(op),
+        })
+    }
+
+    fn predicate(name: &'static str, op: fn(f64) -> bool) -> Box<Self> {
+        Box::new(Self {
+            name,
+            arity: Some(1),
+            op: MathOp::Predicate(op),
+        })
+    }
+}
+
+impl NativeFun for MathFn {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        let name = format!("math.{}", self.name);
+        match self.arity {
+            Some(arity) => expect_arity(&name, args, arity)?,
+            None if args.is_empty() => {
+                return Err(format!("{}() expected at least 1 argument but got 0.", name).into())
+            }
+            None => {}
+        }
+
+        let numbers = (0..args.len())
+            .map(|i| number_arg(&name, args, i))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match self.op {
+            MathOp::Number(op) => Value::Number(op(&numbers)),
+            MathOp::Predicate(op) => Value::Bool(op(numbers[0])),
+        })
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{expect_arity, number_arg, Denied};
+use crate::vm::{
+    object::{Module, NativeFun, Obj},
+    value::Value,
+};
+
+/// The natives share one generator. Without the `random` capability there is
+/// no generator until `seed()`, always allowed, makes one so draws are reproducible
+pub fn module(entropy: bool) -> Module {
+    let rng = entropy.then(|| {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Xoshiro256::new(seed)
+    });
+    let rng = Rc::new(RefCell::new(rng));
+
+    Module::new("random")
+        .native("random", RandomFn::new("random", 0, &rng, random))
+        .native("randint", RandomFn::new("randint", 2, &rng, randint))
+        .native("choice", RandomFn::new("choice", 1, &rng, choice))
+        .native("shuffle", RandomFn::new("shuffle", 1, &rng, shuffle))
+        .native("seed", Box::new(Seed { rng }))
+}
+
+/// xoshiro256** seeded through splitmix64, only integer arithmetic
+/// so a seed gives the same sequence on every platform
+pub struct Xoshiro256 {
+    state: [u64; 4],
+}
+
+impl Xoshiro256 {
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [splitmix(), splitmix(), splitmix(), splitmix()],
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Uniform in `[0, 1)` with 53 bits of precision
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Uniform in `0..bound` without modulo bias, `bound` must not be zero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % bound;
+            }
+        }
+    }
+}
+
+type Op = fn(&str, &mut Xoshiro256, &[Value]) -> Result<Value, String>;
+
+pub struct RandomFn {
+    name: String,
+    arity: usize,
+    rng: Rc<RefCell<Option<Xoshiro256>>>,
+    op: Op,
+}
+
+impl RandomFn {
+    fn new(name: &str, arity: usize, rng: &Rc<RefCell<Option<Xoshiro256>>>, op: Op) -> Box<Self> {
+        Box::new(Self {
+            name: format!("random.{}", name),
+            arity,
+            rng: Rc::clone(rng),
+            op,
+        })
+    }
+}
+
+impl NativeFun for RandomFn {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        expect_arity(&self.name, args, self.arity)?;
+        match self.rng.borrow_mut().as_mut() {
+            Some(rng) => Ok((self.op)(&self.name, rng, args)?),
+            None => Denied {
+                capability: "random",
+            }
+            .call(args),
+        }
+    }
+}
+
+/// `random.seed(n)`, needs no capability since the draws after it are reproducible
+struct Seed {
+    rng: Rc<RefCell<Option<Xoshiro256>>>,
+}
+
+impl NativeFun for Seed {
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value> {
+        expect_arity("random.seed", args, 1)?;
+        let seed = whole_arg("random.seed", args, 0)?;
+        *self.rng.borrow_mut() = Some(Xoshiro256::new(seed as u64));
+        Ok(Value::Nil)
+    }
+}
+
+fn random(_name: &str, rng: &mut Xoshiro256, _args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(rng.next_f64()))
+}
+
+/// Whole number in `lo..=hi`
+fn randint(name: &str, rng: &mut Xoshiro256, args: &[Value]) -> Result<Value, String> {
+    let lo = whole_arg(name, args, 0)?;
+    let hi = whole_arg(name, args, 1)?;
+    if lo > hi {
+        return Err(format!("{}() empty range {}..={}.", name, lo, hi));
+    }
+    let span = (hi - lo) as u64 + 1;
+    Ok(Value::Number((lo + rng.below(span) as i64) as f64))
+}
+
+fn choice(name: &str, rng: &mut Xoshiro256, args: &[Value]) -> Result<Value, String> {
+    let list = list_arg(name, args, 0)?;
+    let items = list.borrow();
+    if items.is_empty() {
+        return Err(format!("{}() on an empty list.", name));
+    }
+    Ok(items[rng.below(items.len() as u64) as usize].clone())
+}
+
+/// Fisher-Yates shuffle in place
+fn shuffle(name: &str, rng: &mut Xoshiro256, args: &[Value]) -> Result<Value, String> {
+    let list = list_arg(name, args, 0)?;
+    let mut items = list.borrow_mut();
+    for i in (1..items.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+    Ok(Value::Nil)
+}
+
+/// Whole number that fits the 53 bit mantissa
+fn whole_arg(name: &str, args: &[Value], index: usize) -> Result<i64, String> {
+    const MAX_SAFE: f64 = ((1u64 << 53) - 1) as f64;
+
+    let n = number_arg(name, args, index)?;
+    if n.fract() != 0.0 || n.abs() > MAX_SAFE {
+        return Err(format!(
+            "{}() expected an integer as argument {} but got {}.",
+            name,
+            index + 1,
+            n
+        ));
+    }
+    Ok(n as i64)
+}
+
+fn list_arg<'a>(
+    name: &str,
+    args: &'a [Value],
+    index: usize,
+) -> Result<&'a Rc<RefCell<Vec<Value>>>, String> {
+    match &args[index] {
+        Value::Obj(Obj::List(list)) => Ok(list),
+        value => Err(format!(
+            "{}() expected a list as argument {} but got {}.",
+            name,
+            index + 1,
+            value.type_name()
+        )),
+    }
+}
@@ -0,0 +1,98 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{string_arg, Function};
+use crate::vm::{
+    object::{Class, Instance, Obj},
+    value::Value,
+    Vm,
+};
+
+pub fn register(vm: &mut Vm) {
+    vm.define_native("fields", Function::new("fields", 1, fields))
+        .define_native("hasField", Function::new("hasField", 2, has_field))
+        .define_native("getField", Function::new("getField", 2, get_field))
+        .define_native("setField", Function::new("setField", 3, set_field))
+        .define_native("methods", Function::new("methods", 1, methods))
+        .define_native("superclass", Function::new("superclass", 1, superclass))
+        .define_native("className", Function::new("className", 1, class_name));
+}
+
+fn instance(name: &str, args: &[Value]) -> Result<Rc<RefCell<Instance>>, String> {
+    match &args[0] {
+        Value::Obj(Obj::Instance(instance)) => Ok(instance.clone()),
+        value => Err(format!(
+            "{}() expected an instance as argument 1 but got {}.",
+            name,
+            value.type_name()
+        )),
+    }
+}
+
+fn class(name: &str, args: &[Value]) -> Result<Rc<RefCell<Class>>, String> {
+    match &args[0] {
+        Value::Obj(Obj::Class(class)) => Ok(class.clone()),
+        value => Err(format!(
+            "{}() expected a class as argument 1 but got {}.",
+            name,
+            value.type_name()
+        )),
+    }
+}
+
+/// Sorted so scripts see the same order on every run
+fn names<'a>(names: impl Iterator<Item = &'a String>) -> Value {
+    let mut names = names.cloned().collect::<Vec<_>>();
+    names.sort();
+    Value::Obj(Obj::list(names.into_iter().map(Value::String).collect()))
+}
+
+fn fields(args: &[Value]) -> Result<Value, String> {
+    Ok(names(instance("fields", args)?.borrow().fields.keys()))
+}
+
+fn has_field(args: &[Value]) -> Result<Value, String> {
+    let instance = instance("hasField", args)?;
+    let name = string_arg("hasField", args, 1)?;
+    let has_field = instance.borrow().fields.contains_key(name);
+    Ok(Value::Bool(has_field))
+}
+
+fn get_field(args: &[Value]) -> Result<Value, String> {
+    let instance = instance("getField", args)?;
+    let name = string_arg("getField", args, 1)?;
+    let field = instance.borrow().fields.get(name).cloned();
+    field.ok_or_else(|| format!("Undefined field {}.", name))
+}
+
+fn set_field(args: &[Value]) -> Result<Value, String> {
+    let instance = instance("setField", args)?;
+    let name = string_arg("setField", args, 1)?;
+    instance
+        .borrow_mut()
+        .fields
+        .insert(name.to_string(), args[2].clone());
+    Ok(args[2].clone())
+}
+
+fn methods(args: &[Value]) -> Result<Value, String> {
+    Ok(names(class("methods", args)?.borrow().methods.keys()))
+}
+
+fn superclass(args: &[Value]) -> Result<Value, String> {
+    let superclass = class("superclass", args)?.borrow().superclass.clone();
+    Ok(superclass.map_or(Value::Nil, |class| Value::Obj(Obj::Class(class))))
+}
+
+fn class_name(args: &[Value]) -> Result<Value, String> {
+    let name = match &args[0] {
+        Value::Obj(Obj::Instance(instance)) => instance.borrow().class.borrow().name.clone(),
+        Value::Obj(Obj::Class(class)) => class.borrow().name.clone(),
+        value => {
+            return Err(format!(
+                "className() expected an instance or class but got {}.",
+                value.type_name()
+            ))
+        }
+    };
+    Ok(Value::String(name))
+}
@@ -0,0 +1,104 @@
+use super::{expect_arity, index, integer_arg, string_arg};
+use crate::vm::{object::Obj, value::Value};
+
+/// Calls the string method `name`, indices count unicode scalar values and not bytes
+pub fn invoke(receiver: &str, name: &str, args: &[Value]) -> Result<Value, String> {
+    let method = format!("string.{}", name);
+    let arity = |arity| expect_arity(&method, args, arity);
+
+    Ok(match name {
+        "len" => {
+            arity(0)?;
+            Value::Number(receiver.chars().count() as f64)
+        }
+        "substr" => {
+            if args.is_empty() || args.len() > 2 {
+                return Err(format!(
+                    "{}() expected 1 or 2 arguments but got {}.",
+                    method,
+                    args.len()
+                ));
+            }
+            let start = integer_arg(&method, args, 0)?;
+            let chars = receiver.chars().skip(start);
+            Value::String(if args.len() == 2 {
+                chars.take(integer_arg(&method, args, 1)?).collect()
+            } else {
+                chars.collect()
+            })
+        }
+        "indexOf" => {
+            arity(1)?;
+            let needle = string_arg(&method, args, 0)?;
+            Value::Number(match receiver.find(needle) {
+                Some(byte) => receiver[..byte].chars().count() as f64,
+                None => -1.0,
+            })
+        }
+        "split" => {
+            arity(1)?;
+            let separator = string_arg(&method, args, 0)?;
+            if separator.is_empty() {
+                chars(receiver)
+            } else {
+                let parts = receiver
+                    .split(separator)
+                    .map(|part| Value::String(part.to_string()))
+                    .collect();
+                Value::Obj(Obj::list(parts))
+            }
+        }
+        "trim" => {
+            arity(0)?;
+            Value::String(receiver.trim().to_string())
+        }
+        "upper" => {
+            arity(0)?;
+            Value::String(receiver.to_uppercase())
+        }
+        "lower" => {
+            arity(0)?;
+            Value::String(receiver.to_lowercase())
+        }
+        "replace" => {
+            arity(2)?;
+            let from = string_arg(&method, args, 0)?;
+            let to = string_arg(&method, args, 1)?;
+            Value::String(receiver.replace(from, to))
+        }
+        "startsWith" => {
+            arity(1)?;
+            Value::Bool(receiver.starts_with(string_arg(&method, args, 0)?))
+        }
+        "endsWith" => {
+            arity(1)?;
+            Value::Bool(receiver.ends_with(string_arg(&method, args, 0)?))
+        }
+        "contains" => {
+            arity(1)?;
+            Value::Bool(receiver.contains(string_arg(&method, args, 0)?))
+        }
+        "chars" => {
+            arity(0)?;
+            chars(receiver)
+        }
+        _ => return Err(format!("Undefined method {}.", method)),
+    })
+}
+
+/// `s[i]`, the character at char index `i`
+pub fn char_at(receiver: &str, i: &Value) -> Result<Value, String> {
+    let i = index(i, receiver.chars().count())?;
+    Ok(Value::String(
+        receiver.chars().nth(i).unwrap_or_default().to_string(),
+    ))
+}
+
+fn chars(receiver: &str) -> Value {
+    Value::Obj(Obj::list(
+        receiver
+            .chars()
+            .map(|c| Value::String(c.to_string()))
+            .collect(),
+    ))
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use super::{expect_arity, number_arg, string_arg, Denied};
+use crate::{
+    error::{Error, Result},
+    vm::{
+        builder::Capabilities,
+        object::{NativeFun, Obj},
+        value::Value,
+        Vm,
+    },
+};
+
+pub fn register(
+    vm: &mut Vm,
+    capabilities: &Capabilities,
+    args: Vec<String>,
+    vars: Option<HashMap<String, String>>,
+) {
+    vm.define_native("args", Box::new(Args { args }))
+        .define_native("exit", Box::new(Exit));
+
+    if capabilities.env {
+        vm.define_native("env", Box::new(Env { vars }));
+    } else {
+        vm.define_native("env", Denied::new("env"));
+    }
+}
+
+/// `args()`, the arguments given to the script after its path
+struct Args {
+    args: Vec<String>,
+}
+
+impl NativeFun for Args {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        expect_arity("args", args, 0)?;
+        Ok(Value::Obj(Obj::list(
+            self.args.iter().cloned().map(Value::String).collect(),
+        )))
+    }
+}
+
+/// `env(name)`, the environment variable or nil if it isn't set,
+/// from the variables given to the vm or else the process environment
+struct Env {
+    vars: Option<HashMap<String, String>>,
+}
+
+impl NativeFun for Env {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        expect_arity("env", args, 1)?;
+        let name = string_arg("env", args, 0)?;
+        let value = match &self.vars {
+            Some(vars) => vars.get(name).cloned(),
+            None => std::env::var(name).ok(),
+        };
+        Ok(value.map_or(Value::Nil, Value::String))
+    }
+}
+
+/// `exit()` or `exit(code)` stops the script by unwinding with `Error::Exit`
+struct Exit;
+
+impl NativeFun for Exit {
+    fn call(&self, args: &[Value]) -> Result<Value> {
+        if args.len() > 1 {
+            expect_arity("exit", args, 1)?;
+        }
+        let code = if args.is_empty() {
+            0.0
+        } else {
+            number_arg("exit", args, 0)?
+        };
+        if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {
+            return Err(format!("exit() expected an integer status but got {}.", code).into());
+        }
+        Err(Error::Exit(code as i32))
+    }
+}
@@ -0,0 +1,52 @@
+use super::Function;
+use crate::vm::{
+    object::{Class, Obj},
+    value::Value,
+    Vm,
+};
+
+pub fn register(vm: &mut Vm) {
+    vm.define_native("type", Function::new("type", 1, type_of))
+        .define_native("str", Function::new("str", 1, str))
+        .define_native("num", Function::new("num", 1, num))
+        .define_native("instanceof", Function::new("instanceof", 2, instanceof));
+}
+
+fn type_of(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(args[0].type_name().to_string()))
+}
+
+fn str(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+fn num(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            // "inf" and "NaN" parse but are no Lox numbers
+            .filter(|n| n.is_finite())
+            .map(Value::Number)
+            .ok_or_else(|| format!("num() could not parse '{}' as a number.", s)),
+        value => Err(format!(
+            "num() expected a string or number but got {}.",
+            value.type_name()
+        )),
+    }
+}
+
+fn instanceof(args: &[Value]) -> Result<Value, String> {
+    match (&args[0], &args[1]) {
+        (Value::Obj(Obj::Instance(instance)), Value::Obj(Obj::Class(class))) => Ok(Value::Bool(
+            Class::inherits(&instance.borrow().class, class),
+        )),
+        (_, Value::Obj(Obj::Class(_))) => Ok(Value::Bool(false)),
+        (_, value) => Err(format!(
+            "instanceof() expected a class as argument 2 but got {}.",
+            value.type_name()
+        )),
+    }
+}
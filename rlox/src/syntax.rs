@@ -0,0 +1,419 @@
+//! Lossless syntax tree of a script: every token keeps the whitespace and comments
+//! before it, so printing the tree gives back the source byte for byte.
+//! Statements are nodes while expressions stay flat token runs, that's all the formatter
+//! needs; tools that look at what code means use the [`ast`](crate::ast).
+
+use std::fmt;
+
+use crate::compiler::scanner::{Scanner, TokenKind};
+
+/// What the scanner skips between two tokens
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trivia {
+    /// Whitespace, and the text of invalid characters in broken sources
+    Whitespace(String),
+    /// A `//` comment without its line ending
+    Comment(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    /// Byte offset of the text in the source
+    pub offset: usize,
+}
+
+impl SyntaxToken {
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.leading.iter().filter_map(|trivia| match trivia {
+            Trivia::Comment(text) => Some(text.as_str()),
+            Trivia::Whitespace(_) => None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Script,
+    VarDecl,
+    FunDecl,
+    ClassDecl,
+    /// The braces and methods of a class
+    ClassBody,
+    Method,
+    /// The parentheses and names of a parameter list
+    Params,
+    Block,
+    Print,
+    Return,
+    If,
+    While,
+    For,
+    ExprStmt,
+    Expr,
+}
+
+#[derive(Clone, Debug)]
+pub enum Element {
+    Node(Node),
+    Token(SyntaxToken),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn first_token(&self) -> Option<&SyntaxToken> {
+        self.children.iter().find_map(|child| match child {
+            Element::Token(token) => Some(token),
+            Element::Node(node) => node.first_token(),
+        })
+    }
+
+    /// Every token of the node in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in self.children.iter() {
+            match child {
+                Element::Token(token) => tokens.push(token),
+                Element::Node(node) => node.collect_tokens(tokens),
+            }
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in token.leading.iter() {
+                match trivia {
+                    Trivia::Whitespace(text) | Trivia::Comment(text) => f.write_str(text)?,
+                }
+            }
+            f.write_str(&token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the tree of any source, broken code ends up in the nodes it was found in
+pub fn parse(source: &str) -> Node {
+    Parser::new(source).script()
+}
+
+/// Tokens of `source` with their trivia, the last one is the end of the source
+fn tokens(source: &str) -> Vec<SyntaxToken> {
+    let mut scanner = Scanner::new(source).with_comments();
+    let mut tokens = Vec::new();
+    let mut leading = Vec::new();
+    let mut end = 0;
+
+    loop {
+        let token = scanner.scan_token();
+        let span = token.span();
+        if span.start > end {
+            leading.push(Trivia::Whitespace(source[end..span.start].to_string()));
+        }
+        // error tokens are empty, their text is skipped over as trivia
+        end = end.max(span.end);
+
+        match token.kind {
+            TokenKind::Comment => leading.push(Trivia::Comment(token.lexeme.to_string())),
+            TokenKind::Error => {}
+            kind => {
+                tokens.push(SyntaxToken {
+                    kind,
+                    text: source[span.clone()].to_string(),
+                    leading: std::mem::take(&mut leading),
+                    offset: span.start,
+                });
+                if kind == TokenKind::Eof {
+                    return tokens;
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<SyntaxToken>,
+    current: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Self {
+        Self {
+            tokens: tokens(source),
+            current: 0,
+        }
+    }
+
+    fn peek(&self) -> TokenKind {
+        self.tokens[self.current].kind
+    }
+
+    fn at_end(&self) -> bool {
+        self.peek() == TokenKind::Eof
+    }
+
+    fn bump(&mut self, node: &mut Node) {
+        node.children
+            .push(Element::Token(self.tokens[self.current].clone()));
+        if !self.at_end() {
+            self.current += 1;
+        }
+    }
+
+    /// Takes the next token into `node` if it's a `kind`
+    fn eat(&mut self, node: &mut Node, kind: TokenKind) -> bool {
+        if self.peek() == kind && !self.at_end() {
+            self.bump(node);
+            return true;
+        }
+        false
+    }
+
+    fn script(&mut self) -> Node {
+        let mut script = Node::new(NodeKind::Script);
+        while !self.at_end() {
+            let statement = self.declaration();
+            script.children.push(Element::Node(statement));
+        }
+        // the end keeps the trivia after the last statement
+        self.bump(&mut script);
+        script
+    }
+
+    fn declaration(&mut self) -> Node {
+        let start = self.current;
+        let node = match self.peek() {
+            TokenKind::Var => self.var_declaration(),
+            TokenKind::Fun => {
+                let mut node = Node::new(NodeKind::FunDecl);
+                self.bump(&mut node);
+                self.eat(&mut node, TokenKind::Identifier);
+                self.function(node)
+            }
+            TokenKind::Class => self.class_declaration(),
+            _ => self.statement(),
+        };
+
+        if self.current == start && !self.at_end() {
+            // nothing could use the token, it becomes a statement of its own
+            let mut node = Node::new(NodeKind::ExprStmt);
+            self.bump(&mut node);
+            return node;
+        }
+        node
+    }
+
+    fn var_declaration(&mut self) -> Node {
+        let mut node = Node::new(NodeKind::VarDecl);
+        self.bump(&mut node);
+        self.eat(&mut node, TokenKind::Identifier);
+        if self.eat(&mut node, TokenKind::Equal) {
+            self.expression(&mut node);
+        }
+        self.eat(&mut node, TokenKind::Semicolon);
+        node
+    }
+
+    /// Parameters and body of a function or method
+    fn function(&mut self, mut node: Node) -> Node {
+        if self.peek() == TokenKind::LeftParen {
+            let mut params = Node::new(NodeKind::Params);
+            self.bump(&mut params);
+            while !matches!(self.peek(), TokenKind::RightParen | TokenKind::LeftBrace)
+                && !self.at_end()
+            {
+                self.bump(&mut params);
+            }
+            self.eat(&mut params, TokenKind::RightParen);
+            node.children.push(Element::Node(params));
+        }
+        if self.peek() == TokenKind::LeftBrace {
+            let block = self.block();
+            node.children.push(Element::Node(block));
+        }
+        node
+    }
+
+    fn class_declaration(&mut self) -> Node {
+        let mut node = Node::new(NodeKind::ClassDecl);
+        self.bump(&mut node);
+        self.eat(&mut node, TokenKind::Identifier);
+        if self.eat(&mut node, TokenKind::Less) {
+            self.eat(&mut node, TokenKind::Identifier);
+        }
+
+        if self.peek() == TokenKind::LeftBrace {
+            let mut body = Node::new(NodeKind::ClassBody);
+            self.bump(&mut body);
+            while !matches!(self.peek(), TokenKind::RightBrace | TokenKind::Eof) {
+                let mut method = Node::new(NodeKind::Method);
+                self.bump(&mut method);
+                let method = self.function(method);
+                body.children.push(Element::Node(method));
+            }
+            self.eat(&mut body, TokenKind::RightBrace);
+            node.children.push(Element::Node(body));
+        }
+        node
+    }
+
+    fn block(&mut self) -> Node {
+        let mut node = Node::new(NodeKind::Block);
+        self.bump(&mut node);
+        while !matches!(self.peek(), TokenKind::RightBrace | TokenKind::Eof) {
+            let statement = self.declaration();
+            node.children.push(Element::Node(statement));
+        }
+        self.eat(&mut node, TokenKind::RightBrace);
+        node
+    }
+
+    fn statement(&mut self) -> Node {
+        match self.peek() {
+            TokenKind::LeftBrace => self.block(),
+            TokenKind::Print => self.keyword_statement(NodeKind::Print),
+            TokenKind::Return => self.keyword_statement(NodeKind::Return),
+            TokenKind::If => {
+                let mut node = self.condition(NodeKind::If);
+                if self.eat(&mut node, TokenKind::Else) {
+                    self.body(&mut node);
+                }
+                node
+            }
+            TokenKind::While => self.condition(NodeKind::While),
+            TokenKind::For => self.for_statement(),
+            _ => {
+                let mut node = Node::new(NodeKind::ExprStmt);
+                self.expression(&mut node);
+                self.eat(&mut node, TokenKind::Semicolon);
+                node
+            }
+        }
+    }
+
+    /// `print` or `return` with an optional expression
+    fn keyword_statement(&mut self, kind: NodeKind) -> Node {
+        let mut node = Node::new(kind);
+        self.bump(&mut node);
+        if self.peek() != TokenKind::Semicolon {
+            self.expression(&mut node);
+        }
+        self.eat(&mut node, TokenKind::Semicolon);
+        node
+    }
+
+    /// `if` or `while` with the condition and body
+    fn condition(&mut self, kind: NodeKind) -> Node {
+        let mut node = Node::new(kind);
+        self.bump(&mut node);
+        if self.eat(&mut node, TokenKind::LeftParen) {
+            self.expression(&mut node);
+            self.eat(&mut node, TokenKind::RightParen);
+        }
+        self.body(&mut node);
+        node
+    }
+
+    fn body(&mut self, node: &mut Node) {
+        if !self.at_end() {
+            let body = self.statement();
+            node.children.push(Element::Node(body));
+        }
+    }
+
+    fn for_statement(&mut self) -> Node {
+        let mut node = Node::new(NodeKind::For);
+        self.bump(&mut node);
+        if self.eat(&mut node, TokenKind::LeftParen) {
+            match self.peek() {
+                TokenKind::Semicolon => self.bump(&mut node),
+                TokenKind::Var => {
+                    let initializer = self.var_declaration();
+                    node.children.push(Element::Node(initializer));
+                }
+                _ => {
+                    let mut initializer = Node::new(NodeKind::ExprStmt);
+                    self.expression(&mut initializer);
+                    self.eat(&mut initializer, TokenKind::Semicolon);
+                    node.children.push(Element::Node(initializer));
+                }
+            }
+            if self.peek() != TokenKind::Semicolon {
+                self.expression(&mut node);
+            }
+            self.eat(&mut node, TokenKind::Semicolon);
+            if self.peek() != TokenKind::RightParen {
+                self.expression(&mut node);
+            }
+            self.eat(&mut node, TokenKind::RightParen);
+        }
+        self.body(&mut node);
+        node
+    }
+
+    /// Tokens up to the `;` or closing bracket that ends the expression
+    fn expression(&mut self, parent: &mut Node) {
+        let mut node = Node::new(NodeKind::Expr);
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                TokenKind::Eof => break,
+                TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::Semicolon if depth == 0 => break,
+                // a statement can't start inside an expression, the expression is missing its end
+                TokenKind::Var
+                | TokenKind::Fun
+                | TokenKind::Class
+                | TokenKind::Print
+                | TokenKind::Return
+                | TokenKind::If
+                | TokenKind::Else
+                | TokenKind::While
+                | TokenKind::For
+                    if depth == 0 =>
+                {
+                    break
+                }
+                _ => {}
+            }
+            self.bump(&mut node);
+        }
+        if !node.children.is_empty() {
+            parent.children.push(Element::Node(node));
+        }
+    }
+}
@@ -1,4 +1,24 @@
-use crate::run;
+use crate::{
+    analysis::{self, Analysis},
+    ast::{self, parser::parse, BinaryOp, ExprKind, StmtKind},
+    compiler::{
+        scanner::{Scanner, TokenKind},
+        Compiler, FunctionKind, State,
+    },
+    error::Error,
+    format::{format, FormatOptions},
+    lint::{lint, LintCode},
+    run, syntax,
+    vm::{
+        builder::{Capabilities, Captured, VmBuilder},
+        debug::{Breakpoint, Debugger, FrameInfo, Step},
+        limits::Limits,
+        object::Obj,
+        value::Value,
+        RunState, Vm,
+    },
+};
+use std::sync::atomic::Ordering;
 
 macro_rules! check {
     ( $src:literal ) => {
@@ -253,3 +273,1886 @@ fn classes_inheritance_super() {
     Cock().finish();
     "#};
 }
+
+#[test]
+fn classes_leave_no_stack_slot() {
+    let mut vm = Vm::new();
+    vm.interpret(indoc::indoc! {r#"
+        class A {}
+        class B < A {}
+        var result;
+        {
+            var a = 1;
+            var b = 2;
+            result = a + b;
+        }
+    "#})
+        .unwrap();
+    assert_eq!(vm.get_global("result"), Some(&Value::Number(3.0)));
+}
+
+#[test]
+fn globals_host_access() {
+    let mut vm = Vm::new();
+    vm.set_global("input", Value::Number(20.0))
+        .set_global("name", Value::String("rlox".to_string()));
+
+    vm.interpret(indoc::indoc! {r#"
+        var result = input * 2;
+        var greeting = "hello " + name;
+        fun exported() {}
+    "#})
+        .unwrap();
+
+    assert_eq!(vm.get_global("result"), Some(&Value::Number(40.0)));
+    assert_eq!(
+        vm.get_global("greeting"),
+        Some(&Value::String("hello rlox".to_string()))
+    );
+    assert!(vm.get_global("exported").is_some());
+    assert!(vm.get_global("missing").is_none());
+
+    let names = vm
+        .globals_iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"exported"));
+
+    assert_eq!(vm.remove_global("input"), Some(Value::Number(20.0)));
+    assert!(vm.interpret("print input;").is_err());
+}
+
+#[test]
+fn limits_stack_overflow() {
+    let mut vm = Vm::new();
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            fun recurse(n) {
+                return recurse(n + 1);
+            }
+            recurse(0);
+        "#})
+        .unwrap_err();
+
+    assert!(matches!(&errors[..], [Error::Runtime(msg, 2)] if msg == "Stack overflow."));
+
+    vm.set_limits(Limits {
+        max_frames: 200,
+        ..Limits::default()
+    });
+    vm.interpret(indoc::indoc! {r#"
+        fun depth(n) {
+            if (n < 1) return 0;
+            return depth(n - 1) + 1;
+        }
+        var result = depth(150);
+    "#})
+        .unwrap();
+    assert_eq!(vm.get_global("result"), Some(&Value::Number(150.0)));
+}
+
+#[test]
+fn limits_fuel() {
+    let mut vm = Vm::new();
+    vm.set_limits(Limits {
+        fuel: Some(10_000),
+        ..Limits::default()
+    });
+
+    let errors = vm.interpret("while (true) {}").unwrap_err();
+    assert!(matches!(&errors[..], [Error::OutOfFuel(1)]));
+    assert_eq!(vm.fuel(), Some(0));
+
+    vm.interpret("var a = 1 + 2;").unwrap();
+    assert!(vm.fuel().unwrap() > 0);
+}
+
+#[test]
+fn limits_heap() {
+    let mut vm = Vm::new();
+    vm.set_limits(Limits {
+        max_heap: Some(64 * 1024),
+        ..Limits::default()
+    });
+
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            var s = "data";
+            while (true) {
+                s = s + s;
+            }
+        "#})
+        .unwrap_err();
+    assert!(matches!(&errors[..], [Error::OutOfMemory(65536, 3)]));
+
+    // values pushed into a list count as they are added
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            var list = [];
+            var s = "a string that is copied into the list every time";
+            while (true) {
+                list.push(s);
+            }
+        "#})
+        .unwrap_err();
+    assert!(matches!(&errors[..], [Error::OutOfMemory(65536, 4)]));
+    vm.remove_global("list");
+
+    // garbage that is no longer reachable doesn't count towards the limit
+    vm.interpret(indoc::indoc! {r#"
+        for (var i = 0; i < 10000; i = i + 1) {
+            var s = "some temporary string" + "that gets dropped";
+        }
+    "#})
+        .unwrap();
+
+    // measuring deeply nested values doesn't overflow the stack
+    let mut vm = Vm::new();
+    vm.set_limits(Limits {
+        max_heap: Some(64 << 20),
+        ..Limits::default()
+    });
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            var l = [];
+            for (var i = 0; i < 100000; i = i + 1) l = [l];
+            var s = "data";
+            while (true) s = s + s;
+        "#})
+        .unwrap_err();
+    assert!(matches!(&errors[..], [Error::OutOfMemory(_, 4)]));
+}
+
+#[test]
+fn suspend_and_resume() {
+    let mut vm = Vm::new();
+    vm.set_yield_interval(Some(100));
+
+    let mut state = vm
+        .interpret(indoc::indoc! {r#"
+            fun sum(n) {
+                var total = 0;
+                for (var i = 1; i <= n; i = i + 1) {
+                    total = total + i;
+                }
+                return total;
+            }
+            var result = sum(1000);
+        "#})
+        .unwrap();
+
+    let mut suspensions = 0;
+    while state == RunState::Suspended {
+        assert!(vm.is_suspended());
+        assert!(vm.get_global("result").is_none());
+        suspensions += 1;
+        state = vm.resume().unwrap();
+    }
+
+    assert!(suspensions > 10);
+    assert!(!vm.is_suspended());
+    assert_eq!(vm.get_global("result"), Some(&Value::Number(500500.0)));
+    assert_eq!(vm.resume().unwrap(), RunState::Finished);
+}
+
+#[test]
+fn suspend_on_interrupt() {
+    let mut vm = Vm::new();
+    let interrupt = vm.interrupt_handle();
+    interrupt.store(true, Ordering::Relaxed);
+
+    let state = vm.interpret("var a = 1; var b = a + 1;").unwrap();
+    assert_eq!(state, RunState::Suspended);
+    assert!(!interrupt.load(Ordering::Relaxed));
+    assert!(vm.get_global("a").is_none());
+
+    assert_eq!(vm.resume().unwrap(), RunState::Finished);
+    assert_eq!(vm.get_global("b"), Some(&Value::Number(2.0)));
+}
+
+#[test]
+fn capabilities_denied() {
+    let mut vm = VmBuilder::new().build();
+
+    let errors = vm.interpret("var start = clock();").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'clock' was not granted"
+    ));
+
+    let errors = vm.interpret("print 1;").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'output' was not granted"
+    ));
+
+    let mut vm = VmBuilder::new().input(std::io::Cursor::new("line")).build();
+    for call in ["readLine()", "readAll()", r#"input("")"#] {
+        let errors = vm.interpret(&format!("{};", call)).unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [Error::Runtime(msg, 1)] if msg == "Capability 'input' was not granted"
+        ));
+    }
+}
+
+#[test]
+fn capabilities_granted() {
+    let mut vm = VmBuilder::new()
+        .capabilities(Capabilities {
+            clock: true,
+            ..Capabilities::none()
+        })
+        .build();
+
+    vm.interpret("var start = clock();").unwrap();
+    assert!(matches!(vm.get_global("start"), Some(Value::Number(_))));
+    assert!(!vm.capabilities().output);
+}
+
+fn std_vm() -> Vm {
+    VmBuilder::new().capabilities(Capabilities::all()).build()
+}
+
+fn number(vm: &Vm, name: &str) -> f64 {
+    match vm.get_global(name) {
+        Some(Value::Number(n)) => *n,
+        other => panic!("{} is not a number: {:?}", name, other),
+    }
+}
+
+#[test]
+fn math_module() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var root = math.sqrt(16);
+        var floored = math.floor(-1.5);
+        var rounded = math.round(2.5);
+        var absolute = math.abs(-3);
+        var smallest = math.min(3, 1, 2);
+        var largest = math.max(3, 1, 2);
+        var power = math.pow(2, 10);
+        var angle = math.atan2(1, 1) * 4;
+        var circle = math.TAU / 2;
+        print math.sin(math.PI / 2);
+    "#})
+        .unwrap();
+
+    assert_eq!(number(&vm, "root"), 4.0);
+    assert_eq!(number(&vm, "floored"), -2.0);
+    assert_eq!(number(&vm, "rounded"), 3.0);
+    assert_eq!(number(&vm, "absolute"), 3.0);
+    assert_eq!(number(&vm, "smallest"), 1.0);
+    assert_eq!(number(&vm, "largest"), 3.0);
+    assert_eq!(number(&vm, "power"), 1024.0);
+    assert_eq!(number(&vm, "angle"), std::f64::consts::PI);
+    assert_eq!(number(&vm, "circle"), std::f64::consts::PI);
+}
+
+#[test]
+fn math_edge_cases() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var nan = math.sqrt(-1);
+        var isNan = math.isNan(nan);
+        var negInf = math.log(0);
+        var finite = math.isFinite(1 / 0);
+        var zeroPow = math.pow(0, 0);
+        var minNan = math.min(math.NAN, 2);
+        var signZero = math.sign(-0);
+        var signNeg = math.sign(-5);
+    "#})
+        .unwrap();
+
+    assert!(number(&vm, "nan").is_nan());
+    assert_eq!(vm.get_global("isNan"), Some(&Value::Bool(true)));
+    assert_eq!(number(&vm, "negInf"), f64::NEG_INFINITY);
+    assert_eq!(vm.get_global("finite"), Some(&Value::Bool(false)));
+    assert_eq!(number(&vm, "zeroPow"), 1.0);
+    assert_eq!(number(&vm, "minNan"), 2.0);
+    assert_eq!(number(&vm, "signZero"), 0.0);
+    assert_eq!(number(&vm, "signNeg"), -1.0);
+}
+
+#[test]
+fn math_argument_errors() {
+    let mut vm = std_vm();
+
+    let errors = vm.interpret(r#"math.sqrt("four");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)]
+            if msg == "math.sqrt() expected a number as argument 1 but got string."
+    ));
+
+    let errors = vm.interpret("math.pow(2);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "math.pow() expected 2 arguments but got 1."
+    ));
+
+    let errors = vm.interpret("math.max();").unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    let errors = vm.interpret("math.cube(2);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Undefined property math.cube."
+    ));
+}
+
+fn string(vm: &Vm, name: &str) -> String {
+    match vm.get_global(name) {
+        Some(Value::String(s)) => s.clone(),
+        other => panic!("{} is not a string: {:?}", name, other),
+    }
+}
+
+#[test]
+fn string_methods() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var s = "  Grüße, Welt  ";
+        var trimmed = s.trim();
+        var length = trimmed.len();
+        var upper = trimmed.upper();
+        var lower = trimmed.lower();
+        var sub = trimmed.substr(0, 5);
+        var rest = trimmed.substr(7);
+        var index = trimmed.indexOf("Welt");
+        var missing = trimmed.indexOf("moon");
+        var replaced = trimmed.replace("Welt", "World");
+        var starts = trimmed.startsWith("Grü");
+        var contains = trimmed.contains("ß");
+        var parts = "a,b,,c".split(",");
+        var partCount = parts.len();
+        var third = parts[2];
+        var chars = "añb".chars();
+        var second = chars[1];
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "trimmed"), "Grüße, Welt");
+    assert_eq!(number(&vm, "length"), 11.0);
+    assert_eq!(string(&vm, "upper"), "GRÜSSE, WELT");
+    assert_eq!(string(&vm, "lower"), "grüße, welt");
+    assert_eq!(string(&vm, "sub"), "Grüße");
+    assert_eq!(string(&vm, "rest"), "Welt");
+    assert_eq!(number(&vm, "index"), 7.0);
+    assert_eq!(number(&vm, "missing"), -1.0);
+    assert_eq!(string(&vm, "replaced"), "Grüße, World");
+    assert_eq!(vm.get_global("starts"), Some(&Value::Bool(true)));
+    assert_eq!(vm.get_global("contains"), Some(&Value::Bool(true)));
+    assert_eq!(number(&vm, "partCount"), 4.0);
+    assert_eq!(string(&vm, "third"), "");
+    assert_eq!(string(&vm, "second"), "ñ");
+}
+
+#[test]
+fn string_indexing() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var s = "héllo";
+        var first = s[0];
+        var second = s[1];
+        var last = s[s.len() - 1];
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "first"), "h");
+    assert_eq!(string(&vm, "second"), "é");
+    assert_eq!(string(&vm, "last"), "o");
+
+    let errors = vm.interpret(r#"var c = "abc"[3];"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Index 3 out of range for length 3."
+    ));
+
+    let errors = vm.interpret(r#"var c = "abc"[0.5];"#).unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    let errors = vm.interpret(r#""abc"[0] = "x";"#).unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    let errors = vm.interpret(r#""abc".reverse();"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Undefined method string.reverse."
+    ));
+}
+
+#[test]
+fn lists() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var list = [1, "two", [3]];
+        list[0] = list[0] + 10;
+        list.push(4);
+        var popped = list.pop();
+        var length = list.len();
+        var first = list[0];
+        var nested = list[2][0];
+    "#})
+        .unwrap();
+
+    assert_eq!(number(&vm, "popped"), 4.0);
+    assert_eq!(number(&vm, "length"), 3.0);
+    assert_eq!(number(&vm, "first"), 11.0);
+    assert_eq!(number(&vm, "nested"), 3.0);
+    assert_eq!(vm.get_global("list").unwrap().to_string(), "[11, two, [3]]");
+
+    // containers holding themselves are printed once, shared ones in full
+    vm.interpret(indoc::indoc! {r#"
+        var cycle = [1];
+        cycle.push(cycle);
+        var m = {"a": 1};
+        m["self"] = m;
+        m["list"] = cycle;
+        var shared = [cycle, cycle];
+        var text = str(m);
+        print cycle;
+    "#})
+        .unwrap();
+    assert_eq!(vm.get_global("cycle").unwrap().to_string(), "[1, [...]]");
+    assert_eq!(string(&vm, "text"), "{a: 1, self: {...}, list: [1, [...]]}");
+    assert_eq!(
+        vm.get_global("shared").unwrap().to_string(),
+        "[[1, [...]], [1, [...]]]"
+    );
+
+    // printing stops at a depth, dropping doesn't recurse at all
+    vm.interpret(indoc::indoc! {r#"
+        var deep = [];
+        for (var i = 0; i < 50000; i = i + 1) deep = [deep];
+        var text = str(deep);
+        deep = {"deep": deep};
+    "#})
+        .unwrap();
+    assert_eq!(
+        string(&vm, "text"),
+        format!("{}[...]{}", "[".repeat(512), "]".repeat(512))
+    );
+    drop(vm);
+}
+
+#[test]
+fn utf8_source() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        // kommentar mit ümlauten 🦀
+        var größe = "日本語";
+        var _private = 1;
+        var ñandú2 = größe + " ✓";
+        var line = 0;
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "ñandú2"), "日本語 ✓");
+    assert_eq!(number(&vm, "_private"), 1.0);
+
+    let errors = vm.interpret("// ü\n// ö\nvar € = 1;").unwrap_err();
+    assert!(matches!(&errors[0], Error::Compile(msg, 3) if msg.contains("Unexpected character.")));
+}
+
+#[test]
+fn scanner_spans() {
+    let source = "var ü = \"ß\"; // ✓\nprint ü;";
+    let mut scanner = Scanner::new(source);
+    let mut lexemes = Vec::new();
+
+    loop {
+        let token = scanner.scan_token();
+        if token.kind == TokenKind::Eof {
+            assert_eq!(token.offset, source.len());
+            break;
+        }
+        assert_eq!(&source[token.span()], token.lexeme);
+        lexemes.push((token.lexeme, token.line, token.offset));
+    }
+
+    assert_eq!(
+        lexemes,
+        vec![
+            ("var", 1, 0),
+            ("ü", 1, 4),
+            ("=", 1, 7),
+            ("\"ß\"", 1, 9),
+            (";", 1, 13),
+            ("print", 2, 22),
+            ("ü", 2, 28),
+            (";", 2, 30),
+        ]
+    );
+}
+
+/// xorshift so the fuzz inputs are the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.next() as usize % items.len()]
+    }
+}
+
+fn random_source(rng: &mut Rng, len: usize) -> String {
+    const PIECES: &[&str] = &[
+        "var", "fun", "class", "print", "(", ")", "{", "}", "[", "]", ";", ",", ".", "=", "==",
+        "!", "<", ">=", "+", "-", "*", "/", "//", "\"", " ", "\n", "\t", "0", "1.5", "x", "_", "é",
+        "ß", "日本", "🦀", "\u{200b}", "\u{301}", "€", "\u{7f}", "\0",
+    ];
+
+    let mut source = String::new();
+    for _ in 0..len {
+        if rng.next().is_multiple_of(4) {
+            source.push(char::from_u32(rng.next() as u32 % 0x11000).unwrap_or('?'));
+        } else {
+            source.push_str(rng.pick(PIECES));
+        }
+    }
+    source
+}
+
+#[test]
+fn scanner_fuzz() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+
+    for _ in 0..2000 {
+        let source = random_source(&mut rng, 40);
+        let mut scanner = Scanner::new(&source);
+        let mut last_offset = 0;
+        let mut last_line = 1;
+
+        loop {
+            let token = scanner.scan_token();
+            assert!(token.offset >= last_offset, "{:?}", source);
+            assert!(token.line >= last_line, "{:?}", source);
+            assert!(source.is_char_boundary(token.offset));
+            if token.kind != TokenKind::Error {
+                assert_eq!(&source[token.span()], token.lexeme);
+            }
+            if token.kind == TokenKind::Eof {
+                assert_eq!(token.offset, source.len());
+                break;
+            }
+            last_offset = token.offset;
+            last_line = token.line;
+        }
+
+        let _ = Compiler::new(&source, State::new("", FunctionKind::Script)).compile();
+    }
+}
+
+#[test]
+fn type_introspection() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        class Animal {}
+        class Dog < Animal {
+            bark() {}
+        }
+        fun f() {}
+        var dog = Dog();
+
+        var types = [
+            type(1), type("s"), type(true), type(nil), type(f), type(clock),
+            type(Dog), type(dog), type(dog.bark), type([]), type(math)
+        ];
+        var text = str(1.5) + str(true) + str([1, 2]);
+        var parsed = num(" 42.5 ") + num(1);
+        var isDog = instanceof(dog, Dog);
+        var isAnimal = instanceof(dog, Animal);
+        var animalIsDog = instanceof(Animal(), Dog);
+        var numberIsDog = instanceof(1, Dog);
+    "#})
+        .unwrap();
+
+    assert_eq!(
+        vm.get_global("types").unwrap().to_string(),
+        "[number, string, bool, nil, function, function, class, instance, function, list, module]"
+    );
+    assert_eq!(string(&vm, "text"), "1.5true[1, 2]");
+    assert_eq!(number(&vm, "parsed"), 43.5);
+    assert_eq!(vm.get_global("isDog"), Some(&Value::Bool(true)));
+    assert_eq!(vm.get_global("isAnimal"), Some(&Value::Bool(true)));
+    assert_eq!(vm.get_global("animalIsDog"), Some(&Value::Bool(false)));
+    assert_eq!(vm.get_global("numberIsDog"), Some(&Value::Bool(false)));
+
+    let errors = vm.interpret(r#"num("12abc");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "num() could not parse '12abc' as a number."
+    ));
+
+    for text in ["inf", "-infinity", "NaN", "1e999"] {
+        let errors = vm.interpret(&format!(r#"num("{}");"#, text)).unwrap_err();
+        let expected = format!("num() could not parse '{}' as a number.", text);
+        assert!(matches!(&errors[..], [Error::Runtime(msg, 1)] if *msg == expected));
+    }
+
+    let errors = vm.interpret("instanceof(1, 2);").unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    let errors = vm.interpret("type();").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "type() expected 1 arguments but got 0."
+    ));
+}
+
+#[test]
+fn reflection() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        class Shape {
+            area() { return 0; }
+        }
+        class Rect < Shape {
+            init(w, h) {
+                this.w = w;
+                this.h = h;
+            }
+            area() { return this.w * this.h; }
+        }
+
+        var rect = Rect(2, 3);
+        var fieldNames = fields(rect);
+        var hasW = hasField(rect, "w");
+        var hasDepth = hasField(rect, "depth");
+        setField(rect, "depth", 4);
+        var depth = getField(rect, "depth") + rect.depth;
+        var methodNames = methods(Rect);
+        var parent = className(superclass(Rect));
+        var root = superclass(Shape);
+        var name = className(rect);
+
+        var copy = Rect(0, 0);
+        var names = fields(rect);
+        for (var i = 0; i < names.len(); i = i + 1) {
+            setField(copy, names[i], getField(rect, names[i]));
+        }
+        var copiedArea = copy.area();
+    "#})
+        .unwrap();
+
+    assert_eq!(vm.get_global("fieldNames").unwrap().to_string(), "[h, w]");
+    assert_eq!(vm.get_global("hasW"), Some(&Value::Bool(true)));
+    assert_eq!(vm.get_global("hasDepth"), Some(&Value::Bool(false)));
+    assert_eq!(number(&vm, "depth"), 8.0);
+    assert_eq!(
+        vm.get_global("methodNames").unwrap().to_string(),
+        "[area, init]"
+    );
+    assert_eq!(string(&vm, "parent"), "Shape");
+    assert_eq!(vm.get_global("root"), Some(&Value::Nil));
+    assert_eq!(string(&vm, "name"), "Rect");
+    assert_eq!(number(&vm, "copiedArea"), 6.0);
+
+    let errors = vm
+        .interpret(r#"getField(Rect(1, 1), "missing");"#)
+        .unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Undefined field missing."
+    ));
+
+    let errors = vm.interpret("fields(Rect);").unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+}
+
+#[test]
+fn maps() {
+    let mut vm = std_vm();
+    vm.interpret(indoc::indoc! {r#"
+        var m = {"a": 1, "b": "two"};
+        m["c"] = [3];
+        m["a"] = m["a"] + 10;
+        var a = m["a"];
+        var missing = m["zzz"];
+        var len = m.len();
+        var keys = m.keys();
+        var hasB = m.has("b");
+        var removed = m.remove("b");
+        var after = m.len();
+        var empty = {};
+    "#})
+        .unwrap();
+
+    assert_eq!(number(&vm, "a"), 11.0);
+    assert_eq!(vm.get_global("missing"), Some(&Value::Nil));
+    assert_eq!(number(&vm, "len"), 3.0);
+    assert_eq!(vm.get_global("keys").unwrap().to_string(), "[a, b, c]");
+    assert_eq!(vm.get_global("hasB"), Some(&Value::Bool(true)));
+    assert_eq!(string(&vm, "removed"), "two");
+    assert_eq!(number(&vm, "after"), 2.0);
+    assert_eq!(vm.get_global("m").unwrap().to_string(), "{a: 11, c: [3]}");
+    assert_eq!(vm.get_global("empty").unwrap().to_string(), "{}");
+
+    let errors = vm.interpret("var bad = {1: 2};").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Map keys must be strings, got number."
+    ));
+}
+
+#[test]
+fn json_round_trip() {
+    let mut vm = std_vm();
+    vm.set_global(
+        "text",
+        Value::String(
+            r#"{"name": "lox", "tags": [1, 2.5, true, null], "nested": {"e": "\u00e9\n"}}"#
+                .to_string(),
+        ),
+    );
+    vm.interpret(indoc::indoc! {r#"
+        var value = json.parse(text);
+        var name = value["name"];
+        var second = value["tags"][1];
+        var e = value["nested"]["e"];
+        var compact = json.stringify(value);
+        var again = json.stringify(json.parse(compact));
+        var pretty = json.stringify([1, {"a": nil}], 2);
+
+        class Point { init(x, y) { this.y = y; this.x = x; } }
+        var point = json.stringify(Point(1, 2));
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "name"), "lox");
+    assert_eq!(number(&vm, "second"), 2.5);
+    assert_eq!(string(&vm, "e"), "é\n");
+    assert_eq!(
+        string(&vm, "compact"),
+        r#"{"name":"lox","tags":[1,2.5,true,null],"nested":{"e":"é\n"}}"#
+    );
+    assert_eq!(string(&vm, "again"), string(&vm, "compact"));
+    assert_eq!(
+        string(&vm, "pretty"),
+        "[\n  1,\n  {\n    \"a\": null\n  }\n]"
+    );
+    assert_eq!(string(&vm, "point"), r#"{"x":1,"y":2}"#);
+}
+
+#[test]
+fn json_errors() {
+    let mut vm = std_vm();
+    vm.set_global("text", Value::String("{\n  \"a\": [1, 2,]\n}".to_string()));
+    let errors = vm.interpret("json.parse(text);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "json.parse() Unexpected character ']' at line 2, column 14."
+    ));
+
+    let errors = vm.interpret(r#"json.parse("01");"#).unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    vm.set_global("text", Value::String("[".repeat(200000)));
+    let errors = vm.interpret("json.parse(text);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "json.parse() Nesting deeper than 512 levels at line 1, column 513."
+    ));
+
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            var nested = [];
+            for (var i = 0; i < 1000; i = i + 1) nested = [nested];
+            json.stringify(nested);
+        "#})
+        .unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 3)] if msg == "json.stringify() can't serialize nesting deeper than 512 levels."
+    ));
+
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            var list = [1];
+            list.push(list);
+            json.stringify(list);
+        "#})
+        .unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 3)] if msg == "json.stringify() can't serialize a cyclic structure."
+    ));
+
+    let errors = vm.interpret("json.stringify(clock);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "json.stringify() can't serialize a function."
+    ));
+}
+
+#[test]
+fn random_is_reproducible() {
+    let script = indoc::indoc! {r#"
+        random.seed(42);
+        var draws = [];
+        for (var i = 0; i < 20; i = i + 1) {
+            draws.push(random.randint(-3, 3));
+        }
+        var x = random.random();
+        var deck = [1, 2, 3, 4, 5, 6, 7, 8];
+        random.shuffle(deck);
+        var pick = random.choice(deck);
+    "#};
+
+    let mut a = std_vm();
+    a.interpret(script).unwrap();
+    let mut b = VmBuilder::new().build();
+    b.interpret(script).unwrap();
+
+    for name in ["draws", "x", "deck", "pick"] {
+        assert_eq!(
+            a.get_global(name).unwrap().to_string(),
+            b.get_global(name).unwrap().to_string()
+        );
+    }
+
+    let x = number(&a, "x");
+    assert!((0.0..1.0).contains(&x));
+    assert_eq!(number(&a, "x"), 0.0928600625172592);
+    let Some(Value::Obj(Obj::List(draws))) = a.get_global("draws") else {
+        panic!("draws is not a list");
+    };
+    assert!(draws.borrow().iter().all(|draw| matches!(
+        draw,
+        Value::Number(n) if (-3.0..=3.0).contains(n) && n.fract() == 0.0
+    )));
+    let Some(Value::Obj(Obj::List(deck))) = a.get_global("deck") else {
+        panic!("deck is not a list");
+    };
+    let mut sorted = deck
+        .borrow()
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    sorted.sort();
+    assert_eq!(sorted, ["1", "2", "3", "4", "5", "6", "7", "8"]);
+
+    let errors = a.interpret("random.choice([]);").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "random.choice() on an empty list."
+    ));
+    let errors = a.interpret("random.randint(2, 1);").unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+
+    // without the capability only seeded draws are allowed
+    let mut sandboxed = VmBuilder::new().build();
+    let errors = sandboxed.interpret("random.random();").unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'random' was not granted"
+    ));
+    let seeded = "random.seed(42); var first = random.random();";
+    sandboxed.interpret(seeded).unwrap();
+    a.interpret(seeded).unwrap();
+    assert_eq!(number(&sandboxed, "first"), number(&a, "first"));
+}
+
+/// Fresh directory under the system temp dir, removed when dropped
+struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rlox-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn sandboxed_vm(root: &std::path::Path) -> Vm {
+    VmBuilder::new()
+        .capabilities(Capabilities {
+            fs_roots: vec![root.to_path_buf()],
+            ..Capabilities::none()
+        })
+        .build()
+}
+
+#[test]
+fn file_io() {
+    let dir = TempDir::new("file-io");
+    std::fs::create_dir(dir.0.join("sub")).unwrap();
+    let mut vm = sandboxed_vm(&dir.0);
+
+    vm.interpret(indoc::indoc! {r#"
+        writeFile("report.txt", "first");
+        appendFile("report.txt", " line");
+        appendFile("sub/log.txt", "a");
+        var text = readFile("report.txt");
+        var lines = readLines("report.txt");
+        var there = exists("sub/log.txt");
+        var missing = exists("nope.txt");
+        var entries = listDir(".");
+        var nested = readFile("sub/../sub/log.txt");
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "text"), "first line");
+    assert_eq!(vm.get_global("lines").unwrap().to_string(), "[first line]");
+    assert_eq!(vm.get_global("there"), Some(&Value::Bool(true)));
+    assert_eq!(vm.get_global("missing"), Some(&Value::Bool(false)));
+    assert_eq!(
+        vm.get_global("entries").unwrap().to_string(),
+        "[report.txt, sub]"
+    );
+    assert_eq!(string(&vm, "nested"), "a");
+    assert_eq!(
+        std::fs::read_to_string(dir.0.join("report.txt")).unwrap(),
+        "first line"
+    );
+
+    let errors = vm.interpret(r#"readFile("nope.txt");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg.starts_with("readFile() failed for 'nope.txt'")
+    ));
+}
+
+#[test]
+fn file_io_sandbox() {
+    let dir = TempDir::new("file-sandbox");
+    let root = dir.0.join("root");
+    std::fs::create_dir(&root).unwrap();
+    std::fs::write(dir.0.join("secret.txt"), "secret").unwrap();
+    let mut vm = sandboxed_vm(&root);
+
+    let outside = dir.0.join("secret.txt");
+    for path in [
+        "../secret.txt",
+        "a/../../secret.txt",
+        outside.to_str().unwrap(),
+    ] {
+        vm.set_global("path", Value::String(path.to_string()));
+        let errors = vm.interpret("readFile(path);").unwrap_err();
+        assert!(
+            matches!(
+                &errors[..],
+                [Error::Runtime(msg, 1)] if msg.ends_with("is outside the sandbox.")
+            ),
+            "{} escaped: {:?}",
+            path,
+            errors
+        );
+    }
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&dir.0, root.join("link")).unwrap();
+        let errors = vm.interpret(r#"readFile("link/secret.txt");"#).unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [Error::Runtime(msg, 1)] if msg.ends_with("is outside the sandbox.")
+        ));
+    }
+
+    let mut vm = VmBuilder::new().build();
+    let errors = vm.interpret(r#"exists("a.txt");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'fs' was not granted"
+    ));
+}
+
+#[test]
+fn script_args_env_and_exit() {
+    let mut vm = VmBuilder::new()
+        .capabilities(Capabilities {
+            env: true,
+            ..Capabilities::none()
+        })
+        .args(vec!["one".to_string(), "--two".to_string()])
+        .env([("RLOX_TEST_ENV".to_string(), "value".to_string())])
+        .build();
+
+    vm.interpret(indoc::indoc! {r#"
+        var argv = args();
+        var set = env("RLOX_TEST_ENV");
+        var unset = env("RLOX_TEST_ENV_UNSET");
+    "#})
+        .unwrap();
+    assert_eq!(vm.get_global("argv").unwrap().to_string(), "[one, --two]");
+    assert_eq!(string(&vm, "set"), "value");
+    assert_eq!(vm.get_global("unset"), Some(&Value::Nil));
+
+    let errors = vm
+        .interpret(indoc::indoc! {r#"
+            fun quit() { exit(3); }
+            var before = 1;
+            quit();
+            var after = 1;
+        "#})
+        .unwrap_err();
+    assert!(matches!(&errors[..], [Error::Exit(3)]));
+    assert_eq!(crate::exit_code(&errors), 3);
+    assert_eq!(vm.get_global("before"), Some(&Value::Number(1.0)));
+    assert_eq!(vm.get_global("after"), None);
+    assert!(!vm.is_suspended());
+
+    let errors = vm.interpret("exit(1.5);").unwrap_err();
+    assert!(matches!(&errors[..], [Error::Runtime(_, 1)]));
+    assert_eq!(crate::exit_code(&errors), 70);
+    assert_eq!(crate::exit_code(&vm.interpret("var;").unwrap_err()), 65);
+
+    let mut vm = VmBuilder::new().build();
+    let errors = vm.interpret(r#"env("HOME");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'env' was not granted"
+    ));
+}
+
+#[test]
+fn read_input() {
+    let mut vm = VmBuilder::new()
+        .capabilities(Capabilities {
+            input: true,
+            ..Capabilities::none()
+        })
+        .input(std::io::Cursor::new("first\r\nsecond\nthird\nrest\nof it"))
+        .build();
+
+    vm.interpret(indoc::indoc! {r#"
+        var a = readLine();
+        var b = input("");
+        var c = readLine();
+        var rest = readAll();
+        var end = readLine();
+        var empty = readAll();
+    "#})
+        .unwrap();
+
+    assert_eq!(string(&vm, "a"), "first");
+    assert_eq!(string(&vm, "b"), "second");
+    assert_eq!(string(&vm, "c"), "third");
+    assert_eq!(string(&vm, "rest"), "rest\nof it");
+    assert_eq!(vm.get_global("end"), Some(&Value::Nil));
+    assert_eq!(string(&vm, "empty"), "");
+
+    let errors = vm.interpret(r#"input("name? ");"#).unwrap_err();
+    assert!(matches!(
+        &errors[..],
+        [Error::Runtime(msg, 1)] if msg == "Capability 'output' was not granted"
+    ));
+}
+
+#[test]
+fn format_matches_rust() {
+    let mut vm = std_vm();
+    let cases = [
+        ("{}", 1.5, format!("{}", 1.5)),
+        ("{:>8.2}", 3.14659, format!("{:>8.2}", 3.14659)),
+        ("{:<8.1}|", -2.25, format!("{:<8.1}|", -2.25)),
+        ("{:^9.3}", 2.0, format!("{:^9.3}", 2.0)),
+        ("{:*^9}", 42.0, format!("{:*^9}", 42.0)),
+        ("{:+}", 7.0, format!("{:+}", 7.0)),
+        ("{:+08.2}", 1.5, format!("{:+08.2}", 1.5)),
+        ("{:08.2}", -1.5, format!("{:08.2}", -1.5)),
+        ("{:x}", 255.0, format!("{:x}", 255)),
+        ("{:#X}", 255.0, format!("{:#X}", 255)),
+        ("{:#010x}", 42.0, format!("{:#010x}", 42)),
+        ("{:x}", -1.0, format!("{:x}", -1i64)),
+        ("{:#b}", 5.0, format!("{:#b}", 5)),
+        ("{:o}", 8.0, format!("{:o}", 8)),
+        ("{:e}", 1234.5, format!("{:e}", 1234.5)),
+        ("{:.2E}", 0.000123, format!("{:.2E}", 0.000123)),
+        ("{:>6}", f64::INFINITY, format!("{:>6}", f64::INFINITY)),
+        ("{:+}", f64::NAN, format!("{:+}", f64::NAN)),
+        ("{:.0}", 2.5, format!("{:.0}", 2.5)),
+    ];
+    for (template, value, expected) in cases {
+        vm.set_global("template", Value::String(template.to_string()))
+            .set_global("value", Value::Number(value));
+        vm.interpret("var out = format(template, value);").unwrap();
+        assert_eq!(string(&vm, "out"), expected, "{}", template);
+    }
+
+    vm.interpret(indoc::indoc! {r#"
+        var table = format("{:<6}|{:>5}|{:^7.3}|", "name", true, "abcdef");
+        var positional = format("{1} {0} {1} {{{}}}", "a", "b");
+        var fixed = toFixed(2.345, 1);
+        var whole = toFixed(-3, 2);
+    "#})
+        .unwrap();
+    assert_eq!(
+        string(&vm, "table"),
+        format!("{:<6}|{:>5}|{:^7.3}|", "name", true, "abcdef")
+    );
+    assert_eq!(
+        string(&vm, "positional"),
+        format!("{1} {0} {1} {{{}}}", "a", "b")
+    );
+    assert_eq!(string(&vm, "fixed"), format!("{:.1}", 2.345));
+    assert_eq!(string(&vm, "whole"), "-3.00");
+
+    for (source, message) in [
+        (
+            r#"format("{} {}", 1);"#,
+            "format() has no argument 1 for placeholder {}.",
+        ),
+        (
+            r#"format("{}", 1, 2);"#,
+            "format() argument 1 is never used.",
+        ),
+        (
+            r#"format("{:q}", 1);"#,
+            "format() invalid placeholder {:q}.",
+        ),
+        (
+            r#"format("{:x}", 1.5);"#,
+            "format() expected an integer for 'x' but got 1.5.",
+        ),
+        (
+            r#"format("{:x}", "s");"#,
+            "format() can't format a string with 'x'.",
+        ),
+        (
+            r#"format("{:99999999999}", 1);"#,
+            "format() invalid placeholder {:99999999999}.",
+        ),
+        (
+            r#"format("{", 1);"#,
+            "format() unterminated placeholder in template.",
+        ),
+    ] {
+        let errors = vm.interpret(source).unwrap_err();
+        assert!(
+            matches!(&errors[..], [Error::Runtime(msg, 1)] if msg == message),
+            "{}: {:?}",
+            source,
+            errors
+        );
+    }
+}
+
+#[derive(Default)]
+struct RecordingTracer {
+    events: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+}
+
+impl crate::vm::trace::Tracer for RecordingTracer {
+    fn instruction(
+        &mut self,
+        frame: &crate::vm::CallFrame,
+        ip: usize,
+        op: &crate::vm::opcode::OpCode,
+        stack: &[Value],
+    ) {
+        self.events.borrow_mut().push(format!(
+            "{}:{} {:?} [{}]",
+            frame.function().name,
+            ip,
+            op,
+            stack.len()
+        ));
+    }
+
+    fn call(&mut self, frame: &crate::vm::CallFrame) {
+        self.events
+            .borrow_mut()
+            .push(format!("call {}", frame.function().name));
+    }
+
+    fn ret(&mut self, frame: &crate::vm::CallFrame) {
+        self.events.borrow_mut().push(format!(
+            "return to {}:{}",
+            frame.function().name,
+            frame.ip()
+        ));
+    }
+}
+
+#[test]
+fn tracer_receives_events() {
+    let tracer = RecordingTracer::default();
+    let events = tracer.events.clone();
+    let mut vm = VmBuilder::new().tracer(tracer).build();
+
+    vm.interpret(indoc::indoc! {"
+        fun add(a, b) { return a + b; }
+        var sum = add(1, 2);
+    "})
+        .unwrap();
+
+    let recorded = events.borrow().clone();
+    let call = recorded.iter().position(|e| e == "call add").unwrap();
+    assert_eq!(recorded[call - 1], ":5 Call { arg_count: 2 } [4]");
+    assert_eq!(recorded[call + 1], "add:0 GetLocal { local: 1 } [4]");
+    assert!(recorded.contains(&"return to :6".to_string()));
+    assert_eq!(recorded.last().unwrap(), ":8 Return [2]");
+
+    vm.set_tracer(None);
+    vm.interpret("var x = 1;").unwrap();
+    assert_eq!(events.borrow().len(), recorded.len());
+}
+
+#[test]
+fn disassembly_and_trace_go_to_writers() {
+    let output = Captured::default();
+    let trace = Captured::default();
+    let mut vm = VmBuilder::new()
+        .capabilities(Capabilities::all())
+        .output(output.clone())
+        .tracer(crate::vm::trace::PrintTracer::new(trace.clone(), false))
+        .compiler_options(crate::compiler::CompilerOptions {
+            disassemble: true,
+            ..Default::default()
+        })
+        .build();
+    vm.interpret("print 1;").unwrap();
+
+    let printed = output.take();
+    assert!(
+        printed.starts_with("     >--< Entry Point\n"),
+        "{}",
+        printed
+    );
+    assert!(printed.ends_with("\n1\n"), "{}", printed);
+    let traced = trace.take();
+    assert!(traced.contains("Print"), "{}", traced);
+    assert!(
+        traced.contains(">>  <closure <script>>,  1, "),
+        "{}",
+        traced
+    );
+}
+
+#[test]
+fn check_reports_every_error() {
+    assert!(crate::check("var a = 1; print a;").is_ok());
+
+    let errors = crate::check(indoc::indoc! {"
+        var a = 1
+        print a;
+        fun f() { return +; }
+        var b = 2;
+    "})
+    .unwrap_err();
+    let lines = errors
+        .iter()
+        .map(|error| match error {
+            Error::Compile(_, line) => *line,
+            error => panic!("unexpected {:?}", error),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines[..2], [2, 3]);
+    assert_eq!(crate::exit_code(&errors), 65);
+}
+
+#[test]
+fn disassemble_everything() {
+    let source = indoc::indoc! {r#"
+        fun counter() {
+          var n = 0;
+          fun next() { n = n + 1; return n; }
+          return next;
+        }
+        var c = counter();
+        if (c() > 0) print "yes"; else print "no";
+    "#};
+    let expected = indoc::indoc! {"
+             >--< <script>
+        5    0000 Closure         1    '<fn counter>'
+             0001 DefineGlobal    0    'counter'
+        6    0002 GetGlobal       3    'counter'
+             0003 Call            (0 args)
+             0004 DefineGlobal    2    'c'
+        7    0005 GetGlobal       4    'c'
+             0006 Call            (0 args)
+             0007 Constant        5    '0'
+             0008 Greater
+             0009 JumpIfFalse     5    -> 0014
+             0010 Pop
+             0011 Constant        6    'yes'
+             0012 Print
+             0013 Jump            4    -> 0017
+             0014 Pop
+             0015 Constant        7    'no'
+             0016 Print
+        8    0017 Nil
+             0018 Return
+             >--<
+
+             >--< <fn counter>
+        2    0000 Constant        0    '0'
+        3    0001 Closure         1    '<fn next>'
+                  |                local 1
+        4    0002 GetLocal        slot 2
+             0003 Return
+        5    0004 Nil
+             0005 Return
+             >--<
+
+             >--< <fn next>
+        3    0000 GetUpValue      upvalue 0
+             0001 Constant        0    '1'
+             0002 Add
+             0003 SetUpValue      upvalue 0
+             0004 Pop
+             0005 GetUpValue      upvalue 0
+             0006 Return
+             0007 Nil
+             0008 Return
+             >--<
+    "};
+
+    assert_eq!(crate::disassemble(source, false, 0).unwrap(), expected);
+}
+
+#[test]
+fn optimizer_folds_constants() {
+    let source = indoc::indoc! {r#"
+        fun f() {
+            var a = 1 + 2 * 3;
+            while (true) {
+                print a;
+                return;
+            }
+        }
+        print !true == false;
+        print "a" + "b" + "a";
+        if (false) print "never"; else print -(4);
+        f();
+    "#};
+    let expected = indoc::indoc! {"
+             >--< <script>
+        7    0000 Closure         1    '<fn f>'
+             0001 DefineGlobal    0    'f'
+        8    0002 True
+             0003 Print
+        9    0004 Constant        3    'aba'
+             0005 Print
+        10   0006 Constant        2    '-4'
+             0007 Print
+        11   0008 GetGlobal       0    'f'
+             0009 Call            (0 args)
+             0010 Pop
+        12   0011 Nil
+             0012 Return
+             >--<
+
+             >--< <fn f>
+        2    0000 Constant        0    '7'
+        4    0001 GetLocal        slot 1
+             0002 Print
+        5    0003 Nil
+             0004 Return
+             >--<
+    "};
+    assert_eq!(crate::disassemble(source, false, 2).unwrap(), expected);
+}
+
+#[test]
+fn optimizer_preserves_semantics() {
+    let sources = [
+        indoc::indoc! {r#"
+            print 1 + 2 * 3 - 4 / 8;
+            print "con" + "cat" == "concat";
+            print !nil;
+            print -(2 - 5) > 2 and 0 or "zero is falsey";
+            print nil or false;
+            print 1 < 2 and "yes";
+            var s = "";
+            for (var i = 0; i < 3; i = i + 1) {
+                if (true) s = s + "x"; else s = s + "y";
+            }
+            print s;
+        "#},
+        indoc::indoc! {r#"
+            fun counter(step) {
+                var n = 0;
+                fun next() { n = n + step * (1 + 1); return n; }
+                return next;
+            }
+            var next = counter(3);
+            next();
+            while (false) print "never";
+            fun second() {
+                for (;;) {
+                    if (true) return next();
+                    print "never";
+                }
+            }
+            print second();
+        "#},
+        indoc::indoc! {r#"
+            class A { say() { return "a" + "!"; } }
+            class B < A {
+                init(n) { this.n = n * (2 + 2); return; }
+                say() { return super.say() + " and b"; }
+            }
+            var b = B(1 + 1);
+            print b.say();
+            print b.n;
+            print 1 + nil;
+        "#},
+    ];
+
+    for source in sources {
+        let results: Vec<_> = (0..=2)
+            .map(|optimize| {
+                let output = Captured::default();
+                let mut vm = VmBuilder::new()
+                    .capabilities(Capabilities::all())
+                    .output(output.clone())
+                    .compiler_options(crate::compiler::CompilerOptions {
+                        optimize,
+                        ..Default::default()
+                    })
+                    .build();
+                let result = vm
+                    .interpret(source)
+                    .map_err(|errors| format!("{:?}", errors));
+                let printed = output.take();
+                (printed, result.map(|_| ()))
+            })
+            .collect();
+        assert!(!results[0].0.is_empty());
+        assert_eq!(results[0], results[1], "{}", source);
+        assert_eq!(results[0], results[2], "{}", source);
+    }
+}
+
+#[test]
+fn debugger_steps_and_inspects() {
+    let mut debugger = Debugger::new();
+    debugger
+        .add_breakpoint(Breakpoint::Function("add".to_string()))
+        .add_breakpoint(Breakpoint::Line(9));
+    let mut vm = VmBuilder::new()
+        .capabilities(Capabilities::all())
+        .debugger(debugger)
+        .build();
+    let frame = |function: &str, line| FrameInfo {
+        function: function.to_string(),
+        line,
+    };
+    let variable = |name: &str, value| (name.to_string(), Value::Number(value));
+
+    let state = vm
+        .interpret(indoc::indoc! {"
+            fun add(a, b) {
+                var sum = a + b;
+                return sum;
+            }
+            fun makeCounter() {
+                var count = 0;
+                fun increment() {
+                    count = count + 1;
+                    return count;
+                }
+                return increment;
+            }
+            var counter = makeCounter();
+            counter();
+            var total = add(1, 2);
+            print total;
+        "})
+        .unwrap();
+    assert_eq!(state, RunState::Paused);
+    assert_eq!(vm.call_stack(), [frame("", 14), frame("increment", 9)]);
+    assert_eq!(vm.upvalues(1), [variable("count", 1.0)]);
+    assert!(vm.locals(1).is_empty());
+
+    vm.debugger_mut().unwrap().run();
+    assert_eq!(vm.resume().unwrap(), RunState::Paused);
+    assert_eq!(vm.call_stack(), [frame("", 15), frame("add", 2)]);
+    assert_eq!(vm.locals(1), [variable("a", 1.0), variable("b", 2.0)]);
+
+    vm.debugger_mut().unwrap().step(Step::Over);
+    assert_eq!(vm.resume().unwrap(), RunState::Paused);
+    assert_eq!(vm.call_stack(), [frame("", 15), frame("add", 3)]);
+    assert_eq!(vm.locals(1)[2], variable("sum", 3.0));
+
+    vm.debugger_mut().unwrap().step(Step::Out);
+    assert_eq!(vm.resume().unwrap(), RunState::Paused);
+    assert_eq!(vm.call_stack(), [frame("", 15)]);
+
+    vm.debugger_mut().unwrap().step(Step::Over);
+    assert_eq!(vm.resume().unwrap(), RunState::Paused);
+    assert_eq!(vm.call_stack(), [frame("", 16)]);
+    assert_eq!(vm.get_global("total"), Some(&Value::Number(3.0)));
+
+    vm.debugger_mut().unwrap().run();
+    assert_eq!(vm.resume().unwrap(), RunState::Finished);
+    assert!(vm.call_stack().is_empty());
+}
+
+#[test]
+fn analysis_resolves_names() {
+    let source = indoc::indoc! {"
+        var count = 0;
+        fun add(a, b) {
+            var sum = a + b;
+            for (var i = 0; i < 3; i = i + 1) print i;
+            return sum + later;
+        }
+        class Point < Base {
+            init(x, y) {
+                this.x = x;
+            }
+            norm() { return add(this.x, count); }
+        }
+        var later = add(1, 2);
+        { var count = 1; print count; }
+    "};
+    let analysis = Analysis::new(source);
+    let at = |text: &str, nth: usize| source.match_indices(text).nth(nth).unwrap().0;
+    let symbol = |text, nth| &analysis.symbols[analysis.symbol_at(at(text, nth)).unwrap()];
+    let uses = |text, nth| {
+        let symbol = analysis.symbol_at(at(text, nth)).unwrap();
+        analysis
+            .references_to(symbol)
+            .map(|span| span.start)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(symbol("add", 1).signature(), "fun add(a, b)");
+    assert_eq!(uses("add", 0), [at("add", 1), at("add", 2)]);
+    assert_eq!(uses("a", 4), [at("a +", 0)]);
+    assert_eq!(symbol("later", 0).span.start, at("later", 1));
+    assert_eq!(uses("count", 0), [at("count", 1)]);
+    assert_eq!(uses("count", 2), [at("count", 3)]);
+    assert_eq!(
+        uses("i", 2),
+        [
+            at("i <", 0),
+            at("i = i", 0),
+            at("i = i", 0) + 4,
+            at("i;", 0)
+        ]
+    );
+    assert!(analysis.symbol_at(at("x;", 0) - 1).is_none());
+
+    let point = analysis.symbol_at(at("Point", 0)).unwrap();
+    let methods: Vec<_> = analysis
+        .methods_of(point)
+        .map(|(_, method)| method.signature())
+        .collect();
+    assert_eq!(methods, ["init(x, y)", "norm()"]);
+
+    let visible = |offset| -> Vec<String> {
+        analysis
+            .visible_at(offset)
+            .iter()
+            .map(|symbol| symbol.name.clone())
+            .collect()
+    };
+    assert_eq!(
+        visible(at("return sum", 0)),
+        ["count", "add", "a", "b", "sum", "Point", "later"]
+    );
+    assert_eq!(
+        visible(at("print count", 0)),
+        ["add", "Point", "later", "count"]
+    );
+
+    let diagnostics = analysis::diagnostics("var a = 1;\nprint a +;\nvar 1;");
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].message, "Error at 'var': Expect expression.");
+    assert_eq!(diagnostics[0].span, 22..25);
+    assert!(analysis::diagnostics("print 1;").is_empty());
+}
+
+#[test]
+fn syntax_tree_is_lossless() {
+    let src = indoc::indoc! {r#"
+        // header
+        class A < B { m(x) { return super.m(x) ; } }
+        fun f( a ,b ){ if (a) print 1; else { @ print "s"; } } // trailing
+        for (var i = 0; i < 3; i = i + 1) print [1, {"k": 2}][0];
+        print 1 +
+    "#};
+    let script = syntax::parse(src);
+    assert_eq!(script.to_string(), src);
+    assert_eq!(script.nodes().count(), 4);
+    assert_eq!(syntax::parse("").to_string(), "");
+}
+
+#[test]
+fn parse_tree() {
+    let src = indoc::indoc! {r#"
+        class A < B { m(x) { return super.m(x); } }
+        for (var i = 0; i < 3; i = i + 1) print (a.b)(i) * -2;
+    "#};
+    let (script, errors) = parse(src);
+    assert!(errors.is_empty());
+    assert_eq!(script.statements.len(), 2);
+
+    let class = match &script.statements[0].kind {
+        StmtKind::Class(class) => class,
+        stmt => panic!("unexpected {:?}", stmt),
+    };
+    assert_eq!(class.name.name, "A");
+    assert_eq!(class.superclass.as_ref().unwrap().name, "B");
+    assert_eq!(class.methods[0].params[0].name, "x");
+    assert_eq!(
+        &src[class.methods[0].span.range()],
+        "m(x) { return super.m(x); }"
+    );
+    match &class.methods[0].body[0].kind {
+        StmtKind::Return(Some(value)) => match &value.kind {
+            ExprKind::Call { callee, arguments } => {
+                assert!(matches!(&callee.kind, ExprKind::Super { method } if method.name == "m"));
+                assert_eq!(arguments.len(), 1);
+            }
+            expr => panic!("unexpected {:?}", expr),
+        },
+        stmt => panic!("unexpected {:?}", stmt),
+    }
+
+    let (condition, body) = match &script.statements[1].kind {
+        StmtKind::For {
+            initializer: Some(_),
+            condition: Some(condition),
+            increment: Some(_),
+            body,
+        } => (condition, body),
+        stmt => panic!("unexpected {:?}", stmt),
+    };
+    assert_eq!(&src[condition.span.range()], "i < 3");
+    assert_eq!(&src[body.span.range()], "print (a.b)(i) * -2;");
+    match &body.kind {
+        StmtKind::Print(value) => match &value.kind {
+            ExprKind::Binary {
+                operator: BinaryOp::Multiply,
+                left,
+                right,
+            } => {
+                assert_eq!(&src[left.span.range()], "(a.b)(i)");
+                assert!(matches!(&left.kind, ExprKind::Call { callee, .. }
+                    if matches!(callee.kind, ExprKind::Grouping(_))));
+                assert_eq!(right.span, ast::Span::new(95, 97));
+            }
+            expr => panic!("unexpected {:?}", expr),
+        },
+        stmt => panic!("unexpected {:?}", stmt),
+    }
+}
+
+#[test]
+fn parse_recovers_from_errors() {
+    let src = indoc::indoc! {"
+        var = 1;
+        print 1 +;
+        fun f() { return; }
+        print 2;
+    "};
+    let (script, errors) = parse(src);
+    let found: Vec<_> = errors
+        .iter()
+        .map(|error| match &error.error {
+            Error::Compile(message, _) => (&src[error.span.range()], message.as_str()),
+            error => panic!("unexpected {:?}", error),
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                "=",
+                "[line 1, <script>] Error at '=': Expect variable name.\n"
+            ),
+            (
+                "fun",
+                "[line 3, <script>] Error at 'fun': Expect expression.\n"
+            ),
+        ]
+    );
+    // parsing goes on at the next statement after an error
+    assert_eq!(script.statements.len(), 4);
+    assert!(matches!(
+        script.statements[3].kind,
+        StmtKind::Print(ast::Expr { kind: ExprKind::Number(n), .. }) if n == 2.0
+    ));
+}
+
+#[test]
+fn format_source() {
+    let src = indoc::indoc! {r#"
+        // header
+
+
+        var a=-1 ; // a
+        class A<B{init(x){this.x=x;}
+        m(){return super.m ( ) ;}}
+        fun f(a,b)
+        {
+          // first
+          if(!a) print a*-b; else if (b) { print {"k":[1,2]}["k"] ; }
+          else {}
+          if (a) print 1; // t
+          else print 2;
+          if (a) {} // u
+          else print 3;
+
+          while (a and
+            // why
+            b) a = a - 1;
+          // last
+        }
+        for(var i=0;i<3;i=i+1){print i;}
+        for (;;) {}
+    "#};
+    let expected = indoc::indoc! {r#"
+        // header
+
+        var a = -1; // a
+        class A < B {
+            init(x) {
+                this.x = x;
+            }
+            m() {
+                return super.m();
+            }
+        }
+        fun f(a, b) {
+            // first
+            if (!a) print a * -b;
+            else if (b) {
+                print {"k": [1, 2]}["k"];
+            } else {}
+            if (a) print 1; // t
+            else print 2;
+            if (a) {} // u
+            else print 3;
+
+            while (a and // why
+                b) a = a - 1;
+            // last
+        }
+        for (var i = 0; i < 3; i = i + 1) {
+            print i;
+        }
+        for (;;) {}
+    "#};
+    let formatted = format(src, &FormatOptions::default()).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(
+        format(&formatted, &FormatOptions::default()).unwrap(),
+        expected
+    );
+
+    assert_eq!(
+        format("{print 1;}", &FormatOptions { indent: 2 }).unwrap(),
+        "{\n  print 1;\n}\n"
+    );
+    assert!(format("print 1 +;", &FormatOptions::default()).is_err());
+}
+
+#[test]
+fn lint_warnings() {
+    let src = indoc::indoc! {r#"
+        fun add(a, b, _c) {
+            var unused = 1;
+            return a + b;
+            print "never";
+        }
+        var a = add(1, 2, 3);
+        add(1, 2, 3, 4);
+        {
+            var a = 1;
+            print a == a;
+            print a < b < 3;
+            print a > "b";
+        }
+        if (a = 2) print a;
+        missing = 1;
+        class Point {
+            init(x) {
+                this.x = x;
+                this.norm = 0;
+            }
+            norm() {
+                return this.x + this.y;
+            }
+        }
+        Point();
+    "#};
+    let warnings = lint(src).unwrap();
+    let found: Vec<_> = warnings
+        .iter()
+        .map(|warning| (warning.line, warning.code.code()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (2, "W001"),
+            (4, "W003"),
+            (7, "W007"),
+            (9, "W004"),
+            (10, "W008"),
+            (11, "W008"),
+            (12, "W008"),
+            (14, "W008"),
+            (15, "W005"),
+            (19, "W006"),
+            (22, "W006"),
+            (25, "W007"),
+        ]
+    );
+    assert_eq!(
+        warnings[2].to_string(),
+        "[line 7] Warning W007 (wrong-arity): 'add' expects 3 arguments but got 4."
+    );
+    assert_eq!(
+        warnings[9].message,
+        "Assigning 'this.norm' hides the method 'norm'."
+    );
+    assert_eq!(
+        LintCode::parse("unused-parameter"),
+        Some(LintCode::UnusedParameter)
+    );
+
+    let src = indoc::indoc! {"
+        fun f(a) { // allow(unused-parameter)
+            // allow(W001, shadowed-variable)
+            var f = 1;
+            var b = 2;
+        }
+    "};
+    let warnings = lint(src).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, LintCode::UnusedVariable);
+    assert_eq!(warnings[0].line, 4);
+    assert!(lint("print 1 +;").is_err());
+
+    // a closure in a method sees the method's instance
+    let src = indoc::indoc! {"
+        class A {
+            init() {
+                this.x = 1;
+            }
+            m() {
+                fun f() {
+                    return this.x;
+                }
+                return f;
+            }
+        }
+        print A().m()();
+    "};
+    assert!(lint(src).unwrap().is_empty());
+
+    let warnings = lint("fun g() {\n    while (true) { return 1; print 2; }\n}\n").unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, LintCode::UnreachableCode);
+}
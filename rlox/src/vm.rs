@@ -1,24 +1,45 @@
+pub mod builder;
 pub mod chunk;
+pub mod debug;
+pub mod limits;
 pub mod object;
 pub mod opcode;
+pub mod trace;
 pub mod value;
 
+use crate::compiler::{Compiler, CompilerOptions, FunctionKind, State};
 use crate::error::*;
-use colored::Colorize;
+use crate::rlox_std;
 use indexmap::IndexMap;
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::Write,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use crate::vm::{
-    chunk::{disassemble_instruction, Chunk},
+    builder::Capabilities,
+    chunk::Chunk,
+    debug::Debugger,
+    limits::{allocation_size, HeapMeter, Limits},
     opcode::OpCode,
+    trace::Tracer,
     value::Value,
 };
 
-use self::object::{BoundMethod, Class, Closure, FunDescriptor, Instance, NativeFun, Obj};
+use self::object::{BoundMethod, Class, Closure, FunDescriptor, Instance, Module, NativeFun, Obj};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Where `print` and the prompt of `input` write, shared with the natives
+pub type Output = Rc<RefCell<Box<dyn Write>>>;
+
 macro_rules! stack_operands {
     ( $opcode:literal, $vec:expr $(, $name:ident)+ ) => {
         $(
@@ -32,53 +53,216 @@ pub struct Vm {
     globals: HashMap<String, Value>,
     frames: Vec<CallFrame>,
     open_upvalues: IndexMap<usize, Rc<RefCell<Value>>>,
+    limits: Limits,
+    fuel: Option<u64>,
+    bytes_allocated: usize,
+    next_heap_check: usize,
+    yield_interval: Option<u64>,
+    interrupt: Arc<AtomicBool>,
+    capabilities: Capabilities,
+    tracer: Option<Box<dyn Tracer>>,
+    debugger: Option<Debugger>,
+    output: Output,
+    compiler_options: CompilerOptions,
+}
+
+/// Outcome of [`Vm::execute`] and [`Vm::resume`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Finished,
+    /// Execution paused, the call frames and stack are kept until [`Vm::resume`]
+    Suspended,
+    /// Stopped by the [`Debugger`] at a breakpoint or after a step, resumable like a suspension
+    Paused,
 }
 
+/// Bytes allocated before the live heap is measured for the first time
+const FIRST_HEAP_CHECK: usize = 1024 * 1024;
+/// Lower bound between heap measurements so a nearly full heap isn't walked every instruction
+const MIN_HEAP_CHECK: usize = 4 * 1024;
+
 impl Vm {
+    /// Bare vm without any natives that may use every capability,
+    /// [`builder::VmBuilder`] sets up the standard library for a set of capabilities
     pub fn new() -> Self {
         Self {
             stack: Vec::new(),
             globals: HashMap::new(),
             frames: Vec::new(),
             open_upvalues: IndexMap::new(),
+            limits: Limits::default(),
+            fuel: None,
+            bytes_allocated: 0,
+            next_heap_check: FIRST_HEAP_CHECK,
+            yield_interval: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            capabilities: Capabilities::all(),
+            tracer: None,
+            debugger: None,
+            output: Rc::new(RefCell::new(Box::new(std::io::stdout()))),
+            compiler_options: CompilerOptions::default(),
         }
     }
 
-    pub fn execute(&mut self, function: FunDescriptor) -> Result<()> {
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Suspend execution every `instructions` executed instructions
+    pub fn set_yield_interval(&mut self, instructions: Option<u64>) -> &mut Self {
+        self.yield_interval = instructions.map(|n| n.max(1));
+        self
+    }
+
+    /// Share a flag that suspends execution once it's set,
+    /// the flag is cleared again when the vm suspends
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) -> &mut Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Continue a suspended execution where it left off
+    pub fn resume(&mut self) -> Result<RunState> {
+        if self.frames.is_empty() {
+            return Ok(RunState::Finished);
+        }
+
+        self.run_to_suspension()
+    }
+
+    /// Receive an event for every executed instruction, `None` turns tracing off
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) -> &mut Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Write what scripts print to `output` instead of stdout
+    pub fn set_output(&mut self, output: impl Write + 'static) -> &mut Self {
+        *self.output.borrow_mut() = Box::new(output);
+        self
+    }
+
+    pub fn output(&self) -> Output {
+        self.output.clone()
+    }
+
+    /// Options for compiling the sources given to [`Vm::interpret`]
+    pub fn set_compiler_options(&mut self, options: CompilerOptions) -> &mut Self {
+        self.compiler_options = options;
+        self
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Instructions left from the budget of the last execution
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn interpret(&mut self, source: &str) -> Result<RunState, Vec<Error>> {
+        let mut compiler = Compiler::new(source, State::new("", FunctionKind::Script))
+            .with_options(self.compiler_options);
+        let function = compiler.compile()?;
+        if !compiler.disassembly().is_empty() {
+            write!(self.output.borrow_mut(), "{}", compiler.disassembly())
+                .map_err(|e| vec![Error::Io(e.to_string())])?;
+        }
+        self.execute(function).map_err(|e| vec![e])
+    }
+
+    pub fn execute(&mut self, function: FunDescriptor) -> Result<RunState> {
+        // a previous run may have bailed out with a runtime error midway
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.fuel = self.limits.fuel;
+        self.bytes_allocated = 0;
+        self.next_heap_check = self
+            .limits
+            .max_heap
+            .map_or(FIRST_HEAP_CHECK, |max| max.min(FIRST_HEAP_CHECK));
+        if let Some(debugger) = self.debugger.as_mut() {
+            debugger.reset();
+        }
+
         let func_rc = Rc::new(function);
         let closure_rc = Rc::new(Closure::new(Vec::new(), func_rc));
         self.frames.push(CallFrame::new(closure_rc.clone(), 0));
         self.stack.push(Value::Obj(Obj::Closure(closure_rc)));
 
-        self.run()
+        self.run_to_suspension()
     }
 
-    fn run(&mut self) -> Result<()> {
+    fn run_to_suspension(&mut self) -> Result<RunState> {
+        let state = self.run();
+        if state.is_err() {
+            // a failed execution can't be resumed
+            self.frames.clear();
+        }
+        state
+    }
+
+    fn run(&mut self) -> Result<RunState> {
         let mut frame = self
             .frames
             .last_mut()
             .ok_or(Error::EmptyStack("rlox vm".to_string()))?
             .clone();
         let mut chunk = &frame.closure.function.chunk;
-        let _exit = false;
+        let mut until_yield = self.yield_interval;
 
         loop {
             let absolute_ip = frame.slot + frame.ip;
             let instruction: OpCode = chunk.get_op(frame.ip);
 
-            if cfg!(trace_exec) {
-                let mut out = String::new();
-                disassemble_instruction(&mut out, chunk, frame.ip)
-                    .map_err(|_| Error::Runtime("Could not disassemble".to_string(), 0))?;
-
-                print!("{}>> ", out);
-                if self.stack.len() > 5 {
-                    print!(" ... ",);
+            if let Some(debugger) = self.debugger.as_mut() {
+                if debugger.should_pause(self.frames.len(), &frame, chunk.get_line(frame.ip)) {
+                    let len = self.frames.len();
+                    self.frames[len - 1] = frame;
+                    return Ok(RunState::Paused);
                 }
-                for value in self.stack[self.stack.len().saturating_sub(5)..].iter() {
-                    print!(" {}, ", value.to_string().replace('\n', "\\n").green());
+            }
+            if until_yield == Some(0)
+                || (self.interrupt.load(Ordering::Relaxed)
+                    && self.interrupt.swap(false, Ordering::Relaxed))
+            {
+                let len = self.frames.len();
+                self.frames[len - 1] = frame;
+                return Ok(RunState::Suspended);
+            }
+            if let Some(until_yield) = until_yield.as_mut() {
+                *until_yield -= 1;
+            }
+
+            if let Some(fuel) = self.fuel.as_mut() {
+                if *fuel == 0 {
+                    return Err(Error::OutOfFuel(chunk.get_line(frame.ip)));
                 }
-                println!();
+                *fuel -= 1;
+            }
+
+            if self.bytes_allocated > self.next_heap_check {
+                self.check_heap(&frame)
+                    .map_err(|max| Error::OutOfMemory(max, chunk.get_line(frame.ip)))?;
+            }
+
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.instruction(&frame, frame.ip, &instruction, &self.stack);
             }
 
             match instruction {
@@ -163,7 +347,7 @@ impl Vm {
                         .ok_or(Error::EmptyStack("OpCode::SetUpValue".to_string()))?
                         .clone();
                 }
-                OpCode::GetProperty { prop_name } => match self.stack.last().cloned() {
+                OpCode::GetProperty { prop_name } => match &self.stack.last().cloned() {
                     Some(Value::Obj(Obj::Instance(instance))) => {
                         let name = Self::identifier(chunk.get_constant(prop_name));
                         if let Some(value) = instance.borrow().fields.get(&name) {
@@ -179,6 +363,18 @@ impl Vm {
                             )?;
                         }
                     }
+                    Some(Value::Obj(Obj::Module(module))) => {
+                        let name = Self::identifier(chunk.get_constant(prop_name));
+                        if let Some(value) = module.members.get(&name) {
+                            self.stack.pop();
+                            self.stack.push(value.clone());
+                        } else {
+                            Self::error(
+                                format!("Undefined property {}.{}.", module.name, name),
+                                chunk.get_line(frame.ip),
+                            )?
+                        }
+                    }
                     _ => Self::error(
                         "Only instances have properties.",
                         frame.closure.function.chunk.get_line(absolute_ip),
@@ -187,7 +383,8 @@ impl Vm {
                 OpCode::SetProperty { prop_name } => {
                     stack_operands!("OpCode::SetProperty", self.stack, value, instance);
 
-                    match instance {
+                    self.track_allocation(&value);
+                    match &instance {
                         Value::Obj(Obj::Instance(instance)) => {
                             instance.borrow_mut().fields.insert(
                                 Self::identifier(chunk.get_constant(prop_name)),
@@ -206,16 +403,16 @@ impl Vm {
                 OpCode::GetSuper { name } => {
                     stack_operands!("OpCode::GetSuper", self.stack, superclass, receiver);
 
-                    match (superclass, receiver) {
+                    match (&superclass, &receiver) {
                         (
                             Value::Obj(Obj::Class(superclass)),
                             Value::Obj(Obj::Instance(receiver)),
                         ) => self.method(
-                            superclass,
+                            superclass.clone(),
                             Self::identifier(chunk.get_constant(name)),
                             chunk,
                             absolute_ip,
-                            Some(receiver),
+                            Some(receiver.clone()),
                         ),
                         _ => {
                             Self::error("Super only works for a instance", chunk.get_line(frame.ip))
@@ -236,7 +433,9 @@ impl Vm {
                 }
                 OpCode::Add => {
                     stack_operands!("OpCode::Add", self.stack, b, a);
-                    self.stack.push((a + b)?);
+                    let result = (a + b)?;
+                    self.track_allocation(&result);
+                    self.stack.push(result);
                 }
                 OpCode::Subtract => {
                     stack_operands!("OpCode::Subtract", self.stack, b, a);
@@ -260,19 +459,15 @@ impl Vm {
                 }
                 OpCode::Print => {
                     stack_operands!("OpCode::Print", self.stack, a);
-                    let mut a = a.to_string();
-
-                    if cfg!(trace_exec) {
-                        println!("{}", "     -----Print-----".magenta());
-                        a.insert_str(0, "     ");
-                        a = a.replace('\n', "\n     ");
-                    }
-
-                    println!("{}", a);
-
-                    if cfg!(trace_exec) {
-                        println!("{}", "     ---------------".magenta());
+                    if !self.capabilities.output {
+                        Self::error(
+                            "Capability 'output' was not granted",
+                            chunk.get_line(frame.ip),
+                        )?;
                     }
+                    writeln!(self.output.borrow_mut(), "{}", a).or_else(|e| {
+                        Self::error(format!("Can't print: {}.", e), chunk.get_line(frame.ip))
+                    })?;
                 }
                 OpCode::Jump { offset } => {
                     frame.ip += offset;
@@ -296,10 +491,9 @@ impl Vm {
                 OpCode::Call { arg_count } => {
                     let len = self.frames.len();
                     frame.ip += 1;
-                    let ip = frame.ip;
                     self.frames[len - 1] = frame;
 
-                    let err = self.call_value(arg_count, ip);
+                    let err = self.call_value(arg_count);
                     frame = self
                         .frames
                         .last_mut()
@@ -307,7 +501,7 @@ impl Vm {
                         .clone();
                     chunk = &frame.closure.function.chunk;
 
-                    err.map_err(|e| Error::Runtime(e, chunk.get_line(frame.ip)))?;
+                    err.map_err(|e| Self::located(e, chunk.get_line(frame.ip)))?;
                     continue;
                 }
                 OpCode::CloseUpValue => {
@@ -335,15 +529,8 @@ impl Vm {
                             .last_mut()
                             .ok_or(Error::EmptyStack("OpCode::Return".to_string()))?
                             .clone();
-                        if cfg!(trace_exec) {
-                            println!(
-                                "{}",
-                                format!(
-                                    "\n      Returned to: {}[{:04}]\n",
-                                    frame.closure.function, frame.ip
-                                )
-                                .magenta()
-                            );
+                        if let Some(tracer) = self.tracer.as_mut() {
+                            tracer.ret(&frame);
                         }
                         chunk = &frame.closure.function.chunk;
                     }
@@ -351,42 +538,32 @@ impl Vm {
                     continue;
                 }
                 OpCode::Invoke { method, arg_count } => {
-                    let index = self.stack.len() - arg_count - 1;
-                    if let Some(Value::Obj(Obj::Instance(receiver))) =
-                        self.stack.get(index).cloned()
-                    {
-                        let name = Self::identifier(chunk.get_constant(method));
-                        if let Some(method) = receiver.borrow().class.borrow().methods.get(&name) {
-                            frame.ip += 1;
-                            let len = self.frames.len();
-                            self.frames[len - 1] = frame;
-                            self.call(method.clone(), index);
+                    let name = Self::identifier(chunk.get_constant(method));
+                    frame.ip += 1;
+                    let len = self.frames.len();
+                    self.frames[len - 1] = frame;
 
-                            frame = self
-                                .frames
-                                .last_mut()
-                                .ok_or(Error::EmptyStack("OpCode::Call".to_string()))?
-                                .clone();
-                            chunk = &frame.closure.function.chunk;
-                            continue;
-                        }
-                    } else {
-                        Self::error(
-                            "Invoke only on instances",
-                            frame.closure.function.chunk.get_line(absolute_ip),
-                        )?;
-                    }
+                    let err = self.invoke(&name, arg_count);
+                    frame = self
+                        .frames
+                        .last_mut()
+                        .ok_or(Error::EmptyStack("OpCode::Invoke".to_string()))?
+                        .clone();
+                    chunk = &frame.closure.function.chunk;
+
+                    err.map_err(|e| Self::located(e, chunk.get_line(frame.ip)))?;
+                    continue;
                 }
                 OpCode::SuperInvoke { method, arg_count } => {
                     stack_operands!("OpCode::SuperInvoke", self.stack, superclass);
 
-                    if let Value::Obj(Obj::Class(superclass)) = superclass {
+                    if let Value::Obj(Obj::Class(superclass)) = &superclass {
                         let name = Self::identifier(chunk.get_constant(method));
                         if let Some(method) = superclass.borrow().methods.get(&name) {
                             frame.ip += 1;
                             let len = self.frames.len();
                             self.frames[len - 1] = frame;
-                            self.call(method.clone(), self.stack.len() - arg_count - 1);
+                            let err = self.call(method.clone(), self.stack.len() - arg_count - 1);
 
                             frame = self
                                 .frames
@@ -394,6 +571,8 @@ impl Vm {
                                 .ok_or(Error::EmptyStack("OpCode::SuperInvoke".to_string()))?
                                 .clone();
                             chunk = &frame.closure.function.chunk;
+
+                            err.map_err(|e| Self::located(e.into(), chunk.get_line(frame.ip)))?;
                             continue;
                         }
                     } else {
@@ -404,17 +583,22 @@ impl Vm {
                     }
                 }
                 OpCode::Closure { func } => {
-                    if let Value::Obj(Obj::Fun(func)) = chunk.get_constant(func) {
-                        let closure =
-                            Closure::new(self.open_upvalues(frame.closure.clone(), &func), func);
-                        self.stack.push(Value::Obj(Obj::Closure(Rc::new(closure))));
+                    if let Value::Obj(Obj::Fun(func)) = &chunk.get_constant(func) {
+                        let closure = Closure::new(
+                            self.open_upvalues(frame.closure.clone(), func),
+                            func.clone(),
+                        );
+                        let closure = Value::Obj(Obj::Closure(Rc::new(closure)));
+                        self.track_allocation(&closure);
+                        self.stack.push(closure);
                     }
                 }
                 OpCode::Class { name } => {
-                    self.stack
-                        .push(Value::Obj(Obj::Class(Class::new(Self::identifier(
-                            chunk.get_constant(name),
-                        )))))
+                    let class = Value::Obj(Obj::Class(Class::new(Self::identifier(
+                        chunk.get_constant(name),
+                    ))));
+                    self.track_allocation(&class);
+                    self.stack.push(class);
                 }
                 OpCode::Inerhit => {
                     stack_operands!("OpCode::Inerhit", self.stack, subclass);
@@ -423,12 +607,11 @@ impl Vm {
                         .last_mut()
                         .ok_or(Error::EmptyStack("OpCode::Inerhit".to_string()))?;
                     if let (Value::Obj(Obj::Class(subclass)), Value::Obj(Obj::Class(superclass))) =
-                        (subclass, superclass)
+                        (&subclass, superclass)
                     {
-                        subclass
-                            .borrow_mut()
-                            .methods
-                            .extend(superclass.borrow_mut().methods.clone());
+                        let mut subclass = subclass.borrow_mut();
+                        subclass.methods.extend(superclass.borrow().methods.clone());
+                        subclass.superclass = Some(superclass.clone());
                     } else {
                         Self::error(
                             "Superclass must be a class.",
@@ -439,21 +622,83 @@ impl Vm {
                 OpCode::Method { name } => {
                     stack_operands!("OpCode::Method", self.stack, method);
 
-                    if let Some(Value::Obj(Obj::Class(class))) = self.stack.last_mut().cloned() {
-                        if let Value::Obj(Obj::Closure(method)) = method {
+                    if let Some(Value::Obj(Obj::Class(class))) = &self.stack.last_mut().cloned() {
+                        if let Value::Obj(Obj::Closure(method)) = &method {
                             class
                                 .borrow_mut()
                                 .methods
-                                .insert(Self::identifier(chunk.get_constant(name)), method);
+                                .insert(Self::identifier(chunk.get_constant(name)), method.clone());
+                        }
+                    }
+                }
+                OpCode::BuildList { count } => {
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    let list = Value::Obj(Obj::list(items));
+                    self.track_allocation(&list);
+                    self.stack.push(list);
+                }
+                OpCode::BuildMap { count } => {
+                    let items = self.stack.split_off(self.stack.len() - count * 2);
+                    let mut entries = IndexMap::with_capacity(count);
+                    for pair in items.chunks(2) {
+                        match &pair[0] {
+                            Value::String(key) => {
+                                entries.insert(key.clone(), pair[1].clone());
+                            }
+                            key => Self::error(
+                                format!("Map keys must be strings, got {}.", key.type_name()),
+                                chunk.get_line(frame.ip),
+                            )?,
+                        }
+                    }
+
+                    let map = Value::Obj(Obj::map(entries));
+                    self.track_allocation(&map);
+                    self.stack.push(map);
+                }
+                OpCode::GetIndex => {
+                    stack_operands!("OpCode::GetIndex", self.stack, index, target);
+
+                    let value = match &target {
+                        Value::Obj(Obj::List(list)) => {
+                            let list = list.borrow();
+                            rlox_std::index(&index, list.len()).map(|i| list[i].clone())
+                        }
+                        Value::String(s) => rlox_std::string::char_at(s, &index),
+                        Value::Obj(Obj::Map(map)) => rlox_std::map::key(&index)
+                            .map(|key| map.borrow().get(key).cloned().unwrap_or(Value::Nil)),
+                        _ => Err(format!("Can't index into a {}.", target.type_name())),
+                    }
+                    .map_err(|e| Error::Runtime(e, chunk.get_line(frame.ip)))?;
+
+                    self.stack.push(value);
+                }
+                OpCode::SetIndex => {
+                    stack_operands!("OpCode::SetIndex", self.stack, value, index, target);
+
+                    match &target {
+                        Value::Obj(Obj::List(list)) => {
+                            let mut list = list.borrow_mut();
+                            rlox_std::index(&index, list.len()).map(|i| list[i] = value.clone())
                         }
+                        Value::Obj(Obj::Map(map)) => rlox_std::map::key(&index).map(|key| {
+                            map.borrow_mut().insert(key.to_string(), value.clone());
+                        }),
+                        _ => Err(format!(
+                            "Can't assign to an index of a {}.",
+                            target.type_name()
+                        )),
                     }
+                    .map_err(|e| Error::Runtime(e, chunk.get_line(frame.ip)))?;
+
+                    self.stack.push(value);
                 }
             }
 
             frame.ip += 1;
         }
 
-        Ok(())
+        Ok(RunState::Finished)
     }
 
     fn identifier(value: Value) -> String {
@@ -467,69 +712,169 @@ impl Vm {
         Err(Error::Runtime(message.into(), line))
     }
 
-    fn call(&mut self, method: Rc<Closure>, slot: usize) {
-        if cfg!(trace_exec) {
-            println!(
-                "{}",
-                format!("\n      Called: {}()\n", method.function).magenta()
-            );
+    fn track_allocation(&mut self, value: &Value) {
+        self.bytes_allocated += allocation_size(value);
+    }
+
+    /// Measures everything reachable from the vm roots, errors with the
+    /// configured maximum if the live heap grew past it
+    fn check_heap(&mut self, frame: &CallFrame) -> Result<(), usize> {
+        let Some(max_heap) = self.limits.max_heap else {
+            self.next_heap_check = usize::MAX;
+            return Ok(());
+        };
+
+        let mut meter = HeapMeter::new();
+        meter.closure(&frame.closure);
+        for frame in self.frames.iter() {
+            meter.closure(&frame.closure);
+        }
+        for value in self.stack.iter().chain(self.globals.values()) {
+            meter.value(value);
+        }
+        for upvalue in self.open_upvalues.values() {
+            meter.upvalue(upvalue);
+        }
+
+        let live = meter.size();
+        if live > max_heap {
+            return Err(max_heap);
+        }
+
+        // nothing can go over the limit before this many bytes are allocated
+        self.bytes_allocated = 0;
+        self.next_heap_check = (max_heap - live).max(MIN_HEAP_CHECK);
+        Ok(())
+    }
+
+    /// Failures reported as plain messages become runtime errors on `line`,
+    /// anything else like an `exit()` unwinds unchanged
+    fn located(error: Error, line: usize) -> Error {
+        match error {
+            Error::Native(message) => Error::Runtime(message, line),
+            error => error,
+        }
+    }
+
+    /// Calls a closure, the callee sits at `slot` with the arguments above it
+    fn call(&mut self, method: Rc<Closure>, slot: usize) -> Result<(), String> {
+        let arg_count = self.stack.len() - slot - 1;
+        if method.function.arity != arg_count {
+            return Err(format!(
+                "Expected {} arguments but got {}.",
+                method.function.arity, arg_count
+            ));
+        }
+
+        if self.frames.len() >= self.limits.max_frames {
+            return Err("Stack overflow.".to_string());
         }
 
         self.frames.push(CallFrame::new(method, slot));
+        if let (Some(tracer), Some(frame)) = (self.tracer.as_mut(), self.frames.last()) {
+            tracer.call(frame);
+        }
+        Ok(())
     }
 
-    fn call_method(&mut self, method: Rc<Closure>, slot: usize, receiver: Value) {
+    fn call_method(
+        &mut self,
+        method: Rc<Closure>,
+        slot: usize,
+        receiver: Value,
+    ) -> Result<(), String> {
         self.stack[slot] = receiver;
         self.call(method, slot)
     }
 
-    fn call_value(&mut self, arg_count: usize, _ip: usize) -> Result<(), String> {
+    fn call_value(&mut self, arg_count: usize) -> Result<()> {
         let index = self.stack.len() - arg_count - 1;
         let callee = &self.stack[index];
 
         match callee {
             Value::Obj(object::Obj::BoundMethod(bound)) => {
-                if bound.method.function.arity != arg_count {
-                    return Err(format!(
-                        "Expected {} arguments but got {}.",
-                        bound.method.function.arity, arg_count
-                    ));
-                }
                 let this = Value::Obj(Obj::Instance(bound.receiver.clone()));
                 let method = bound.method.clone();
 
-                self.call_method(method, index, this);
-                Ok(())
+                Ok(self.call_method(method, index, this)?)
             }
             Value::Obj(object::Obj::Class(class)) => {
                 let class = class.clone();
-                let instance = Instance::new(class.clone());
-                self.stack[index] = Value::Obj(Obj::Instance(instance));
+                let instance = Value::Obj(Obj::Instance(Instance::new(class.clone())));
+                self.track_allocation(&instance);
+                self.stack[index] = instance;
 
                 if let Some(init) = class.borrow().methods.get("init") {
-                    self.call(init.clone(), index);
+                    self.call(init.clone(), index)?;
+                } else if arg_count != 0 {
+                    return Err(format!("Expected 0 arguments but got {}.", arg_count).into());
                 }
 
                 Ok(())
             }
-            Value::Obj(object::Obj::Closure(closure)) => {
-                if closure.function.arity != arg_count {
-                    return Err(format!(
-                        "Expected {} arguments but got {}.",
-                        closure.function.arity, arg_count
-                    ));
+            Value::Obj(object::Obj::Closure(closure)) => Ok(self.call(closure.clone(), index)?),
+            Value::Obj(object::Obj::NativeFun(func)) => {
+                let result = func.call(&self.stack[index + 1..])?;
+                self.track_allocation(&result);
+                self.stack.truncate(index);
+                self.stack.push(result);
+                Ok(())
+            }
+            _ => Err(Error::Native("Call Failed".to_string())),
+        }
+    }
+
+    /// Calls the property `name` of the value below the arguments
+    fn invoke(&mut self, name: &str, arg_count: usize) -> Result<()> {
+        let index = self.stack.len() - arg_count - 1;
+
+        match &self.stack[index].clone() {
+            Value::Obj(Obj::Instance(instance)) => {
+                if let Some(field) = instance.borrow().fields.get(name) {
+                    self.stack[index] = field.clone();
+                    return self.call_value(arg_count);
                 }
 
-                self.call(closure.clone(), index);
+                let method = instance.borrow().class.borrow().methods.get(name).cloned();
+                match method {
+                    Some(method) => Ok(self.call(method, index)?),
+                    None => Err(format!("Undefined property {}.", name).into()),
+                }
+            }
+            Value::Obj(Obj::Module(module)) => match module.members.get(name) {
+                Some(member) => {
+                    self.stack[index] = member.clone();
+                    self.call_value(arg_count)
+                }
+                None => Err(format!("Undefined property {}.{}.", module.name, name).into()),
+            },
+            Value::String(receiver) => {
+                let result = rlox_std::string::invoke(receiver, name, &self.stack[index + 1..])?;
+                self.track_allocation(&result);
+                self.stack.truncate(index);
+                self.stack.push(result);
                 Ok(())
             }
-            Value::Obj(object::Obj::NativeFun(func)) => {
-                let result = func.call(&self.stack[index..])?;
+            Value::Obj(Obj::List(receiver)) => {
+                let len = receiver.borrow().len();
+                let result = rlox_std::list::invoke(receiver, name, &self.stack[index + 1..])?;
+                // values pushed are copied into the list
+                for value in receiver.borrow().iter().skip(len) {
+                    self.track_allocation(value);
+                }
+                self.track_allocation(&result);
                 self.stack.truncate(index);
                 self.stack.push(result);
                 Ok(())
             }
-            _ => Err("Call Failed".to_string()),
+            Value::Obj(Obj::Map(receiver)) => {
+                let result = rlox_std::map::invoke(receiver, name, &self.stack[index + 1..])?;
+                self.track_allocation(&result);
+                self.stack.truncate(index);
+                self.stack.push(result);
+                Ok(())
+            }
+            value => Err(format!("A {} has no methods.", value.type_name()).into()),
         }
     }
 
@@ -567,7 +912,6 @@ impl Vm {
     ) -> Vec<Rc<RefCell<Value>>> {
         func.upvalues
             .iter()
-            .cloned()
             .map(|upvalue_descriptor| {
                 if upvalue_descriptor.is_local {
                     if let Some(open_upvalue) = self.open_upvalues.get(&upvalue_descriptor.index) {
@@ -598,6 +942,31 @@ impl Vm {
             .insert(name.into(), Value::Obj(Obj::NativeFun(Rc::new(function))));
         self
     }
+
+    pub fn define_module(&mut self, module: Module) -> &mut Self {
+        self.globals.insert(
+            module.name.clone(),
+            Value::Obj(Obj::Module(Rc::new(module))),
+        );
+        self
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    pub fn set_global(&mut self, name: impl Into<String>, value: Value) -> &mut Self {
+        self.globals.insert(name.into(), value);
+        self
+    }
+
+    pub fn remove_global(&mut self, name: &str) -> Option<Value> {
+        self.globals.remove(name)
+    }
+
+    pub fn globals_iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.globals.iter()
+    }
 }
 
 impl Default for Vm {
@@ -621,4 +990,18 @@ impl CallFrame {
             slot,
         }
     }
+
+    pub fn function(&self) -> &FunDescriptor {
+        &self.closure.function
+    }
+
+    /// Next instruction of the frame, for the innermost frame the tracer is given the current one
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Stack index of the callee, the locals follow it
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
 }
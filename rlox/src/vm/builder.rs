@@ -0,0 +1,158 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use super::{debug::Debugger, limits::Limits, trace::Tracer, Vm};
+use crate::{compiler::CompilerOptions, rlox_std};
+
+/// What a script is allowed to touch outside of the vm,
+/// natives that need a missing capability error when they are called
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    /// Directories scripts can access, relative paths resolve against the first one
+    pub fs_roots: Vec<PathBuf>,
+    pub env: bool,
+    pub clock: bool,
+    pub random: bool,
+    pub output: bool,
+    /// Reading what the vm was given as input, stdin by default
+    pub input: bool,
+}
+
+impl Capabilities {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn all() -> Self {
+        Self {
+            fs_roots: vec![PathBuf::from(".")],
+            env: true,
+            clock: true,
+            random: true,
+            output: true,
+            input: true,
+        }
+    }
+}
+
+/// Collects what a script prints, give a clone to [`VmBuilder::output`]
+#[derive(Clone, Debug, Default)]
+pub struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Captured {
+    /// What was printed since the last call, as text
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct VmBuilder {
+    capabilities: Capabilities,
+    limits: Limits,
+    yield_interval: Option<u64>,
+    args: Vec<String>,
+    env: Option<HashMap<String, String>>,
+    input: Option<Box<dyn BufRead>>,
+    output: Option<Box<dyn Write>>,
+    tracer: Option<Box<dyn Tracer>>,
+    debugger: Option<Debugger>,
+    compiler_options: CompilerOptions,
+}
+
+impl VmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn yield_interval(mut self, instructions: u64) -> Self {
+        self.yield_interval = Some(instructions);
+        self
+    }
+
+    /// Arguments for the script, returned by the `args()` native
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Variables the `env()` native sees instead of the process environment,
+    /// it still needs the `env` capability
+    pub fn env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env = Some(vars.into_iter().collect());
+        self
+    }
+
+    /// Where `readLine`, `readAll` and `input` read from, stdin by default
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Where `print` and `input` write, stdout by default
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    pub fn tracer(mut self, tracer: impl Tracer + 'static) -> Self {
+        self.tracer = Some(Box::new(tracer));
+        self
+    }
+
+    /// Pause at the debugger's breakpoints, see [`super::RunState::Paused`]
+    pub fn debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    pub fn compiler_options(mut self, options: CompilerOptions) -> Self {
+        self.compiler_options = options;
+        self
+    }
+
+    pub fn build(self) -> Vm {
+        let mut vm = Vm::new();
+        vm.set_limits(self.limits)
+            .set_yield_interval(self.yield_interval)
+            .set_tracer(self.tracer)
+            .set_debugger(self.debugger)
+            .set_compiler_options(self.compiler_options);
+        if let Some(output) = self.output {
+            vm.set_output(output);
+        }
+
+        let input = self
+            .input
+            .unwrap_or_else(|| Box::new(BufReader::new(io::stdin())));
+        rlox_std::register(&mut vm, &self.capabilities, self.args, self.env, input);
+        vm.capabilities = self.capabilities;
+        vm
+    }
+}
@@ -1,7 +1,11 @@
 use std::fmt::Write;
 
-use crate::vm::{opcode::OpCode, value::Value};
-use colored::Colorize;
+use crate::vm::{
+    object::{FunDescriptor, Obj},
+    opcode::OpCode,
+    value::Value,
+};
+use colored::{Color, Colorize};
 
 #[derive(Clone)]
 pub struct Chunk {
@@ -43,13 +47,36 @@ impl Chunk {
         self.constants[index].clone()
     }
 
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
     pub fn get_line(&self, index: usize) -> usize {
         self.lines[index]
     }
 
-    pub fn disassemble(&self, name: impl Into<String>) -> Result<String, std::fmt::Error> {
-        let out = String::new();
-        disassemble_chunk(out, self, name.into().as_str())
+    /// Disassembly of this chunk alone, `color` adds terminal colors
+    pub fn disassemble(
+        &self,
+        name: impl Into<String>,
+        color: bool,
+    ) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        disassemble_chunk(&mut out, self, name.into().as_str(), color)?;
+        Ok(out)
+    }
+
+    /// Takes the chunk apart into its instructions, constants and the line of every instruction
+    pub fn into_parts(self) -> (Vec<OpCode>, Vec<Value>, Vec<usize>) {
+        (self.code, self.constants, self.lines)
+    }
+
+    pub fn from_parts(code: Vec<OpCode>, constants: Vec<Value>, lines: Vec<usize>) -> Self {
+        Self {
+            code,
+            constants,
+            lines,
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -68,62 +95,145 @@ impl Default for Chunk {
 }
 
 pub fn disassemble_chunk(
-    mut out: String,
+    out: &mut String,
     chunk: &Chunk,
     name: &str,
-) -> Result<String, std::fmt::Error> {
+    color: bool,
+) -> Result<(), std::fmt::Error> {
     writeln!(out, "     >--< {}", name)?;
 
     let mut offset = 0;
     while offset < chunk.code.len() {
-        offset += disassemble_instruction(&mut out, chunk, offset)?;
+        disassemble_instruction(out, chunk, offset, color)?;
+        offset += 1;
+        // the columns are padded for the tracer which appends the stack
+        out.truncate(out.trim_end_matches(' ').len());
         writeln!(out)?;
     }
 
     writeln!(out, "     >--<")?;
 
-    Ok(out)
+    Ok(())
+}
+
+/// Disassembly of `function` followed by every function nested in its constants
+pub fn disassemble_function(
+    out: &mut String,
+    function: &FunDescriptor,
+    color: bool,
+) -> Result<(), std::fmt::Error> {
+    disassemble_chunk(out, &function.chunk, &function.to_string(), color)?;
+
+    for constant in function.chunk.constants.iter() {
+        if let Value::Obj(Obj::Fun(function)) = constant {
+            writeln!(out)?;
+            disassemble_function(out, function, color)?;
+        }
+    }
+    Ok(())
+}
+
+fn paint(text: &str, color: Option<Color>) -> String {
+    match color {
+        Some(color) => text.color(color).to_string(),
+        None => text.to_string(),
+    }
 }
 
+/// Writes the instruction at `offset` without a trailing newline. A `Closure` is
+/// followed by one line per captured upvalue
 pub fn disassemble_instruction(
     out: &mut String,
     chunk: &Chunk,
     offset: usize,
-) -> Result<usize, std::fmt::Error> {
+    color: bool,
+) -> Result<(), std::fmt::Error> {
+    let color = |c| color.then_some(c);
     let op = chunk.get_op(offset);
 
     if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
         write!(out, "{:<5}", "")?;
     } else {
-        write!(out, "{:<5}", chunk.lines[offset].to_string().blue())?;
+        write!(
+            out,
+            "{}",
+            paint(&format!("{:<5}", chunk.lines[offset]), color(Color::Blue))
+        )?;
     }
+    write!(
+        out,
+        "{} ",
+        paint(&format!("{:04}", offset), color(Color::BrightBlack))
+    )?;
 
-    write!(out, "{} ", format!("{:04}", offset).bright_black())?;
-
-    //TOO LAZY TO PROPERLY OUTPUT OPERANDS
+    let constant = |index: usize| {
+        let value = chunk.constants[index].to_string().replace('\n', "\\n");
+        format!("{:<4} '{}'", index, value)
+    };
     let operands = match op {
-        OpCode::GetProperty { prop_name } | OpCode::SetProperty { prop_name } => {
-            chunk.constants[prop_name].clone()
+        OpCode::Constant { constant: index }
+        | OpCode::GetGlobal { name: index }
+        | OpCode::DefineGlobal { name: index }
+        | OpCode::SetGlobal { name: index }
+        | OpCode::GetProperty { prop_name: index }
+        | OpCode::SetProperty { prop_name: index }
+        | OpCode::GetSuper { name: index }
+        | OpCode::Class { name: index }
+        | OpCode::Method { name: index }
+        | OpCode::Closure { func: index } => constant(index),
+        OpCode::GetLocal { local } | OpCode::SetLocal { local } => format!("slot {}", local),
+        OpCode::GetUpValue { upvalue } | OpCode::SetUpValue { upvalue } => {
+            format!("upvalue {}", upvalue)
+        }
+        OpCode::Jump { offset: jump } | OpCode::JumpIfFalse { offset: jump } => {
+            format!("{:<4} -> {:04}", jump, offset + jump)
         }
-        _ => {
-            write!(out, "{:<25}", op.to_string().blue())?;
-            return Ok(1);
+        OpCode::Loop { offset: jump } => format!("{:<4} -> {:04}", jump, offset - jump),
+        OpCode::Call { arg_count } => format!("({} args)", arg_count),
+        OpCode::Invoke { method, arg_count } | OpCode::SuperInvoke { method, arg_count } => {
+            format!("{} ({} args)", constant(method), arg_count)
         }
+        OpCode::BuildList { count } => format!("({} items)", count),
+        OpCode::BuildMap { count } => format!("({} entries)", count),
+        OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::Pop
+        | OpCode::Equal
+        | OpCode::Greater
+        | OpCode::Less
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::Print
+        | OpCode::CloseUpValue
+        | OpCode::Return
+        | OpCode::Inerhit
+        | OpCode::GetIndex
+        | OpCode::SetIndex => String::new(),
     };
 
-    let op = op.to_string();
-    let operands = operands.to_string();
-
     write!(
         out,
-        "{}[{}]",
-        op.blue(),
-        operands.replace('\n', "\\n").green()
+        "{}{}",
+        paint(&format!("{:<16}", op.to_string()), color(Color::Blue)),
+        paint(&format!("{:<24}", operands), color(Color::Green))
     )?;
 
-    // manual padding for color output
-    // + 2 for the additional []
-    write!(out, "{}", " ".repeat(25 - (op.len() + operands.len() + 2)))?;
+    if let OpCode::Closure { func } = op {
+        if let Value::Obj(Obj::Fun(function)) = &chunk.constants[func] {
+            if !function.upvalues.is_empty() {
+                out.truncate(out.trim_end_matches(' ').len());
+            }
+            for upvalue in function.upvalues.iter() {
+                let kind = if upvalue.is_local { "local" } else { "upvalue" };
+                write!(out, "\n{:<10}|{:<16}{} {}", "", "", kind, upvalue.index)?;
+            }
+        }
+    }
 
-    Ok(1)
+    Ok(())
 }
@@ -0,0 +1,215 @@
+use std::fmt;
+
+use super::{value::Value, CallFrame, Vm};
+
+/// Where a [`Debugger`] pauses execution
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The start of a source line
+    Line(usize),
+    /// The entry of every function with this name
+    Function(String),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::Function(name) => write!(f, "fun {}", name),
+        }
+    }
+}
+
+/// How far execution goes before pausing again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// To the next line, entering calls
+    Into,
+    /// To the next line of the current function or its callers
+    Over,
+    /// Until the current function returns
+    Out,
+}
+
+/// Why a [`Debugger`] paused the vm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pause {
+    Breakpoint,
+    /// A step finished, a breakpoint reached at the same time wins
+    Step,
+}
+
+/// Breakpoints and stepping, set with [`Vm::set_debugger`]; the vm is
+/// suspended with [`super::RunState::Paused`] whenever the debugger stops it
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    step: Option<(Step, usize)>,
+    /// Line and instruction last executed by every frame, to tell when a new line starts
+    positions: Vec<(usize, usize)>,
+    depth: usize,
+    pause: Option<Pause>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> &mut Self {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+        self
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    pub fn clear_breakpoints(&mut self) -> &mut Self {
+        self.breakpoints.clear();
+        self
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Pause again after `step` once the vm is resumed
+    pub fn step(&mut self, step: Step) -> &mut Self {
+        self.step = Some((step, self.depth));
+        self.pause = None;
+        self
+    }
+
+    /// Run until the next breakpoint once the vm is resumed
+    pub fn run(&mut self) -> &mut Self {
+        self.step = None;
+        self.pause = None;
+        self
+    }
+
+    /// Why the vm paused last, none before it paused
+    pub fn pause(&self) -> Option<Pause> {
+        self.pause
+    }
+
+    /// Forget where the previous execution was
+    pub fn reset(&mut self) {
+        self.positions.clear();
+        self.depth = 0;
+    }
+
+    /// Whether the vm pauses before `ip` of `frame`, which is `depth` frames deep
+    pub fn should_pause(&mut self, depth: usize, frame: &CallFrame, line: usize) -> bool {
+        self.positions.truncate(depth);
+        let entered = match self.positions.get(depth - 1) {
+            // jumping back into the same line, like a loop does, enters it again
+            Some(&(last_line, last_ip)) => last_line != line || frame.ip < last_ip,
+            None => true,
+        };
+        self.positions.resize(depth, (line, frame.ip));
+        self.positions[depth - 1] = (line, frame.ip);
+
+        let step = match self.step {
+            Some((Step::Into, _)) => entered,
+            Some((Step::Over, from)) => entered && depth <= from,
+            Some((Step::Out, from)) => depth < from,
+            None => false,
+        };
+        let breakpoint = entered
+            && self.breakpoints.iter().any(|breakpoint| match breakpoint {
+                Breakpoint::Line(at) => *at == line,
+                Breakpoint::Function(name) => frame.ip == 0 && frame.function().name == *name,
+            });
+
+        if step || breakpoint {
+            self.step = None;
+            self.depth = depth;
+            self.pause = Some(if breakpoint {
+                Pause::Breakpoint
+            } else {
+                Pause::Step
+            });
+        }
+        step || breakpoint
+    }
+}
+
+/// A frame of a paused vm
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInfo {
+    /// Empty for the script itself
+    pub function: String,
+    pub line: usize,
+}
+
+impl Vm {
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>) -> &mut Self {
+        self.debugger = debugger;
+        self
+    }
+
+    pub fn debugger(&self) -> Option<&Debugger> {
+        self.debugger.as_ref()
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    /// Frames of a suspended execution, the innermost last
+    pub fn call_stack(&self) -> Vec<FrameInfo> {
+        (0..self.frames.len())
+            .filter_map(|index| {
+                let (frame, ip) = self.frame_at(index)?;
+                Some(FrameInfo {
+                    function: frame.function().name.clone(),
+                    line: frame.function().chunk.get_line(ip),
+                })
+            })
+            .collect()
+    }
+
+    /// Locals in scope of a frame from [`Vm::call_stack`], innermost scope last
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let Some((frame, ip)) = self.frame_at(frame) else {
+            return Vec::new();
+        };
+        frame
+            .function()
+            .locals
+            .iter()
+            .filter(|local| local.is_alive(ip))
+            .filter_map(|local| {
+                let value = self.stack.get(frame.slot + local.slot)?;
+                Some((local.name.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// Variables captured by the closure of a frame from [`Vm::call_stack`]
+    pub fn upvalues(&self, frame: usize) -> Vec<(String, Value)> {
+        let Some(frame) = self.frames.get(frame) else {
+            return Vec::new();
+        };
+        frame
+            .function()
+            .upvalues
+            .iter()
+            .zip(frame.closure.upvalues.iter())
+            .map(|(upvalue, value)| (upvalue.name.clone(), value.borrow().clone()))
+            .collect()
+    }
+
+    /// The frame with the instruction it's executing, the callers are at their call
+    fn frame_at(&self, index: usize) -> Option<(&CallFrame, usize)> {
+        let frame = self.frames.get(index)?;
+        if index + 1 == self.frames.len() {
+            Some((frame, frame.ip))
+        } else {
+            Some((frame, frame.ip.saturating_sub(1)))
+        }
+    }
+}
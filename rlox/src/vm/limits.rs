@@ -0,0 +1,216 @@
+use std::{cell::RefCell, collections::HashSet, mem::size_of, rc::Rc};
+
+use super::{
+    object::{Class, Closure, FunDescriptor, Instance, Obj},
+    opcode::OpCode,
+    value::Value,
+};
+
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximum depth of nested calls, the script itself counts as one frame.
+    pub max_frames: usize,
+    /// Amount of instructions a single execution may run, unlimited if `None`.
+    pub fuel: Option<u64>,
+    /// Approximate ceiling for the live heap in bytes, unlimited if `None`.
+    pub max_heap: Option<usize>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_frames: usize::MAX,
+            fuel: None,
+            max_heap: None,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frames: 64,
+            fuel: None,
+            max_heap: None,
+        }
+    }
+}
+
+/// Shallow size of a freshly allocated value, used to decide when the
+/// live heap has to be measured again.
+pub fn allocation_size(value: &Value) -> usize {
+    size_of::<Value>()
+        + match value {
+            Value::String(s) => s.capacity(),
+            Value::Obj(Obj::Instance(instance)) => {
+                size_of::<Instance>() + instance.borrow().fields.capacity() * size_of::<Value>()
+            }
+            Value::Obj(Obj::Class(_)) => size_of::<Class>(),
+            Value::Obj(Obj::List(list)) => list.borrow().capacity() * size_of::<Value>(),
+            Value::Obj(Obj::Map(map)) => map.borrow().capacity() * size_of::<(String, Value)>(),
+            Value::Obj(Obj::Closure(closure)) => {
+                size_of::<Closure>() + closure.upvalues.len() * size_of::<Rc<RefCell<Value>>>()
+            }
+            _ => 0,
+        }
+}
+
+/// Something reachable that still has to be measured
+enum Pending {
+    Obj(Obj),
+    Upvalue(Rc<RefCell<Value>>),
+}
+
+/// Walks everything reachable from the given roots and estimates its size.
+/// Shared objects are only counted once, and the walk keeps a worklist
+/// instead of recursing so deeply nested values can't overflow the stack.
+#[derive(Default)]
+pub struct HeapMeter {
+    visited: HashSet<usize>,
+    pending: Vec<Pending>,
+    size: usize,
+}
+
+impl HeapMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn value(&mut self, value: &Value) {
+        self.push(value);
+        self.measure();
+    }
+
+    pub fn obj(&mut self, obj: &Obj) {
+        self.pending.push(Pending::Obj(obj.clone()));
+        self.measure();
+    }
+
+    pub fn upvalue(&mut self, upvalue: &Rc<RefCell<Value>>) {
+        self.pending.push(Pending::Upvalue(upvalue.clone()));
+        self.measure();
+    }
+
+    pub fn closure(&mut self, closure: &Rc<Closure>) {
+        self.obj(&Obj::Closure(closure.clone()));
+    }
+
+    fn first_visit<T: ?Sized>(&mut self, rc: &Rc<T>) -> bool {
+        self.visited.insert(Rc::as_ptr(rc) as *const () as usize)
+    }
+
+    /// Counts the value itself, what it refers to is measured later
+    fn push(&mut self, value: &Value) {
+        self.size += size_of::<Value>();
+
+        match value {
+            Value::String(s) => self.size += s.capacity(),
+            Value::Obj(obj) => self.pending.push(Pending::Obj(obj.clone())),
+            _ => {}
+        }
+    }
+
+    fn measure(&mut self) {
+        while let Some(pending) = self.pending.pop() {
+            match pending {
+                Pending::Obj(obj) => self.visit(&obj),
+                Pending::Upvalue(upvalue) => {
+                    if self.first_visit(&upvalue) {
+                        self.push(&upvalue.borrow());
+                    }
+                }
+            }
+        }
+    }
+
+    fn visit(&mut self, obj: &Obj) {
+        match obj {
+            Obj::Fun(function) => {
+                if self.first_visit(function) {
+                    self.size +=
+                        size_of::<FunDescriptor>() + function.chunk.len() * size_of::<OpCode>();
+                    for constant in function.chunk.constants() {
+                        self.push(constant);
+                    }
+                }
+            }
+            Obj::Closure(closure) => {
+                if self.first_visit(closure) {
+                    self.size += size_of::<Closure>();
+                    self.pending
+                        .push(Pending::Obj(Obj::Fun(closure.function.clone())));
+                    for upvalue in closure.upvalues.iter() {
+                        self.pending.push(Pending::Upvalue(upvalue.clone()));
+                    }
+                }
+            }
+            Obj::NativeFun(native) => {
+                if self.first_visit(native) {
+                    self.size += size_of::<usize>() * 2;
+                }
+            }
+            Obj::Class(class) => {
+                if self.first_visit(class) {
+                    let class = class.borrow();
+                    self.size += size_of::<Class>() + class.name.capacity();
+                    for (name, method) in class.methods.iter() {
+                        self.size += name.capacity();
+                        self.pending
+                            .push(Pending::Obj(Obj::Closure(method.clone())));
+                    }
+                    if let Some(superclass) = &class.superclass {
+                        self.pending
+                            .push(Pending::Obj(Obj::Class(superclass.clone())));
+                    }
+                }
+            }
+            Obj::Instance(instance) => {
+                if self.first_visit(instance) {
+                    let instance = instance.borrow();
+                    self.size += size_of::<Instance>();
+                    self.pending
+                        .push(Pending::Obj(Obj::Class(instance.class.clone())));
+                    for (name, value) in instance.fields.iter() {
+                        self.size += name.capacity();
+                        self.push(value);
+                    }
+                }
+            }
+            Obj::BoundMethod(bound) => {
+                if self.first_visit(bound) {
+                    self.pending
+                        .push(Pending::Obj(Obj::Instance(bound.receiver.clone())));
+                    self.pending
+                        .push(Pending::Obj(Obj::Closure(bound.method.clone())));
+                }
+            }
+            Obj::List(list) => {
+                if self.first_visit(list) {
+                    for value in list.borrow().iter() {
+                        self.push(value);
+                    }
+                }
+            }
+            Obj::Map(map) => {
+                if self.first_visit(map) {
+                    for (key, value) in map.borrow().iter() {
+                        self.size += size_of::<String>() + key.capacity();
+                        self.push(value);
+                    }
+                }
+            }
+            Obj::Module(module) => {
+                if self.first_visit(module) {
+                    for (name, value) in module.members.iter() {
+                        self.size += name.capacity();
+                        self.push(value);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -7,6 +7,8 @@ use std::{
     string::String,
 };
 
+use indexmap::IndexMap;
+
 use super::{chunk::Chunk, value::Value};
 use crate::error::*;
 
@@ -18,6 +20,58 @@ pub enum Obj {
     Class(Rc<RefCell<Class>>),
     Instance(Rc<RefCell<Instance>>),
     BoundMethod(Rc<BoundMethod>),
+    Module(Rc<Module>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<IndexMap<String, Value>>>),
+}
+
+/// Lists and maps nested deeper than this are printed as `[...]` and `{...}`
+const MAX_RENDER_DEPTH: usize = 512;
+
+impl Obj {
+    pub fn list(items: Vec<Value>) -> Self {
+        Obj::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(entries: IndexMap<String, Value>) -> Self {
+        Obj::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Moves the values out of a list, map or instance nothing else holds
+    fn take_values(&mut self, values: &mut Vec<Value>) {
+        match self {
+            Obj::List(list) if Rc::strong_count(list) == 1 => {
+                if let Ok(mut items) = list.try_borrow_mut() {
+                    values.append(&mut items);
+                }
+            }
+            Obj::Map(map) if Rc::strong_count(map) == 1 => {
+                if let Ok(mut entries) = map.try_borrow_mut() {
+                    values.extend(entries.drain(..).map(|(_, value)| value));
+                }
+            }
+            Obj::Instance(instance) if Rc::strong_count(instance) == 1 => {
+                if let Ok(mut instance) = instance.try_borrow_mut() {
+                    values.extend(instance.fields.drain().map(|(_, value)| value));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Deeply nested lists, maps and instances would overflow the stack if dropped
+/// recursively, the values they held alone are dropped from a worklist instead
+impl Drop for Obj {
+    fn drop(&mut self) {
+        let mut values = Vec::new();
+        self.take_values(&mut values);
+        while let Some(value) = values.pop() {
+            if let Value::Obj(mut obj) = value {
+                obj.take_values(&mut values);
+            }
+        }
+    }
 }
 
 impl Debug for Obj {
@@ -35,11 +89,50 @@ impl Display for Obj {
             Obj::Class(v) => v.borrow().to_string(),
             Obj::Instance(v) => v.borrow().to_string(),
             Obj::BoundMethod(v) => v.to_string(),
+            Obj::Module(v) => v.to_string(),
+            Obj::List(v) => render_once(Rc::as_ptr(v) as *const (), "[...]", || {
+                let items = v.borrow();
+                let items = items.iter().map(Value::to_string).collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }),
+            Obj::Map(v) => render_once(Rc::as_ptr(v) as *const (), "{...}", || {
+                let entries = v.borrow();
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", entries.join(", "))
+            }),
         };
         write!(f, "{}", s)
     }
 }
 
+thread_local! {
+    /// Lists and maps being rendered, meeting one of them again is a cycle
+    static RENDERING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Output of `render` for the container at `ptr`, `cycle` if it contains itself
+/// or is nested too deep
+fn render_once(ptr: *const (), cycle: &str, render: impl FnOnce() -> String) -> String {
+    let repeat = RENDERING.with(|rendering| {
+        let mut rendering = rendering.borrow_mut();
+        let repeat = rendering.len() == MAX_RENDER_DEPTH || rendering.contains(&ptr);
+        if !repeat {
+            rendering.push(ptr);
+        }
+        repeat
+    });
+    if repeat {
+        return cycle.to_string();
+    }
+
+    let s = render();
+    RENDERING.with(|rendering| rendering.borrow_mut().pop());
+    s
+}
+
 impl PartialEq for Obj {
     fn eq(&self, _other: &Self) -> bool {
         false
@@ -60,6 +153,8 @@ pub struct FunDescriptor {
     pub arity: usize,
     pub chunk: Chunk,
     pub upvalues: Vec<UpValueDescriptor>,
+    /// Names of the locals for debuggers
+    pub locals: Vec<LocalInfo>,
 }
 
 impl FunDescriptor {
@@ -69,6 +164,7 @@ impl FunDescriptor {
             arity: 0,
             chunk: Chunk::new(),
             upvalues: Vec::new(),
+            locals: Vec::new(),
         }
     }
 }
@@ -90,6 +186,26 @@ pub type NativeFunction = &'static dyn Fn(&[Value]) -> Value;
 pub struct UpValueDescriptor {
     pub index: usize,
     pub is_local: bool,
+    /// Name of the captured variable, for debuggers
+    pub name: String,
+}
+
+/// Debug info of a local variable, its stack slot and the instructions where it's alive
+#[derive(Clone, Debug)]
+pub struct LocalInfo {
+    pub name: String,
+    /// Slot relative to the start of the call frame
+    pub slot: usize,
+    /// First instruction after the local is initialized
+    pub start: usize,
+    /// Instruction that pops it, `usize::MAX` for locals alive until the function returns
+    pub end: usize,
+}
+
+impl LocalInfo {
+    pub fn is_alive(&self, ip: usize) -> bool {
+        (self.start..self.end).contains(&ip)
+    }
 }
 
 #[derive(Clone)]
@@ -121,7 +237,7 @@ impl Native {
     }
 }
 pub trait NativeFun {
-    fn call(&self, args: &[Value]) -> Result<Value, String>;
+    fn call(&self, args: &[Value]) -> crate::error::Result<Value>;
 }
 
 impl Display for Box<dyn NativeFun> {
@@ -134,6 +250,7 @@ impl Display for Box<dyn NativeFun> {
 pub struct Class {
     pub name: String,
     pub methods: HashMap<String, Rc<Closure>>,
+    pub superclass: Option<Rc<RefCell<Class>>>,
 }
 
 impl Class {
@@ -141,8 +258,21 @@ impl Class {
         Rc::new(RefCell::new(Self {
             name,
             methods: HashMap::new(),
+            superclass: None,
         }))
     }
+
+    /// Whether `class` is this class or one of its superclasses
+    pub fn inherits(this: &Rc<RefCell<Class>>, class: &Rc<RefCell<Class>>) -> bool {
+        let mut current = Some(this.clone());
+        while let Some(c) = current {
+            if Rc::ptr_eq(&c, class) {
+                return true;
+            }
+            current = c.borrow().superclass.clone();
+        }
+        false
+    }
 }
 
 impl Display for Class {
@@ -189,3 +319,34 @@ impl Display for BoundMethod {
         write!(f, "<bound method {}>", self.method.function)
     }
 }
+
+/// Namespace of natives and constants like `math`
+#[derive(Clone)]
+pub struct Module {
+    pub name: String,
+    pub members: HashMap<String, Value>,
+}
+
+impl Module {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            members: HashMap::new(),
+        }
+    }
+
+    pub fn constant(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.members.insert(name.into(), value);
+        self
+    }
+
+    pub fn native(self, name: impl Into<String>, function: Box<dyn NativeFun>) -> Self {
+        self.constant(name, Value::Obj(Obj::NativeFun(Rc::new(function))))
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
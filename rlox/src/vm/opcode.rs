@@ -39,17 +39,16 @@ pub enum OpCode {
     Class { name: usize },
     Inerhit,
     Method { name: usize },
+    BuildList { count: usize },
+    BuildMap { count: usize },
+    GetIndex,
+    SetIndex,
 }
 
+/// Just the name of the instruction, the disassembler renders the operands
 impl Display for OpCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            format!("{:?}", self)
-                .chars()
-                .filter(|c| c.is_alphabetic())
-                .collect::<String>()
-        )
+        let debug = format!("{:?}", self);
+        f.write_str(debug.split([' ', '{']).next().unwrap_or_default())
     }
 }
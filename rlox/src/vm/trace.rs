@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+
+use colored::Colorize;
+
+use super::{chunk::disassemble_instruction, opcode::OpCode, value::Value, CallFrame};
+
+/// Receives execution events from the vm, set with [`super::Vm::set_tracer`]
+pub trait Tracer {
+    /// Called before the instruction `op` at `ip` of the innermost `frame` executes
+    fn instruction(&mut self, frame: &CallFrame, ip: usize, op: &OpCode, stack: &[Value]);
+
+    /// A new `frame` was pushed
+    fn call(&mut self, _frame: &CallFrame) {}
+
+    /// Execution returned into `frame`
+    fn ret(&mut self, _frame: &CallFrame) {}
+}
+
+/// Writes every instruction with the top of the stack, what `--trace` shows
+pub struct PrintTracer {
+    out: Box<dyn Write>,
+    color: bool,
+}
+
+impl PrintTracer {
+    /// Writes the trace to `out`, `color` adds terminal colors
+    pub fn new(out: impl Write + 'static, color: bool) -> Self {
+        Self {
+            out: Box::new(out),
+            color,
+        }
+    }
+}
+
+impl Default for PrintTracer {
+    fn default() -> Self {
+        Self::new(io::stdout(), false)
+    }
+}
+
+/// Stack slots shown next to each instruction
+const STACK_PREVIEW: usize = 5;
+
+impl Tracer for PrintTracer {
+    fn instruction(&mut self, frame: &CallFrame, ip: usize, _op: &OpCode, stack: &[Value]) {
+        let mut out = String::new();
+        if disassemble_instruction(&mut out, &frame.function().chunk, ip, self.color).is_err() {
+            out = format!("{:04} <could not disassemble>\n", ip);
+        }
+
+        out.push_str(">> ");
+        if stack.len() > STACK_PREVIEW {
+            out.push_str(" ... ");
+        }
+        for value in stack[stack.len().saturating_sub(STACK_PREVIEW)..].iter() {
+            let value = value.to_string().replace('\n', "\\n");
+            if self.color {
+                out.push_str(&format!(" {}, ", value.green()));
+            } else {
+                out.push_str(&format!(" {}, ", value));
+            }
+        }
+        out.push('\n');
+        self.write(&out);
+    }
+
+    fn call(&mut self, frame: &CallFrame) {
+        self.event(format!("Called: {}()", frame.function()));
+    }
+
+    fn ret(&mut self, frame: &CallFrame) {
+        self.event(format!(
+            "Returned to: {}[{:04}]",
+            frame.function(),
+            frame.ip()
+        ));
+    }
+}
+
+impl PrintTracer {
+    fn event(&mut self, text: String) {
+        let text = format!("\n      {}\n", text);
+        if self.color {
+            self.write(&format!("{}\n", text.magenta()));
+        } else {
+            self.write(&format!("{}\n", text));
+        }
+    }
+
+    /// Tracing is best effort, a writer that fails doesn't stop the script
+    fn write(&mut self, text: &str) {
+        let _ = self.out.write_all(text.as_bytes());
+    }
+}
@@ -17,6 +17,25 @@ pub enum Value {
 }
 
 impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Obj(obj) => match obj {
+                Obj::Fun(_) | Obj::Closure(_) | Obj::BoundMethod(_) | Obj::NativeFun(_) => {
+                    "function"
+                }
+                Obj::Class(_) => "class",
+                Obj::Instance(_) => "instance",
+                Obj::Module(_) => "module",
+                Obj::List(_) => "list",
+                Obj::Map(_) => "map",
+            },
+        }
+    }
+
     pub fn is_falsey(&self) -> bool {
         match self {
             Value::Number(n) => n < &1.0,